@@ -1,3 +1,103 @@
-fn main() {
-    println!("Hello, world!");
+use std::{error::Error, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use reqwest::{blocking::Client, Method};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// Command line client of the market admin API.
+#[derive(Parser)]
+struct Cli {
+    /// Base URL of the market server.
+    #[arg(long, env = "MARKET_URL", default_value = "http://localhost:8000")]
+    url: String,
+    /// API key of the market server, `api_key` in its config.
+    #[arg(long, env = "MARKET_API_KEY")]
+    api_key: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Halt trading of all strategies or of one.
+    Halt {
+        #[arg(long)]
+        strategy: Option<Uuid>,
+        #[arg(long)]
+        reason: Option<String>,
+        /// Cancel open orders.
+        #[arg(long)]
+        cancel_orders: bool,
+        /// Close positions.
+        #[arg(long)]
+        flatten: bool,
+    },
+    /// Lift the global halt or the halt of a strategy.
+    Resume {
+        #[arg(long)]
+        strategy: Option<Uuid>,
+    },
+    /// List active halts.
+    Status,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(body) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&body).unwrap_or_default()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<Value, Box<dyn Error>> {
+    let (method, path, body) = match &cli.command {
+        Command::Halt {
+            strategy,
+            reason,
+            cancel_orders,
+            flatten,
+        } => (
+            Method::POST,
+            "/admin/halt",
+            Some(json!({
+                "strategy_id": strategy,
+                "reason": reason,
+                "cancel_orders": cancel_orders,
+                "flatten": flatten,
+            })),
+        ),
+        Command::Resume { strategy } => (
+            Method::POST,
+            "/admin/resume",
+            Some(json!({ "strategy_id": strategy })),
+        ),
+        Command::Status => (Method::GET, "/admin/halts", None),
+    };
+
+    let mut request = Client::new()
+        .request(method, format!("{}{}", cli.url.trim_end_matches('/'), path))
+        .header("Authorization", &cli.api_key);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    let response = request.send()?;
+    let status = response.status();
+    let body: Value = response.json().unwrap_or(Value::Null);
+    if !status.is_success() {
+        return Err(format!("{} {}", status, body).into());
+    }
+
+    Ok(body)
 }
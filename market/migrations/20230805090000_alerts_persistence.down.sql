@@ -0,0 +1,13 @@
+DROP INDEX idx_alerts_status;
+DROP INDEX idx_alerts_strategy_id;
+
+ALTER TABLE alerts
+	DROP COLUMN rejection_reason,
+	DROP COLUMN status,
+	DROP COLUMN trail_stop_price,
+	DROP COLUMN strategy_id,
+	ALTER COLUMN bar_open TYPE Decimal(16, 2),
+	ALTER COLUMN bar_high TYPE Decimal(16, 2),
+	ALTER COLUMN bar_low TYPE Decimal(16, 2),
+	ALTER COLUMN bar_close TYPE Decimal(16, 2),
+	ALTER COLUMN bar_volume TYPE Decimal(16, 2);
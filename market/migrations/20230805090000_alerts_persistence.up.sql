@@ -0,0 +1,14 @@
+-- Decimal(16, 2) truncates crypto prices and volumes
+ALTER TABLE alerts
+	ALTER COLUMN bar_open TYPE Numeric,
+	ALTER COLUMN bar_high TYPE Numeric,
+	ALTER COLUMN bar_low TYPE Numeric,
+	ALTER COLUMN bar_close TYPE Numeric,
+	ALTER COLUMN bar_volume TYPE Numeric,
+	ADD COLUMN strategy_id       Uuid,
+	ADD COLUMN trail_stop_price  Numeric,
+	ADD COLUMN status            Text NOT NULL DEFAULT 'accepted',
+	ADD COLUMN rejection_reason  Text;
+
+CREATE INDEX idx_alerts_strategy_id ON alerts (strategy_id);
+CREATE INDEX idx_alerts_status ON alerts (status);
@@ -0,0 +1,3 @@
+DROP TABLE fills;
+DROP TABLE order_transitions;
+DROP TABLE orders;
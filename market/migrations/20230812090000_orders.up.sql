@@ -0,0 +1,56 @@
+CREATE TABLE orders
+(
+	order_id            Uuid,
+	parent_order_id     Uuid REFERENCES orders (order_id),
+	alert_id            Uuid REFERENCES alerts (alert_id),
+	strategy_id         Uuid NOT NULL,
+	broker              Text NOT NULL,
+	broker_order_id     Text,
+	client_order_id     Text NOT NULL,
+	ticker              Text NOT NULL,
+	side                Text NOT NULL,
+	order_type          Text NOT NULL,
+	quantity            Numeric,
+	limit_price         Numeric,
+	stop_price          Numeric,
+	status              Text NOT NULL,
+	filled_quantity     Numeric NOT NULL DEFAULT 0,
+	average_fill_price  Numeric,
+	created_at          Timestamptz NOT NULL,
+	modified_at         Timestamptz NOT NULL,
+
+	PRIMARY KEY (order_id)
+);
+
+CREATE UNIQUE INDEX idx_orders_broker_order_id ON orders (broker, broker_order_id);
+CREATE INDEX idx_orders_strategy_ticker ON orders (strategy_id, ticker, created_at);
+CREATE INDEX idx_orders_status ON orders (status);
+
+CREATE TABLE order_transitions
+(
+	order_transition_id  Uuid,
+	order_id             Uuid NOT NULL REFERENCES orders (order_id),
+	event                Text NOT NULL,
+	from_status          Text,
+	to_status            Text NOT NULL,
+	details              Text,
+	created_at           Timestamptz NOT NULL,
+
+	PRIMARY KEY (order_transition_id)
+);
+
+CREATE INDEX idx_order_transitions_order_id ON order_transitions (order_id, created_at);
+
+CREATE TABLE fills
+(
+	fill_id      Uuid,
+	order_id     Uuid NOT NULL REFERENCES orders (order_id),
+	quantity     Numeric NOT NULL,
+	price        Numeric NOT NULL,
+	filled_at    Timestamptz NOT NULL,
+	created_at   Timestamptz NOT NULL,
+
+	PRIMARY KEY (fill_id)
+);
+
+CREATE INDEX idx_fills_order_id ON fills (order_id);
@@ -0,0 +1,5 @@
+DROP INDEX idx_alerts_accepted_dedup_key;
+
+ALTER TABLE alerts
+	DROP COLUMN dedup_key,
+	DROP COLUMN client_alert_id;
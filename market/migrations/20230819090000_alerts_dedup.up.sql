@@ -0,0 +1,6 @@
+ALTER TABLE alerts
+	ADD COLUMN client_alert_id  Text,
+	ADD COLUMN dedup_key        Text;
+
+-- Only one alert per key may be executed, duplicates are stored with `duplicate` status
+CREATE UNIQUE INDEX idx_alerts_accepted_dedup_key ON alerts (dedup_key) WHERE status = 'accepted';
@@ -0,0 +1 @@
+DROP TABLE emulated_brackets;
@@ -0,0 +1,18 @@
+-- Stop loss and take profit placed by `Core` after the entry fills, for brokers or assets
+-- without native bracket orders
+CREATE TABLE emulated_brackets
+(
+	entry_order_id        Uuid REFERENCES orders (order_id),
+	stop_loss_order_id    Uuid REFERENCES orders (order_id),
+	take_profit_order_id  Uuid REFERENCES orders (order_id),
+	currency_type         Text NOT NULL,
+	stop_price            Numeric NOT NULL,
+	take_profit_price     Numeric,
+	status                Text NOT NULL,
+	created_at            Timestamptz NOT NULL,
+	modified_at           Timestamptz NOT NULL,
+
+	PRIMARY KEY (entry_order_id)
+);
+
+CREATE INDEX idx_emulated_brackets_status ON emulated_brackets (status);
@@ -0,0 +1,4 @@
+DROP INDEX idx_orders_broker_order_id;
+CREATE UNIQUE INDEX idx_orders_broker_order_id ON orders (broker, broker_order_id);
+
+ALTER TABLE orders DROP COLUMN account;
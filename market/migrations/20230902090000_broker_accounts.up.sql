@@ -0,0 +1,5 @@
+-- Orders are placed on one of possibly several named accounts of the broker
+ALTER TABLE orders ADD COLUMN account Text NOT NULL DEFAULT 'default';
+
+DROP INDEX idx_orders_broker_order_id;
+CREATE UNIQUE INDEX idx_orders_broker_order_id ON orders (broker, account, broker_order_id);
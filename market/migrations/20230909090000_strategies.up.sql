@@ -0,0 +1,23 @@
+-- Strategies managed through the API, seeded from config at startup. Deleted strategies are
+-- kept for the orders and alerts referencing them
+CREATE TABLE strategies
+(
+	strategy_id           Uuid,
+	name                  Text NOT NULL,
+	enabled               Boolean NOT NULL,
+	broker                Text NOT NULL,
+	account               Text NOT NULL,
+	currency_type         Text NOT NULL,
+	position_sizing       Jsonb NOT NULL,
+	take_profit           Jsonb,
+	allow_stop_loosening  Boolean NOT NULL,
+	max_order_retries     Smallint NOT NULL,
+	order_retry_delay     Double Precision NOT NULL,
+	webhook_passphrase    Text NOT NULL,
+	webhook_hmac_secret   Text,
+	created_at            Timestamptz NOT NULL,
+	modified_at           Timestamptz NOT NULL,
+	deleted_at            Timestamptz,
+
+	PRIMARY KEY (strategy_id)
+);
@@ -0,0 +1,16 @@
+-- Kill switch. A halt without strategy stops all trading, lifted halts are kept for the record
+CREATE TABLE trading_halts
+(
+	halt_id      Uuid,
+	strategy_id  Uuid,
+	reason       Text,
+	created_at   Timestamptz NOT NULL,
+	lifted_at    Timestamptz,
+
+	PRIMARY KEY (halt_id)
+);
+
+-- Only one active halt per strategy and one global one
+CREATE UNIQUE INDEX idx_trading_halts_active
+	ON trading_halts ((COALESCE(strategy_id, '00000000-0000-0000-0000-000000000000')))
+	WHERE lifted_at IS NULL;
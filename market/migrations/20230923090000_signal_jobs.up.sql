@@ -0,0 +1,20 @@
+-- Outbox of accepted alerts waiting to be turned into orders. Jobs are inserted in the same
+-- transaction as their alert and claimed by workers with `FOR UPDATE SKIP LOCKED`
+CREATE TABLE signal_jobs
+(
+	job_id        Uuid,
+	alert_id      Uuid NOT NULL REFERENCES alerts (alert_id),
+	payload       Jsonb NOT NULL,
+	status        Text NOT NULL,
+	attempts      Integer NOT NULL DEFAULT 0,
+	max_attempts  Integer NOT NULL,
+	run_at        Timestamptz NOT NULL,
+	last_error    Text,
+	created_at    Timestamptz NOT NULL,
+	modified_at   Timestamptz NOT NULL,
+
+	PRIMARY KEY (job_id)
+);
+
+CREATE INDEX idx_signal_jobs_pending ON signal_jobs (run_at) WHERE status = 'pending';
+CREATE INDEX idx_signal_jobs_status ON signal_jobs (status, created_at);
@@ -0,0 +1,5 @@
+DROP INDEX idx_signal_jobs_lane;
+
+ALTER TABLE signal_jobs
+	DROP COLUMN lane,
+	DROP COLUMN bar_time;
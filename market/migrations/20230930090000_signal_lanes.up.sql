@@ -0,0 +1,9 @@
+-- Jobs of the same broker account and ticker are processed one at a time in bar time order
+ALTER TABLE signal_jobs
+	ADD COLUMN lane      Text NOT NULL DEFAULT '',
+	ADD COLUMN bar_time  Timestamptz;
+
+UPDATE signal_jobs SET bar_time = (payload -> 'bar_data' ->> 'time')::Timestamptz;
+ALTER TABLE signal_jobs ALTER COLUMN bar_time SET NOT NULL;
+
+CREATE INDEX idx_signal_jobs_lane ON signal_jobs (lane, bar_time, created_at) WHERE status = 'pending';
@@ -0,0 +1,2 @@
+-- Percentage of the position closed by partial_close alerts
+ALTER TABLE alerts ADD COLUMN close_percent Numeric;
@@ -0,0 +1,10 @@
+DROP INDEX idx_strategies_endpoint_slug;
+
+ALTER TABLE strategies
+	DROP COLUMN endpoint_slug,
+	DROP COLUMN endpoint_token,
+	DROP COLUMN endpoint_enabled,
+	DROP COLUMN endpoint_rate_limit,
+	DROP COLUMN endpoint_allowed_tickers,
+	DROP COLUMN endpoint_allowed_timeframes,
+	DROP COLUMN endpoint_allowed_signal_types;
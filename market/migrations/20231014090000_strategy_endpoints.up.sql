@@ -0,0 +1,11 @@
+-- Dedicated webhook endpoints of strategies at /webhook/:slug
+ALTER TABLE strategies
+	ADD COLUMN endpoint_slug                  Text,
+	ADD COLUMN endpoint_token                 Text,
+	ADD COLUMN endpoint_enabled               Boolean NOT NULL DEFAULT true,
+	ADD COLUMN endpoint_rate_limit            Bigint,
+	ADD COLUMN endpoint_allowed_tickers       Text[] NOT NULL DEFAULT '{}',
+	ADD COLUMN endpoint_allowed_timeframes    Text[] NOT NULL DEFAULT '{}',
+	ADD COLUMN endpoint_allowed_signal_types  Text[] NOT NULL DEFAULT '{}';
+
+CREATE UNIQUE INDEX idx_strategies_endpoint_slug ON strategies (endpoint_slug) WHERE deleted_at IS NULL;
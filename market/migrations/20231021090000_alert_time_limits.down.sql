@@ -0,0 +1,3 @@
+ALTER TABLE strategies
+	DROP COLUMN max_signal_age,
+	DROP COLUMN max_future_skew;
@@ -0,0 +1,4 @@
+-- Alerts fired too long before they are received or ahead of the server clock are rejected
+ALTER TABLE strategies
+	ADD COLUMN max_signal_age   Double Precision NOT NULL DEFAULT 300,
+	ADD COLUMN max_future_skew  Double Precision NOT NULL DEFAULT 10;
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
 use strum_macros::{AsRefStr, EnumString};
 use uuid::Uuid;
 
@@ -10,11 +11,14 @@ use crate::{app_config::AppConfig, clients::BrokerClient, strategy::Strategy};
 // NOTE: Webhook body example:
 // {
 // 	"strategy_id": "C6557FC3-0D9A-447A-9D87-E417D98F2114",
+// 	"passphrase": "<strategy webhook passphrase>",
 // 	"time": "{{timenow}}",
 // 	"exchange": "{{exchange}}",
 // 	"ticker": "{{ticker}}",
-// 	"type": "unknown",
-// 	"bar": {
+// 	"timeframe": "{{interval}}",
+// 	"signal_type": "open_long",
+// 	"trail_stop_price": "{{plot_0}}",
+// 	"bar_data": {
 // 		"time": "{{time}}",
 // 		"open": "{{open}}",
 // 		"high": "{{high}}",
@@ -23,33 +27,243 @@ use crate::{app_config::AppConfig, clients::BrokerClient, strategy::Strategy};
 // 		"volume": "{{volume}}"
 // 	}
 // }
+//
+// Exit signals `close_long`, `close_short` and `close_all` need no `trail_stop_price`, `reverse`
+// needs it for the stop of the opposite position and `partial_close` takes `"close_percent": "50"`
+// instead.
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct WebhookAlertData {
     pub strategy_id: Uuid,
+    /// Strategy's `webhook_passphrase`, checked by `middleware::auth`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// Optional id assigned by the signal source. When present, alerts are deduplicated by it
+    /// instead of by their content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_alert_id: Option<String>,
     pub ticker: String,
     pub timeframe: String,
     pub exchange: String,
+    #[serde(flatten)]
     pub signal_type: SignalType,
-    pub trail_stop_price: Option<Decimal>,
     pub bar_data: BarData,
     pub time: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, AsRefStr)]
+impl WebhookAlertData {
+    /// Key identifying retried or double fired alerts: the client supplied alert id if any,
+    /// otherwise strategy, ticker, signal type and bar time.
+    pub fn dedup_key(&self) -> String {
+        match &self.client_alert_id {
+            Some(client_alert_id) => format!("{}/{}", self.strategy_id, client_alert_id),
+            None => format!(
+                "{}/{}/{}/{}",
+                self.strategy_id,
+                self.ticker,
+                self.signal_type.as_ref(),
+                self.bar_data.time.to_rfc3339()
+            ),
+        }
+    }
+
+    /// Stores alert in `alerts` table. Rejected alerts are stored along with the reason of
+    /// rejection. Returns `false` if the alert wasn't stored as accepted because an alert with
+    /// the same `dedup_key` was already accepted.
+    pub async fn save<'c, E>(
+        &self,
+        db: E,
+        alert_id: Uuid,
+        status: AlertStatus,
+        rejection_reason: Option<&str>,
+    ) -> Result<bool, sqlx::Error>
+    where
+        E: PgExecutor<'c>,
+    {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO alerts (
+                alert_id,
+                strategy_id,
+                ticker,
+                timeframe,
+                exchange,
+                alert_type,
+                trail_stop_price,
+                close_percent,
+                bar_time,
+                bar_open,
+                bar_high,
+                bar_low,
+                bar_close,
+                bar_volume,
+                alert_fire_time,
+                status,
+                rejection_reason,
+                client_alert_id,
+                dedup_key,
+                created_at,
+                modified_at
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+                $19, NOW(), NOW()
+            )
+            ON CONFLICT (dedup_key) WHERE status = 'accepted' DO NOTHING
+            "#,
+        )
+        .bind(alert_id)
+        .bind(self.strategy_id)
+        .bind(&self.ticker)
+        .bind(&self.timeframe)
+        .bind(&self.exchange)
+        .bind(self.signal_type.as_ref())
+        .bind(self.signal_type.trail_stop_price())
+        .bind(self.signal_type.close_percent())
+        .bind(self.bar_data.time)
+        .bind(self.bar_data.open.as_ref())
+        .bind(self.bar_data.high.as_ref())
+        .bind(self.bar_data.low.as_ref())
+        .bind(self.bar_data.close.as_ref())
+        .bind(self.bar_data.volume)
+        .bind(self.time)
+        .bind(status.as_ref())
+        .bind(rejection_reason)
+        .bind(&self.client_alert_id)
+        .bind(self.dedup_key())
+        .execute(db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl BarData {
+    /// Latest `limit` distinct bars of the ticker and timeframe received with the strategy's
+    /// accepted alerts up to `until`, ordered from the oldest to the most recent one. Rejected
+    /// alerts are left out, as anyone can send them. Of several alerts for a bar the latest one
+    /// counts.
+    pub async fn recent(
+        db: &PgPool,
+        strategy_id: Uuid,
+        ticker: &str,
+        timeframe: &str,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let mut bars: Vec<Self> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT ON (bar_time)
+                bar_time AS time,
+                bar_open AS open,
+                bar_high AS high,
+                bar_low AS low,
+                bar_close AS close,
+                bar_volume AS volume
+            FROM alerts
+            WHERE strategy_id = $1
+                AND status = $2
+                AND ticker = $3
+                AND timeframe = $4
+                AND bar_time <= $5
+            ORDER BY bar_time DESC, created_at DESC
+            LIMIT $6
+            "#,
+        )
+        .bind(strategy_id)
+        .bind(AlertStatus::Accepted.as_ref())
+        .bind(ticker)
+        .bind(timeframe)
+        .bind(until)
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(db)
+        .await?;
+
+        bars.reverse();
+        Ok(bars)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum AlertStatus {
+    Accepted,
+    Rejected,
+    Duplicate,
+}
+
+#[derive(Debug, Clone, AsRefStr)]
 #[strum(serialize_all = "snake_case")]
-#[serde(rename_all = "snake_case")]
 /// Signal type to receive from TradingView.
 /// Take profits are being calculated on the server side.
 pub enum SignalType {
     OpenLong(TrailStopPrice),
     OpenShort(TrailStopPrice),
     StopLossUpdate(TrailStopPrice),
+    /// Closes the long position in the ticker, rejected if the position is short.
+    CloseLong,
+    /// Closes the short position in the ticker, rejected if the position is long.
+    CloseShort,
+    /// Closes the position in the ticker whatever its side.
+    CloseAll,
+    /// Closes the position in the ticker and opens one on the opposite side with the stop.
+    Reverse(TrailStopPrice),
+    /// Closes the percentage of the position in the ticker.
+    PartialClose(ClosePercent),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct TrailStopPrice(pub Decimal);
 
+/// Percentage of the position to close, greater than 0 and up to 100.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ClosePercent(pub Decimal);
+
+impl SignalType {
+    /// Side of the position the signal opens, `None` for signals managing an existing one.
+    /// Side of the position opened by `reverse` depends on the position it closes.
+    pub fn entry_side(&self) -> Option<PositionSide> {
+        match self {
+            SignalType::OpenLong(_) => Some(PositionSide::Long),
+            SignalType::OpenShort(_) => Some(PositionSide::Short),
+            _ => None,
+        }
+    }
+
+    /// Stop price of the signals opening a position or moving its stop.
+    pub fn trail_stop_price(&self) -> Option<Decimal> {
+        match self {
+            SignalType::OpenLong(price)
+            | SignalType::OpenShort(price)
+            | SignalType::StopLossUpdate(price)
+            | SignalType::Reverse(price) => Some(price.0),
+            _ => None,
+        }
+    }
+
+    pub fn close_percent(&self) -> Option<Decimal> {
+        match self {
+            SignalType::PartialClose(percent) => Some(percent.0),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for SignalType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("SignalType", 3)?;
+        state.serialize_field("signal_type", self.as_ref())?;
+        state.serialize_field("trail_stop_price", &self.trail_stop_price())?;
+        state.serialize_field("close_percent", &self.close_percent())?;
+        state.end()
+    }
+}
+
 impl<'de> Deserialize<'de> for TrailStopPrice {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -65,28 +279,66 @@ impl<'de> Deserialize<'de> for SignalType {
     where
         D: serde::Deserializer<'de>,
     {
+        use serde::de::Error;
+
         #[derive(Deserialize)]
         struct SignalTypeWrapper {
             #[serde(rename = "signal_type")]
             signal_type: String,
-            trail_stop_price: TrailStopPrice,
+            trail_stop_price: Option<TrailStopPrice>,
+            close_percent: Option<Decimal>,
         }
 
         let value = SignalTypeWrapper::deserialize(deserializer)?;
+        let trail_stop_price = || {
+            value
+                .trail_stop_price
+                .ok_or_else(|| D::Error::missing_field("trail_stop_price"))
+        };
 
         match value.signal_type.as_str() {
-            "open_long" => Ok(SignalType::OpenLong(value.trail_stop_price)),
-            "open_short" => Ok(SignalType::OpenShort(value.trail_stop_price)),
-            "stop_loss_update" => Ok(SignalType::StopLossUpdate(value.trail_stop_price)),
-            _ => Err(serde::de::Error::unknown_variant(
+            "open_long" => Ok(SignalType::OpenLong(trail_stop_price()?)),
+            "open_short" => Ok(SignalType::OpenShort(trail_stop_price()?)),
+            "stop_loss_update" => Ok(SignalType::StopLossUpdate(trail_stop_price()?)),
+            "close_long" => Ok(SignalType::CloseLong),
+            "close_short" => Ok(SignalType::CloseShort),
+            "close_all" => Ok(SignalType::CloseAll),
+            "reverse" => Ok(SignalType::Reverse(trail_stop_price()?)),
+            "partial_close" => match value.close_percent {
+                Some(percent) if percent > Decimal::ZERO && percent <= Decimal::ONE_HUNDRED => {
+                    Ok(SignalType::PartialClose(ClosePercent(percent)))
+                }
+                Some(percent) => Err(D::Error::custom(format!(
+                    "close_percent must be greater than 0 and up to 100, got {}",
+                    percent
+                ))),
+                None => Err(D::Error::missing_field("close_percent")),
+            },
+            _ => Err(D::Error::unknown_variant(
                 &value.signal_type,
-                &["open_long", "open_short", "stop_loss_update"],
+                &[
+                    "open_long",
+                    "open_short",
+                    "stop_loss_update",
+                    "close_long",
+                    "close_short",
+                    "close_all",
+                    "reverse",
+                    "partial_close",
+                ],
             )),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionSide {
+    Long,
+    Short,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, sqlx::FromRow)]
 pub struct BarData {
     pub time: DateTime<Utc>,
     pub open: Price,
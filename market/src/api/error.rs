@@ -60,6 +60,12 @@ pub enum ApiError {
     #[error("{0}")]
     Unauthorized(String), // Added Unauthorized variant
 
+    /// Rate limit exceeded error.
+    ///
+    /// HTTP status code 429
+    #[error("{0}")]
+    TooManyRequests(String),
+
     /// Failed to deserialize json.
     ///
     /// HTTP status code 422
@@ -111,6 +117,7 @@ impl IntoResponse for ApiError {
             ),
             Self::ConstraintError(err) => (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()),
             Self::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
+            Self::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
 
             // Apca crate doesn't allow to get status code from it's response and deserialize error
             // message properly. We get error message as debug string of entire result and
@@ -135,6 +142,7 @@ impl From<StatusCode> for ApiError {
             StatusCode::INTERNAL_SERVER_ERROR => Self::InternalServerError,
             StatusCode::SERVICE_UNAVAILABLE => Self::ServiceUnavailable,
             StatusCode::UNAUTHORIZED => Self::Unauthorized("Unauthorized".to_owned()),
+            StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests("Too many requests".to_owned()),
             _ => Self::InternalServerError,
         }
     }
@@ -154,6 +162,7 @@ impl ApiError {
             Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             Self::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
             Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::TradingClientError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -5,8 +5,10 @@ use axum::{
     Json,
 };
 use axum_extra::extract::WithRejection;
+use chrono::Utc;
 use serde::Deserialize;
-use tracing::error;
+use serde_json::{json, Value};
+use tracing::info;
 use uuid::Uuid;
 
 use super::{
@@ -15,65 +17,177 @@ use super::{
         Account, ActivitiesRequest, Activity, Asset, AssetClass, Broker, Order, OrdersRequest,
         Position,
     },
+    pagination::{Pagination, PaginationQuery},
+    strategy::{CreateStrategy, UpdateStrategy},
     Response,
 };
-use crate::{alert::WebhookAlertData, clients::BrokerClient, trade_signal::TradeSignal, App};
+use crate::{
+    alert::{AlertStatus, WebhookAlertData},
+    app_config::{AppConfig, ConfigDiff},
+    clients::{BrokerClient, BrokerClientError, DEFAULT_ACCOUNT},
+    halt::{Halt, HaltReport, HaltRequest, ResumeRequest},
+    queue::{JobsQuery, LaneDepth, SignalJob},
+    strategy::Strategy,
+    trade_signal::TradeSignal,
+    App,
+};
 
 pub async fn receive_webhook_alert(
     State(app): State<Arc<App>>,
-    WithRejection(alert_data, _): WithRejection<Json<WebhookAlertData>, ApiError>,
+    WithRejection(Json(alert_data), _): WithRejection<Json<WebhookAlertData>, ApiError>,
 ) -> Response<()> {
-    let trade_signal = TradeSignal::from_alert_data(alert_data.0.clone(), &app.config)?;
-    let core = Arc::clone(&app.core);
-    let client = match &trade_signal.strategy.broker {
-        Broker::Alpaca => Arc::clone(&app.clients.alpaca),
+    accept_alert(&app, alert_data, false).await
+}
+
+/// Receives alert at `/webhook/:name`, the endpoint of a signal source configured in
+/// `webhook_sources` or else the webhook endpoint of the strategy with the slug.
+pub async fn receive_named_alert(
+    State(app): State<Arc<App>>,
+    Path(name): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<Value>, ApiError>,
+) -> Response<()> {
+    let mapping = app
+        .config
+        .read()
+        .unwrap()
+        .webhook_sources
+        .get(&name)
+        .cloned();
+    match mapping {
+        Some(mapping) => {
+            let alert_data = mapping.apply(&payload, Utc::now())?;
+            accept_alert(&app, alert_data, false).await
+        }
+        None => receive_endpoint_alert(&app, &name, payload).await,
+    }
+}
+
+/// Alerts at the webhook endpoint of a strategy are attributed to it by the URL and don't need
+/// `strategy_id`. They are checked against the endpoint's limits before the strategy sees them.
+async fn receive_endpoint_alert(app: &App, slug: &str, mut payload: Value) -> Response<()> {
+    let Some(strategy) = Strategy::find_by_slug(&app.db, slug).await? else {
+        return Err(endpoint_not_found(slug));
+    };
+    let Some(endpoint) = strategy
+        .endpoint
+        .as_ref()
+        .filter(|endpoint| endpoint.enabled)
+    else {
+        return Err(endpoint_not_found(slug));
+    };
+
+    if let Some(limit) = endpoint.rate_limit {
+        if !app.webhook_limiter.check(strategy.id, limit) {
+            return Err(ApiError::TooManyRequests(format!(
+                "Webhook endpoint {} accepts {} alerts per minute",
+                slug, limit
+            )));
+        }
+    }
+
+    let Some(fields) = payload.as_object_mut() else {
+        return Err(ApiError::BadRequest(
+            "Alert must be a JSON object".to_owned(),
+        ));
     };
+    let strategy_id = fields
+        .entry("strategy_id")
+        .or_insert_with(|| json!(strategy.id));
+    if strategy_id.as_str().and_then(|id| Uuid::parse_str(id).ok()) != Some(strategy.id) {
+        return Err(ApiError::BadRequest(format!(
+            "Alert of another strategy sent to webhook endpoint {}",
+            slug
+        )));
+    }
+    let alert_data: WebhookAlertData = serde_json::from_value(payload)
+        .map_err(|err| ApiError::BadRequest(format!("Invalid alert - {}", err)))?;
 
-    tokio::spawn(async move {
-        if let Err(err) = core.process_trade_signal(client, trade_signal).await {
-            error!("Failed to process trade signal, error: {:?}", err);
-        };
-    });
-
-    // app.strategy_manager.
-
-    // _ = sqlx::query!(
-    //     r#"
-    //     INSERT INTO alerts (
-    //         alert_id,
-    //         ticker,
-    //         timeframe,
-    //         exchange, 
-    //         alert_type,
-    //         bar_time,
-    //         bar_open,
-    //         bar_high,
-    //         bar_low,
-    //         bar_close,
-    //         bar_volume,
-    //         alert_fire_time,
-    //         created_at,
-    //         modified_at
-    //     )
-    //     VALUES (
-    //         $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW(), NOW()
-    //     )
-    //     "#,
-    //     uuid7::new_v7(),
-    //     alert_data.ticker,
-    //     alert_data.timeframe,
-    //     alert_data.exchange,
-    //     alert_data.signal_type.as_ref(),
-    //     alert_data.bar_data.time,
-    //     alert_data.bar_data.open.as_ref(),
-    //     alert_data.bar_data.high.as_ref(),
-    //     alert_data.bar_data.low.as_ref(),
-    //     alert_data.bar_data.close.as_ref(),
-    //     alert_data.bar_data.volume,
-    //     alert_data.time
-    // )
-    // .execute(&app.db)
-    // .await?;
+    if let Err(reason) = endpoint.check(&alert_data) {
+        alert_data
+            .save(
+                &app.db,
+                uuid7::new_v7(),
+                AlertStatus::Rejected,
+                Some(&reason),
+            )
+            .await?;
+        return Err(ApiError::BadRequest(reason));
+    }
+
+    accept_alert(app, alert_data, true).await
+}
+
+/// Stores the alert and queues its signal. Strategies with a webhook endpoint only accept alerts
+/// received at it, so its limits can't be bypassed.
+async fn accept_alert(app: &App, alert_data: WebhookAlertData, via_endpoint: bool) -> Response<()> {
+    let received_at = Utc::now();
+    let alert_id = uuid7::new_v7();
+    let trade_signal = TradeSignal::from_alert_data(alert_id, alert_data.clone(), &app.db)
+        .await
+        .and_then(|trade_signal| {
+            if trade_signal.strategy.endpoint.is_some() && !via_endpoint {
+                return Err(ApiError::BadRequest(format!(
+                    "Strategy {} accepts alerts only at its webhook endpoint",
+                    trade_signal.strategy.name
+                )));
+            }
+            // Stale alerts were delayed or replayed, the market moved on since they fired
+            trade_signal
+                .strategy
+                .check_alert_time(alert_data.time, received_at)
+                .map_err(ApiError::BadRequest)?;
+            Ok(trade_signal)
+        });
+    let trade_signal = match trade_signal {
+        Ok(trade_signal) => trade_signal,
+        Err(err) => {
+            alert_data
+                .save(
+                    &app.db,
+                    alert_id,
+                    AlertStatus::Rejected,
+                    Some(&err.to_string()),
+                )
+                .await?;
+            return Err(err);
+        }
+    };
+
+    // Alert and its job are stored together, so no accepted alert is left unprocessed
+    let mut tx = app.db.begin().await?;
+    let accepted = alert_data
+        .save(&mut *tx, alert_id, AlertStatus::Accepted, None)
+        .await?;
+    if !accepted {
+        tx.rollback().await?;
+        // Acknowledge retried and double fired alerts so the source stops resending them
+        info!("Skipping duplicate alert {}", alert_data.dedup_key());
+        alert_data
+            .save(
+                &app.db,
+                alert_id,
+                AlertStatus::Duplicate,
+                Some("Alert with the same key was already accepted"),
+            )
+            .await?;
+        return Ok(Json::default());
+    }
+
+    let max_attempts = app.config.read().unwrap().queue.max_attempts;
+    SignalJob::enqueue(
+        &mut *tx,
+        alert_id,
+        &alert_data,
+        &trade_signal.lane(),
+        max_attempts,
+    )
+    .await?;
+    tx.commit().await?;
+    app.metrics.observe_alert_latency(alert_data.time, received_at);
+
+    // Alerts are the market data of the paper broker
+    app.brokers
+        .on_bar(&trade_signal.ticker, &trade_signal.bar_data);
 
     Ok(Json::default())
 }
@@ -81,6 +195,15 @@ pub async fn receive_webhook_alert(
 #[derive(Debug, Deserialize)]
 pub struct BrokerQuery {
     broker: Broker,
+    /// Name of the broker's account, the `default` one if not set.
+    account: Option<String>,
+}
+
+impl BrokerQuery {
+    fn client(&self, app: &App) -> Result<Arc<dyn BrokerClient>, BrokerClientError> {
+        let account = self.account.as_deref().unwrap_or(DEFAULT_ACCOUNT);
+        app.brokers.get(&self.broker, account)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -96,21 +219,20 @@ pub async fn get_account(
     State(app): State<Arc<App>>,
     Query(broker_query): Query<BrokerQuery>,
 ) -> Response<Account> {
-    let client = broker_query.broker.get_client(&app);
-    Ok(Json(client.get_account().await?))
+    let account = broker_query.client(&app)?.get_account().await?;
+    Ok(Json(account))
 }
 
 #[axum::debug_handler]
 pub async fn get_activities(
     State(app): State<Arc<App>>,
+    Query(broker_query): Query<BrokerQuery>,
     WithRejection(activities_req, _): WithRejection<Json<ActivitiesRequest>, ApiError>,
 ) -> Response<Vec<Activity>> {
-    let activities: Vec<Activity> = match activities_req.0 {
-        ActivitiesRequest::AlpacaActivitiesReq(req) => {
-            app.clients.alpaca.get_activities(req).await?
-        }
-    };
-
+    let activities = broker_query
+        .client(&app)?
+        .get_activities(activities_req.0)
+        .await?;
     Ok(Json(activities))
 }
 
@@ -119,8 +241,11 @@ pub async fn get_asset(
     Query(broker_query): Query<BrokerQuery>,
     Path(symbol): Path<String>,
 ) -> Response<Asset> {
-    let client = broker_query.broker.get_client(&app);
-    Ok(Json(client.get_asset(symbol.to_uppercase()).await?))
+    let asset = broker_query
+        .client(&app)?
+        .get_asset(symbol.to_uppercase())
+        .await?;
+    Ok(Json(asset))
 }
 
 pub async fn get_assets(
@@ -128,8 +253,11 @@ pub async fn get_assets(
     Query(broker_query): Query<BrokerQuery>,
     Query(asset_type): Query<AssetTypeQuery>,
 ) -> Response<Vec<Asset>> {
-    let client = broker_query.broker.get_client(&app);
-    Ok(Json(client.get_assets(asset_type.class).await?))
+    let assets = broker_query
+        .client(&app)?
+        .get_assets(asset_type.class)
+        .await?;
+    Ok(Json(assets))
 }
 
 pub async fn get_order(
@@ -137,46 +265,47 @@ pub async fn get_order(
     Query(broker_query): Query<BrokerQuery>,
     Path(id): Path<Uuid>,
 ) -> Response<Order> {
-    let client = broker_query.broker.get_client(&app);
-    let order = client.get_order_by_client_id(id.to_string()).await?;
+    let order = broker_query
+        .client(&app)?
+        .get_order_by_client_id(id.to_string())
+        .await?;
     Ok(Json(order))
 }
 
 pub async fn get_orders(
     State(app): State<Arc<App>>,
+    Query(broker_query): Query<BrokerQuery>,
     WithRejection(orders_req, _): WithRejection<Json<OrdersRequest>, ApiError>,
 ) -> Response<Vec<Order>> {
-    let orders: Vec<Order> = match orders_req.0 {
-        OrdersRequest::AlpacaOrders(req) => app.clients.alpaca.get_orders(req).await?,
-    };
+    let orders = broker_query.client(&app)?.get_orders(orders_req.0).await?;
     Ok(Json(orders))
 }
 
 // NOTE: Algorithmically create orders
 // pub async fn create_order(
 //     State(app): State<Arc<App>>,
-//     WithRejection(new_order_req, _): WithRejection<Json<NewOrder>, ApiError>,
-// ) -> Response<Order> { let new_order = match new_order_req.0 { NewOrder::AlpacaNewOrder(req) =>
-//   app.clients.alpaca.create_order(req).await?, }; Ok(Json(new_order))
+//     Query(broker_query): Query<BrokerQuery>,
+//     WithRejection(new_order_req, _): WithRejection<Json<OrderRequest>, ApiError>,
+// ) -> Response<Order> { let new_order =
+//   broker_query.client(&app)?.create_order(new_order_req.0).await?; Ok(Json(new_order))
 // }
 
 // NOTE: Algorithmically update orders
 // pub async fn update_order(
-//     Path(id): Path<Uuid>,
+//     Path(id): Path<String>,
 //     State(app): State<Arc<App>>,
-//     WithRejection(order_update_req, _): WithRejection<Json<UpdateOrder>, ApiError>,
-// ) -> Response<Order> { let updated_order = match order_update_req.0 {
-//   UpdateOrder::AlpacaUpdateOrder(req) => app.clients.alpaca.update_order(id, req).await?, };
-//   Ok(Json(updated_order))
+//     Query(broker_query): Query<BrokerQuery>,
+//     WithRejection(order_update_req, _): WithRejection<Json<OrderUpdate>, ApiError>,
+// ) -> Response<Order> { let updated_order = broker_query.client(&app)?.update_order(&id,
+//   order_update_req.0).await?; Ok(Json(updated_order))
 // }
 
 // NOTE: Algorithmically delete orders
 // pub async fn delete_order(
 //     State(app): State<Arc<App>>,
 //     Query(broker_query): Query<BrokerQuery>,
-//     Path(id): Path<Uuid>,
-// ) -> Response<()> { let client = broker_query.broker.get_client(&app);
-//   client.delete_order(id).await?; Ok(Json::default())
+//     Path(id): Path<String>,
+// ) -> Response<()> { broker_query.client(&app)?.delete_order(&id).await?; Ok(Json::default())
 // }
 
 pub async fn get_position(
@@ -184,8 +313,7 @@ pub async fn get_position(
     Path(symbol): Path<String>,
     Query(query): Query<BrokerQuery>,
 ) -> Response<Position> {
-    let client = query.broker.get_client(&app);
-    let position = client.get_position(symbol).await?;
+    let position = query.client(&app)?.get_position(symbol).await?;
     Ok(Json(position))
 }
 
@@ -193,8 +321,7 @@ pub async fn get_positions(
     State(app): State<Arc<App>>,
     Query(query): Query<BrokerQuery>,
 ) -> Response<Vec<Position>> {
-    let client = query.broker.get_client(&app);
-    let positions = client.get_positions().await?;
+    let positions = query.client(&app)?.get_positions().await?;
     Ok(Json(positions))
 }
 
@@ -203,7 +330,231 @@ pub async fn delete_position(
     Query(broker_query): Query<BrokerQuery>,
     Path(symbol): Path<String>,
 ) -> Response<Order> {
-    let client = broker_query.broker.get_client(&app);
-    let delete_position_order = client.delete_position(symbol).await?;
+    let delete_position_order = broker_query.client(&app)?.delete_position(symbol).await?;
     Ok(Json(delete_position_order))
 }
+
+/// Reloads config files and applies changed strategies and risk limits.
+pub async fn reload_config(State(app): State<Arc<App>>) -> Response<ConfigDiff> {
+    let config = AppConfig::build().map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    Ok(Json(app.reload_config(config).await?))
+}
+
+/// Activates the kill switch, see `Core::halt`.
+pub async fn halt_trading(
+    State(app): State<Arc<App>>,
+    WithRejection(Json(halt_req), _): WithRejection<Json<HaltRequest>, ApiError>,
+) -> Response<HaltReport> {
+    let strategy = match halt_req.strategy_id {
+        Some(id) => Some(find_strategy(&app, id).await?),
+        None => None,
+    };
+
+    let report = app
+        .core
+        .halt(&app.brokers, strategy.as_ref(), &halt_req)
+        .await?;
+    Ok(Json(report))
+}
+
+pub async fn resume_trading(
+    State(app): State<Arc<App>>,
+    WithRejection(Json(resume_req), _): WithRejection<Json<ResumeRequest>, ApiError>,
+) -> Response<()> {
+    if !Halt::lift(&app.db, resume_req.strategy_id).await? {
+        return Err(ApiError::NotFound("No active halt found".to_owned()));
+    }
+
+    match resume_req.strategy_id {
+        Some(id) => info!("Resumed trading of strategy {}", id),
+        None => info!("Resumed trading"),
+    }
+    Ok(Json::default())
+}
+
+pub async fn get_halts(State(app): State<Arc<App>>) -> Response<Vec<Halt>> {
+    Ok(Json(Halt::active(&app.db).await?))
+}
+
+pub async fn get_jobs(
+    State(app): State<Arc<App>>,
+    Query(jobs_query): Query<JobsQuery>,
+) -> Response<Pagination<SignalJob>> {
+    let (jobs, total) = SignalJob::list(
+        &app.db,
+        jobs_query.status,
+        jobs_query.offset,
+        jobs_query.limit,
+    )
+    .await?;
+    let pagination = PaginationQuery {
+        offset: jobs_query.offset,
+        limit: jobs_query.limit,
+    };
+    Ok(Json(Pagination::new(jobs, total, pagination)))
+}
+
+/// Backlog of every lane with pending jobs.
+pub async fn get_lanes(State(app): State<Arc<App>>) -> Response<Vec<LaneDepth>> {
+    Ok(Json(SignalJob::lane_depths(&app.db).await?))
+}
+
+pub async fn get_metrics(State(app): State<Arc<App>>) -> Result<String, ApiError> {
+    Ok(app.metrics.render(&app.db).await?)
+}
+
+pub async fn get_job(State(app): State<Arc<App>>, Path(id): Path<Uuid>) -> Response<SignalJob> {
+    let job = SignalJob::find(&app.db, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Unknown job - {}", id)))?;
+    Ok(Json(job))
+}
+
+/// Queues dead-lettered job again.
+pub async fn replay_job(State(app): State<Arc<App>>, Path(id): Path<Uuid>) -> Response<SignalJob> {
+    if !SignalJob::replay(&app.db, id).await? {
+        return Err(ApiError::BadRequest(format!(
+            "Job {} doesn't exist or isn't dead",
+            id
+        )));
+    }
+
+    info!("Replaying job {}", id);
+    get_job(State(app), Path(id)).await
+}
+
+pub async fn get_strategies(
+    State(app): State<Arc<App>>,
+    Query(pagination): Query<PaginationQuery>,
+) -> Response<Pagination<Strategy>> {
+    let (strategies, total) = Strategy::list(&app.db, pagination.offset, pagination.limit).await?;
+    Ok(Json(Pagination::new(strategies, total, pagination)))
+}
+
+pub async fn get_strategy(State(app): State<Arc<App>>, Path(id): Path<Uuid>) -> Response<Strategy> {
+    Ok(Json(find_strategy(&app, id).await?))
+}
+
+pub async fn create_strategy(
+    State(app): State<Arc<App>>,
+    WithRejection(Json(create_req), _): WithRejection<Json<CreateStrategy>, ApiError>,
+) -> Response<Strategy> {
+    let strategy = Strategy::from(create_req);
+    validate_strategy(&app, &strategy).await?;
+
+    if !strategy.insert(&app.db).await? {
+        return Err(ApiError::BadRequest(format!(
+            "Strategy with id {} already exists",
+            strategy.id
+        )));
+    }
+
+    info!("Created strategy {} with id {}", strategy.name, strategy.id);
+    Ok(Json(strategy))
+}
+
+pub async fn update_strategy(
+    State(app): State<Arc<App>>,
+    Path(id): Path<Uuid>,
+    WithRejection(Json(update_req), _): WithRejection<Json<UpdateStrategy>, ApiError>,
+) -> Response<Strategy> {
+    let mut strategy = find_strategy(&app, id).await?;
+    update_req.apply(&mut strategy);
+    validate_strategy(&app, &strategy).await?;
+
+    save_strategy(&app, strategy).await
+}
+
+pub async fn enable_strategy(
+    State(app): State<Arc<App>>,
+    Path(id): Path<Uuid>,
+) -> Response<Strategy> {
+    let mut strategy = find_strategy(&app, id).await?;
+    strategy.enabled = true;
+    validate_strategy(&app, &strategy).await?;
+
+    save_strategy(&app, strategy).await
+}
+
+pub async fn disable_strategy(
+    State(app): State<Arc<App>>,
+    Path(id): Path<Uuid>,
+) -> Response<Strategy> {
+    let mut strategy = find_strategy(&app, id).await?;
+    strategy.enabled = false;
+
+    save_strategy(&app, strategy).await
+}
+
+pub async fn delete_strategy(State(app): State<Arc<App>>, Path(id): Path<Uuid>) -> Response<()> {
+    if !Strategy::delete(&app.db, id).await? {
+        return Err(strategy_not_found(id));
+    }
+
+    info!("Deleted strategy with id {}", id);
+    Ok(Json::default())
+}
+
+async fn find_strategy(app: &App, id: Uuid) -> Result<Strategy, ApiError> {
+    Strategy::find(&app.db, id)
+        .await?
+        .ok_or_else(|| strategy_not_found(id))
+}
+
+async fn save_strategy(app: &App, strategy: Strategy) -> Response<Strategy> {
+    if !strategy.update(&app.db).await? {
+        return Err(strategy_not_found(strategy.id));
+    }
+
+    info!("Updated strategy {} with id {}", strategy.name, strategy.id);
+    Ok(Json(strategy))
+}
+
+/// Enabled strategies must trade on a configured broker account. Webhook endpoints need a token
+/// and a slug which isn't taken by a webhook source or another strategy.
+async fn validate_strategy(app: &App, strategy: &Strategy) -> Result<(), ApiError> {
+    for (limit, value) in [
+        ("max_signal_age", strategy.max_signal_age),
+        ("max_future_skew", strategy.max_future_skew),
+    ] {
+        if !value.is_finite() || value < 0.0 {
+            return Err(ApiError::BadRequest(format!(
+                "{} must be a non-negative number of seconds",
+                limit
+            )));
+        }
+    }
+    if let Some(take_profit) = &strategy.take_profit {
+        take_profit.validate().map_err(ApiError::BadRequest)?;
+    }
+    if strategy.enabled {
+        app.brokers
+            .get(&strategy.broker, &strategy.account)
+            .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    }
+    if let Some(endpoint) = &strategy.endpoint {
+        app.config
+            .read()
+            .unwrap()
+            .validate_endpoint(strategy, endpoint)
+            .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        if let Some(other) = Strategy::find_by_slug(&app.db, &endpoint.slug).await? {
+            if other.id != strategy.id {
+                return Err(ApiError::BadRequest(format!(
+                    "Webhook endpoint slug {} is used by strategy {}",
+                    endpoint.slug, other.name
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn strategy_not_found(id: Uuid) -> ApiError {
+    ApiError::NotFound(format!("Unknown strategy - {}", id))
+}
+
+fn endpoint_not_found(slug: &str) -> ApiError {
+    ApiError::NotFound(format!("Unknown webhook endpoint - {}", slug))
+}
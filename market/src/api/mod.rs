@@ -3,6 +3,7 @@ pub mod error;
 pub mod handlers;
 pub mod objects;
 pub mod pagination;
+pub mod payload;
 pub mod price;
 pub mod strategy;
 
@@ -1,41 +1,65 @@
-use apca::api::v2::{
-    account::Account as AlpacaAccount,
-    account_activities::{Activity as AlpacaActivity, ActivityReq as AlpacaActivitiesReq},
-    asset::Asset as AlpacaAsset,
-    order::{ChangeReq as AlpacaOrderUpdateReq, Order as AlpacaOrder, OrderReq as AlpacaNewOrder},
-    orders::OrdersReq as AlpacOrdersReq,
-    position::Position as AlpacaPosition,
-};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum_macros::{AsRefStr, EnumString};
 
-use crate::{clients::BrokerClient, App};
+use crate::alert::PositionSide;
 
-pub trait GetBroker {
-    fn broker(&self) -> Broker;
-}
-
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, AsRefStr, EnumString)]
 #[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum Broker {
     Alpaca,
+    /// In-process simulated broker, see `PaperBroker`.
+    Paper,
+    #[cfg(feature = "bybit")]
+    Bybit,
 }
 
-#[derive(Debug, Serialize)]
-pub enum Account {
-    AlpacaAccount(AlpacaAccount),
+/// Trading account of a broker. Amounts are in the account currency.
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+    pub broker: Broker,
+    pub currency: String,
+    pub cash: Decimal,
+    pub equity: Decimal,
+    /// Equity at the start of the trading day.
+    pub last_equity: Decimal,
+    pub buying_power: Decimal,
+    /// Account as returned by the broker's API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
 }
 
-#[derive(Debug, Deserialize)]
-pub enum ActivitiesRequest {
-    AlpacaActivitiesReq(AlpacaActivitiesReq),
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ActivitiesRequest {
+    /// Broker's names of the activity types, e.g. `FILL`. All types if empty.
+    #[serde(default)]
+    pub activity_types: Vec<String>,
+    pub after: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub enum Activity {
-    AlpacaActivity(AlpacaActivity),
+/// Account activity, e.g. a fill or a dividend.
+#[derive(Debug, Clone, Serialize)]
+pub struct Activity {
+    pub broker: Broker,
+    pub activity_id: String,
+    /// Broker's name of the activity, e.g. `FILL`.
+    pub activity_type: String,
+    pub symbol: Option<String>,
+    pub side: Option<OrderSide>,
+    pub quantity: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub net_amount: Option<Decimal>,
+    pub time: Option<DateTime<Utc>>,
+    /// Activity as returned by the broker's API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum AssetClass {
     #[serde(rename = "us_equity")]
     UsEquity,
@@ -43,63 +67,174 @@ pub enum AssetClass {
     Crypto,
 }
 
-#[derive(Debug, Serialize)]
-pub enum Asset {
-    AlpacaAsset(AlpacaAsset),
+#[derive(Debug, Clone, Serialize)]
+pub struct Asset {
+    pub broker: Broker,
+    pub symbol: String,
+    pub class: AssetClass,
+    pub tradable: bool,
+    /// Whether orders may have fractional quantities.
+    pub fractionable: bool,
+    /// Step order quantities must be a multiple of, if the broker has one.
+    pub quantity_increment: Option<Decimal>,
+    /// Smallest quantity of an order, if the broker has a minimum.
+    pub min_order_quantity: Option<Decimal>,
+    /// Asset as returned by the broker's API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
 }
 
-#[derive(Debug, Serialize)]
-pub enum Order {
-    AlpacaOrder(AlpacaOrder),
+impl Asset {
+    /// Rounds the quantity down to a multiple of the quantity increment, if there is one.
+    pub fn round_to_increment(&self, quantity: Decimal) -> Decimal {
+        match self.quantity_increment.filter(|step| *step > Decimal::ZERO) {
+            Some(step) => ((quantity / step).floor() * step).normalize(),
+            None => quantity,
+        }
+    }
+
+    /// Whether an order of the quantity is below the broker's minimum.
+    pub fn below_min_order(&self, quantity: Decimal) -> bool {
+        self.min_order_quantity.is_some_and(|min| quantity < min)
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub enum OrdersRequest {
-    AlpacaOrders(AlpacOrdersReq),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderSide {
+    Buy,
+    Sell,
 }
 
-// NOTE: Algorithmically create orders
-// #[derive(Debug, Deserialize)]
-// pub enum NewOrder {
-//     AlpacaNewOrder(AlpacaNewOrder),
-// }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderType {
+    Market,
+    Limit,
+    Stop,
+    StopLimit,
+    TrailingStop,
+}
 
-// NOTE: Algorithmically update orders
-// #[derive(Debug, Deserialize)]
-// pub enum UpdateOrder {
-//     AlpacaUpdateOrder(AlpacaOrderUpdateReq),
-// }
+/// Order status normalized across brokers. Statuses the broker has no equivalent of are not
+/// reported by its adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderStatus {
+    /// Accepted and working.
+    New,
+    /// Waiting for a trigger, e.g. the exit legs of a bracket before the entry fills.
+    Held,
+    PartiallyFilled,
+    Filled,
+    PendingCancel,
+    Canceled,
+    Expired,
+    /// Replaced by an amended order.
+    Replaced,
+    Rejected,
+}
 
-#[derive(Debug, Deserialize, Serialize)]
-pub enum Position {
-    AlpacaPosition(AlpacaPosition),
+/// Order as reported by a broker.
+#[derive(Debug, Clone, Serialize)]
+pub struct Order {
+    pub broker: Broker,
+    /// Id of the order assigned by the broker.
+    pub broker_order_id: String,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    /// Not set for orders of a notional amount.
+    pub quantity: Option<Decimal>,
+    pub limit_price: Option<Decimal>,
+    pub stop_price: Option<Decimal>,
+    pub status: OrderStatus,
+    pub filled_quantity: Decimal,
+    pub average_fill_price: Option<Decimal>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub filled_at: Option<DateTime<Utc>>,
+    /// Orders of a bracket or OTO order triggered by this one.
+    pub legs: Vec<Order>,
+    /// Order as returned by the broker's API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
 }
 
-impl From<AssetClass> for apca::api::v2::asset::Class {
-    fn from(value: AssetClass) -> Self {
-        match value {
-            AssetClass::UsEquity => apca::api::v2::asset::Class::UsEquity,
-            AssetClass::Crypto => apca::api::v2::asset::Class::Crypto,
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeInForce {
+    Day,
+    /// Good until canceled.
+    Gtc,
+    /// Immediate or cancel.
+    Ioc,
 }
 
-impl Broker {
-    pub fn get_client<'a>(&self, app: &'a App) -> &'a impl BrokerClient {
-        match self {
-            Broker::Alpaca => &app.clients.alpaca,
-        }
-    }
+/// New order, mapped by each broker client onto its native request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderRequest {
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub quantity: Decimal,
+    pub limit_price: Option<Decimal>,
+    pub stop_price: Option<Decimal>,
+    pub time_in_force: TimeInForce,
+    /// Exits attached to the entry, see `BrokerClient::supports_bracket_orders`.
+    pub stop_loss: Option<Decimal>,
+    pub take_profit: Option<Decimal>,
+    /// Whether the order may only reduce an open position.
+    #[serde(default)]
+    pub reduce_only: bool,
+}
 
-    pub async fn create_order_request(&self) -> Result<(), ()> {
-        Ok(())
-    }
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OrdersRequest {
+    pub symbol: Option<String>,
+    /// Only orders which are not filled or canceled yet.
+    #[serde(default)]
+    pub open_only: bool,
+}
+
+/// Change of an open order. Prices which are not set stay as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OrderUpdate {
+    pub stop_price: Option<Decimal>,
+    pub limit_price: Option<Decimal>,
+    /// Client order id of the order replacing the changed one, for brokers which replace
+    /// orders on change, e.g. Alpaca. Brokers amending orders in place keep the old id.
+    pub client_order_id: Option<String>,
 }
 
-impl GetBroker for OrdersRequest {
-    fn broker(&self) -> Broker {
-        match self {
-            OrdersRequest::AlpacaOrders(_) => Broker::Alpaca,
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    pub broker: Broker,
+    pub symbol: String,
+    pub side: PositionSide,
+    /// Absolute size of the position.
+    pub quantity: Decimal,
+    pub average_entry_price: Decimal,
+    pub current_price: Option<Decimal>,
+    /// Signed market value, negative for short positions.
+    pub market_value: Decimal,
+    /// Unrealized profit or loss of the position since the start of the day.
+    pub unrealized_intraday_pl: Decimal,
+    /// Position as returned by the broker's API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
+}
+
+impl From<AssetClass> for apca::api::v2::asset::Class {
+    fn from(value: AssetClass) -> Self {
+        match value {
+            AssetClass::UsEquity => apca::api::v2::asset::Class::UsEquity,
+            AssetClass::Crypto => apca::api::v2::asset::Class::Crypto,
         }
     }
 }
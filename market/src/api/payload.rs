@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::{alert::WebhookAlertData, error::ApiError};
+
+/// Mapping of a webhook source's payload onto the alert TradingView sends, served at
+/// `POST /webhook/:source`. Fields of the alert are set by their path, nested ones separated by
+/// dots, e.g. in config:
+///
+/// ```toml
+/// [webhook_sources.trendspider.fields]
+/// strategy_id = "/strategy"
+/// passphrase = "/token"
+/// ticker = "/symbol"
+/// timeframe = "/interval"
+/// exchange = { default = "NASDAQ" }
+/// signal_type = { pointer = "/action", values = { buy = "open_long", sell = "open_short" } }
+/// trail_stop_price = "/stop"
+/// "bar_data.close" = "/price"
+/// "bar_data.time" = "/bar_time"
+/// ```
+///
+/// Alerts of a source are deduplicated by `client_alert_id` or else by `bar_data.time`, so a
+/// mapping needs at least one of them and payloads without either are rejected. Unmapped `time`
+/// and `bar_data.time` default to each other, both to the time the payload is received at if only
+/// the alert id is given.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PayloadMapping {
+    pub fields: BTreeMap<String, FieldMapping>,
+}
+
+/// Value of an alert field, either the JSON pointer of the payload field it is taken from or
+/// a table with the pointer, replacements of the payload values and a default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum FieldMapping {
+    Pointer(String),
+    Mapped {
+        pointer: Option<String>,
+        /// Replacements of the payload values, keyed by the value as a string. Values without
+        /// a replacement are rejected.
+        #[serde(default)]
+        values: BTreeMap<String, Value>,
+        /// Used if the pointer isn't set or the payload has no value at it.
+        default: Option<Value>,
+    },
+}
+
+impl PayloadMapping {
+    /// Alert fields of which one has to be mapped for alerts to be deduplicated.
+    const IDENTIFYING_FIELDS: [&'static str; 3] = ["client_alert_id", "bar_data.time", "time"];
+
+    /// Checks that alerts of the source can be told apart, as the receipt time differs between
+    /// retries of the same payload.
+    pub fn validate(&self) -> Result<(), String> {
+        if Self::IDENTIFYING_FIELDS
+            .iter()
+            .any(|field| self.fields.contains_key(*field))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "maps none of {}",
+                Self::IDENTIFYING_FIELDS.join(", ")
+            ))
+        }
+    }
+
+    pub fn apply(
+        &self,
+        payload: &Value,
+        received_at: DateTime<Utc>,
+    ) -> Result<WebhookAlertData, ApiError> {
+        let mut alert = json!({ "bar_data": {} });
+        for (path, mapping) in &self.fields {
+            let value = mapping
+                .value(payload)
+                .map_err(|err| ApiError::BadRequest(format!("Can't map {} - {}", path, err)))?;
+            if let Some(value) = value {
+                set_path(&mut alert, path, value);
+            }
+        }
+        let time = alert.get("time").cloned();
+        let bar_time = alert.pointer("/bar_data/time").cloned();
+        let time = match time.or(bar_time) {
+            Some(time) => time,
+            None if alert.get("client_alert_id").is_some() => json!(received_at),
+            None => {
+                return Err(ApiError::BadRequest(
+                    "Payload has neither an alert id nor a bar time".to_owned(),
+                ))
+            }
+        };
+        if let Some(bar_data) = alert.get_mut("bar_data").and_then(Value::as_object_mut) {
+            bar_data.entry("time").or_insert_with(|| time.clone());
+        }
+        alert["time"] = time;
+
+        serde_json::from_value(alert).map_err(|err| {
+            ApiError::BadRequest(format!("Payload doesn't map onto an alert - {}", err))
+        })
+    }
+}
+
+impl FieldMapping {
+    fn value(&self, payload: &Value) -> Result<Option<Value>, String> {
+        let (pointer, values, default) = match self {
+            FieldMapping::Pointer(pointer) => (Some(pointer), None, None),
+            FieldMapping::Mapped {
+                pointer,
+                values,
+                default,
+            } => (pointer.as_ref(), Some(values), default.as_ref()),
+        };
+
+        let Some(value) = pointer.and_then(|pointer| payload.pointer(pointer)) else {
+            return Ok(default.cloned());
+        };
+        match values.filter(|values| !values.is_empty()) {
+            Some(values) => {
+                let key = match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                values
+                    .get(&key)
+                    .cloned()
+                    .map(Some)
+                    .ok_or_else(|| format!("no replacement for value {}", value))
+            }
+            None => Ok(Some(value.clone())),
+        }
+    }
+}
+
+fn set_path(target: &mut Value, path: &str, value: Value) {
+    let mut target = target;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        let object = target.as_object_mut().unwrap();
+        if keys.peek().is_none() {
+            object.insert(key.to_owned(), value);
+            return;
+        }
+        target = object.entry(key).or_insert(Value::Null);
+    }
+}
@@ -1,2 +1,143 @@
-#[derive(Debug, Clone)]
-pub struct UpdateStrategy;
+use serde::{Deserialize, Deserializer};
+use uuid::Uuid;
+
+use super::objects::Broker;
+use crate::{
+    clients::DEFAULT_ACCOUNT,
+    strategy::{
+        default_max_future_skew, default_max_signal_age, CurrencyType, PositionSizing, Strategy,
+        TakeProfit, WebhookEndpoint,
+    },
+};
+
+/// New strategy. Id is generated if not set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateStrategy {
+    pub id: Option<Uuid>,
+    pub name: String,
+    #[serde(default)]
+    pub enabled: bool,
+    pub broker: Broker,
+    pub account: Option<String>,
+    pub currency_type: CurrencyType,
+    pub position_sizing: PositionSizing,
+    pub take_profit: Option<TakeProfit>,
+    #[serde(default)]
+    pub allow_stop_loosening: bool,
+    pub max_order_retries: u8,
+    pub order_retry_delay: f64,
+    #[serde(default = "default_max_signal_age")]
+    pub max_signal_age: f64,
+    #[serde(default = "default_max_future_skew")]
+    pub max_future_skew: f64,
+    pub webhook_passphrase: String,
+    pub webhook_hmac_secret: Option<String>,
+    pub endpoint: Option<WebhookEndpoint>,
+}
+
+impl From<CreateStrategy> for Strategy {
+    fn from(create: CreateStrategy) -> Self {
+        Self {
+            id: create.id.unwrap_or_else(uuid7::new_v7),
+            name: create.name,
+            enabled: create.enabled,
+            broker: create.broker,
+            account: create.account.unwrap_or_else(|| DEFAULT_ACCOUNT.to_owned()),
+            currency_type: create.currency_type,
+            position_sizing: create.position_sizing,
+            take_profit: create.take_profit,
+            allow_stop_loosening: create.allow_stop_loosening,
+            max_order_retries: create.max_order_retries,
+            order_retry_delay: create.order_retry_delay,
+            max_signal_age: create.max_signal_age,
+            max_future_skew: create.max_future_skew,
+            webhook_passphrase: create.webhook_passphrase,
+            webhook_hmac_secret: create.webhook_hmac_secret,
+            endpoint: create.endpoint,
+        }
+    }
+}
+
+/// Change of a strategy. Fields which are not set stay as they are, optional fields are cleared
+/// by `null`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateStrategy {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub broker: Option<Broker>,
+    pub account: Option<String>,
+    pub currency_type: Option<CurrencyType>,
+    pub position_sizing: Option<PositionSizing>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub take_profit: Option<Option<TakeProfit>>,
+    pub allow_stop_loosening: Option<bool>,
+    pub max_order_retries: Option<u8>,
+    pub order_retry_delay: Option<f64>,
+    pub max_signal_age: Option<f64>,
+    pub max_future_skew: Option<f64>,
+    pub webhook_passphrase: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub webhook_hmac_secret: Option<Option<String>>,
+    /// Replaces the whole endpoint including its token.
+    #[serde(default, deserialize_with = "nullable")]
+    pub endpoint: Option<Option<WebhookEndpoint>>,
+}
+
+impl UpdateStrategy {
+    pub fn apply(self, strategy: &mut Strategy) {
+        if let Some(name) = self.name {
+            strategy.name = name;
+        }
+        if let Some(enabled) = self.enabled {
+            strategy.enabled = enabled;
+        }
+        if let Some(broker) = self.broker {
+            strategy.broker = broker;
+        }
+        if let Some(account) = self.account {
+            strategy.account = account;
+        }
+        if let Some(currency_type) = self.currency_type {
+            strategy.currency_type = currency_type;
+        }
+        if let Some(position_sizing) = self.position_sizing {
+            strategy.position_sizing = position_sizing;
+        }
+        if let Some(take_profit) = self.take_profit {
+            strategy.take_profit = take_profit;
+        }
+        if let Some(allow_stop_loosening) = self.allow_stop_loosening {
+            strategy.allow_stop_loosening = allow_stop_loosening;
+        }
+        if let Some(max_order_retries) = self.max_order_retries {
+            strategy.max_order_retries = max_order_retries;
+        }
+        if let Some(order_retry_delay) = self.order_retry_delay {
+            strategy.order_retry_delay = order_retry_delay;
+        }
+        if let Some(max_signal_age) = self.max_signal_age {
+            strategy.max_signal_age = max_signal_age;
+        }
+        if let Some(max_future_skew) = self.max_future_skew {
+            strategy.max_future_skew = max_future_skew;
+        }
+        if let Some(webhook_passphrase) = self.webhook_passphrase {
+            strategy.webhook_passphrase = webhook_passphrase;
+        }
+        if let Some(webhook_hmac_secret) = self.webhook_hmac_secret {
+            strategy.webhook_hmac_secret = webhook_hmac_secret;
+        }
+        if let Some(endpoint) = self.endpoint {
+            strategy.endpoint = endpoint;
+        }
+    }
+}
+
+/// Tells a field set to `null` (`Some(None)`) from a missing one (`None`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
@@ -1,18 +1,81 @@
-use std::env;
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fmt,
+};
 
 use config::{Config, ConfigError, File};
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::strategy::Strategy;
+use crate::{
+    api::payload::PayloadMapping,
+    clients::DEFAULT_ACCOUNT,
+    queue::QueueSettings,
+    risk::RiskLimits,
+    strategy::{Strategy, WebhookEndpoint},
+};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Database {
     pub url: String,
+    /// Size of the connection pool, shared by the queue workers, `Core` and the API.
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+}
+
+fn default_max_connections() -> u32 {
+    10
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Brokers {
-    pub alpaca: Alpaca,
+    /// Alpaca is not available to strategies if not set.
+    #[serde(default)]
+    pub alpaca: BrokerAccounts<Alpaca>,
+    /// A `default` account with default settings is used if not set.
+    #[serde(default)]
+    pub paper: BrokerAccounts<Paper>,
+    /// Bybit is not available to strategies if not set.
+    #[cfg(feature = "bybit")]
+    #[serde(default)]
+    pub bybit: BrokerAccounts<Bybit>,
+}
+
+/// Accounts of a broker, either a single one which is the `default` account, e.g.
+/// `[brokers.alpaca]`, or several named ones, e.g. `[brokers.alpaca.live]` and
+/// `[brokers.alpaca.research]`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum BrokerAccounts<T> {
+    Named(BTreeMap<String, T>),
+    Single(T),
+}
+
+impl<T> BrokerAccounts<T> {
+    /// Accounts with their names.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&str, &T)> + '_> {
+        match self {
+            BrokerAccounts::Named(accounts) => Box::new(
+                accounts
+                    .iter()
+                    .map(|(name, account)| (name.as_str(), account)),
+            ),
+            BrokerAccounts::Single(account) => {
+                Box::new(std::iter::once((DEFAULT_ACCOUNT, account)))
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, BrokerAccounts::Named(accounts) if accounts.is_empty())
+    }
+}
+
+impl<T> Default for BrokerAccounts<T> {
+    fn default() -> Self {
+        BrokerAccounts::Named(BTreeMap::new())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -20,6 +83,57 @@ pub struct Alpaca {
     pub apca_api_key_id: String,
     pub apca_api_secret_key: String,
     pub apca_api_base_url: String,
+    /// Percent the limit of crypto stop losses is set past the stop price, as Alpaca has only
+    /// stop limit orders for crypto. A gap through the stop by more doesn't fill it.
+    #[serde(default = "default_crypto_stop_slippage")]
+    pub crypto_stop_slippage: Decimal,
+}
+
+fn default_crypto_stop_slippage() -> Decimal {
+    Decimal::ONE
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Paper {
+    /// Cash the simulated account starts with.
+    pub starting_cash: Decimal,
+}
+
+impl Default for Paper {
+    fn default() -> Self {
+        Self {
+            starting_cash: Decimal::from(100_000),
+        }
+    }
+}
+
+/// Bybit linear perpetuals account.
+#[cfg(feature = "bybit")]
+#[derive(Debug, Deserialize, Clone)]
+pub struct Bybit {
+    pub api_key: String,
+    pub api_secret: String,
+    #[serde(default = "default_bybit_base_url")]
+    pub base_url: String,
+    /// Coin contracts are settled in, only its positions and orders are managed.
+    #[serde(default = "default_bybit_settle_coin")]
+    pub settle_coin: String,
+    /// Leverage set on symbols before their first entry. Account setting is kept if not set.
+    pub leverage: Option<Decimal>,
+    /// Whether long and short positions of a symbol are held separately.
+    #[serde(default)]
+    pub hedge_mode: bool,
+}
+
+#[cfg(feature = "bybit")]
+fn default_bybit_base_url() -> String {
+    "https://api.bybit.com".to_owned()
+}
+
+#[cfg(feature = "bybit")]
+fn default_bybit_settle_coin() -> String {
+    "USDT".to_owned()
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -28,6 +142,13 @@ pub struct AppConfig {
     pub database: Database,
     pub brokers: Brokers,
     pub strategies: Vec<Strategy>,
+    #[serde(default)]
+    pub risk: RiskLimits,
+    #[serde(default)]
+    pub queue: QueueSettings,
+    /// Payload mappings of signal sources other than TradingView by their name.
+    #[serde(default)]
+    pub webhook_sources: BTreeMap<String, PayloadMapping>,
 }
 
 impl AppConfig {
@@ -39,10 +160,11 @@ impl AppConfig {
             .add_source(File::with_name(&format!("market/config/{}", run_mode)).required(false))
             .build()?;
 
-        println!("debug: {:?}", config.get_bool("debug"));
-        println!("database: {:?}", config.get::<String>("database.url"));
+        tracing::debug!("debug: {:?}", config.get_bool("debug"));
 
-        config.try_deserialize()
+        let config: Self = config.try_deserialize()?;
+        config.validate()?;
+        Ok(config)
     }
 
     pub fn build_for_test() -> Result<Self, ConfigError> {
@@ -53,9 +175,185 @@ impl AppConfig {
             .add_source(File::with_name(&format!("../market/config/{}", run_mode)).required(false))
             .build()?;
 
-        println!("debug: {:?}", config.get_bool("debug"));
-        println!("database: {:?}", config.get::<String>("database.url"));
+        tracing::debug!("debug: {:?}", config.get_bool("debug"));
+
+        let config: Self = config.try_deserialize()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what deserialization can't, e.g. that strategy ids are unique.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // Every worker holds a connection while processing a job and `Core` needs another one
+        // meanwhile, the API at least one more
+        let workers = self.queue.workers as u64;
+        if workers == 0 || 2 * workers >= u64::from(self.database.max_connections) {
+            return Err(ConfigError::Message(format!(
+                "Queue workers must be at least 1 and less than half of the {} database \
+                 connections",
+                self.database.max_connections
+            )));
+        }
+        let mut ids = HashSet::new();
+        let mut slugs = HashSet::new();
+        for strategy in &self.strategies {
+            if !ids.insert(strategy.id) {
+                return Err(ConfigError::Message(format!(
+                    "Strategy id {} is used more than once",
+                    strategy.id
+                )));
+            }
+            if strategy.webhook_passphrase.is_empty() {
+                return Err(ConfigError::Message(format!(
+                    "Strategy {} has an empty webhook passphrase",
+                    strategy.name
+                )));
+            }
+            if !strategy.order_retry_delay.is_finite() || strategy.order_retry_delay < 0.0 {
+                return Err(ConfigError::Message(format!(
+                    "Strategy {} has an invalid order retry delay",
+                    strategy.name
+                )));
+            }
+            for (limit, value) in [
+                ("maximum signal age", strategy.max_signal_age),
+                ("maximum future skew", strategy.max_future_skew),
+            ] {
+                if !value.is_finite() || value < 0.0 {
+                    return Err(ConfigError::Message(format!(
+                        "Strategy {} has an invalid {}",
+                        strategy.name, limit
+                    )));
+                }
+            }
+            if let Some(take_profit) = &strategy.take_profit {
+                take_profit.validate().map_err(|err| {
+                    ConfigError::Message(format!("Strategy {} has invalid {}", strategy.name, err))
+                })?;
+            }
+            if let Some(endpoint) = &strategy.endpoint {
+                self.validate_endpoint(strategy, endpoint)?;
+                if !slugs.insert(&endpoint.slug) {
+                    return Err(ConfigError::Message(format!(
+                        "Webhook endpoint slug {} is used more than once",
+                        endpoint.slug
+                    )));
+                }
+            }
+        }
+        for (name, mapping) in &self.webhook_sources {
+            mapping
+                .validate()
+                .map_err(|err| ConfigError::Message(format!("Webhook source {} {}", name, err)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Endpoint needs a token and a slug which doesn't clash with a webhook source, as both are
+    /// served at `/webhook/:name`.
+    pub fn validate_endpoint(
+        &self,
+        strategy: &Strategy,
+        endpoint: &WebhookEndpoint,
+    ) -> Result<(), ConfigError> {
+        if !WebhookEndpoint::is_valid_slug(&endpoint.slug) {
+            return Err(ConfigError::Message(format!(
+                "Strategy {} has an invalid webhook endpoint slug {}",
+                strategy.name, endpoint.slug
+            )));
+        }
+        if self.webhook_sources.contains_key(&endpoint.slug) {
+            return Err(ConfigError::Message(format!(
+                "Webhook endpoint slug {} of strategy {} is the name of a webhook source",
+                endpoint.slug, strategy.name
+            )));
+        }
+        if endpoint.token.is_empty() {
+            return Err(ConfigError::Message(format!(
+                "Strategy {} has an empty webhook endpoint token",
+                strategy.name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Changes between the running config and a reloaded one. Strategies are compared with the
+/// stored ones, which may have been changed through the API since the config was loaded. Only
+/// strategies, risk limits and webhook sources are applied on reload, broker accounts and the
+/// database are read at startup.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigDiff {
+    pub added_strategies: Vec<Uuid>,
+    pub changed_strategies: Vec<Uuid>,
+    pub removed_strategies: Vec<Uuid>,
+    pub risk_changed: bool,
+    pub webhook_sources_changed: bool,
+}
+
+impl ConfigDiff {
+    /// Strategies of the reloaded config are added if not `stored`, changed if they differ from
+    /// the stored ones, and those of the current config missing in it are removed.
+    pub fn new(current: &AppConfig, stored: &[Strategy], reloaded: &AppConfig) -> Self {
+        let mut diff = Self {
+            risk_changed: current.risk != reloaded.risk,
+            webhook_sources_changed: current.webhook_sources != reloaded.webhook_sources,
+            ..Default::default()
+        };
+
+        for strategy in &reloaded.strategies {
+            match stored.iter().find(|s| s.id == strategy.id) {
+                None => diff.added_strategies.push(strategy.id),
+                Some(stored) if stored != strategy => diff.changed_strategies.push(strategy.id),
+                Some(_) => {}
+            }
+        }
+        diff.removed_strategies = current
+            .strategies
+            .iter()
+            .filter(|strategy| !reloaded.strategies.iter().any(|s| s.id == strategy.id))
+            .filter(|strategy| stored.iter().any(|s| s.id == strategy.id))
+            .map(|strategy| strategy.id)
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_strategies.is_empty()
+            && self.changed_strategies.is_empty()
+            && self.removed_strategies.is_empty()
+            && !self.risk_changed
+            && !self.webhook_sources_changed
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let mut changes = vec![];
+        for (change, ids) in [
+            ("added", &self.added_strategies),
+            ("changed", &self.changed_strategies),
+            ("removed", &self.removed_strategies),
+        ] {
+            if !ids.is_empty() {
+                let ids: Vec<_> = ids.iter().map(Uuid::to_string).collect();
+                changes.push(format!("{} strategies {}", change, ids.join(", ")));
+            }
+        }
+        if self.risk_changed {
+            changes.push("changed risk limits".to_owned());
+        }
+        if self.webhook_sources_changed {
+            changes.push("changed webhook sources".to_owned());
+        }
 
-        config.try_deserialize()
+        write!(f, "{}", changes.join("; "))
     }
 }
@@ -0,0 +1,634 @@
+use std::{fmt, str::FromStr};
+
+use apca::{
+    api::v2::{
+        account as apca_account, account_activities as apca_activities, asset as apca_asset,
+        assets as apca_assets,
+        order::{self as apca_order, Patch},
+        orders as apca_orders, position as apca_position, positions as apca_positions,
+    },
+    ApiInfo, Client as ApcaClient, RequestError,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use num_decimal::Num;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{BrokerClient, BrokerClientError};
+use crate::{
+    api::{
+        alert::PositionSide,
+        objects::{
+            Account, ActivitiesRequest, Activity, Asset, AssetClass, Broker, Order, OrderRequest,
+            OrderSide, OrderStatus, OrderType, OrderUpdate, OrdersRequest, Position, TimeInForce,
+        },
+    },
+    app_config::Alpaca,
+    order::{EntryOrder, ExitKind, ExitOrder},
+    strategy::CurrencyType,
+    trade_signal::TradeSignal,
+};
+
+/// Alpaca account's client.
+pub struct AlpacaClient {
+    client: ApcaClient,
+    /// Percent the limit of crypto stop loss orders is set past their stop price.
+    crypto_stop_slippage: Decimal,
+}
+
+impl AlpacaClient {
+    pub fn new(config: &Alpaca) -> Result<Self, apca::Error> {
+        let api_info = ApiInfo::from_parts(
+            &config.apca_api_base_url,
+            &config.apca_api_key_id,
+            &config.apca_api_secret_key,
+        )?;
+
+        Ok(Self {
+            client: ApcaClient::new(api_info),
+            crypto_stop_slippage: config.crypto_stop_slippage,
+        })
+    }
+
+    /// Limit of a crypto stop loss, `crypto_stop_slippage` percent below a sell stop or above a
+    /// buy stop, so the stop still fills if the price gaps through it.
+    fn crypto_stop_limit(&self, side: OrderSide, stop_price: Decimal) -> Decimal {
+        let slippage = stop_price * self.crypto_stop_slippage / Decimal::ONE_HUNDRED;
+        match side {
+            OrderSide::Sell => stop_price - slippage,
+            OrderSide::Buy => stop_price + slippage,
+        }
+    }
+}
+
+/// Status code of the endpoint error's `UnexpectedStatus` variant, which every Alpaca endpoint
+/// error has, for `alpaca_error`.
+macro_rules! unexpected_status {
+    ($($error:ident)::+) => {
+        |err: &$($error)::+| match err {
+            $($error)::+::UnexpectedStatus(status, _) => Some(status.as_u16()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    };
+}
+
+#[axum::async_trait]
+impl BrokerClient for AlpacaClient {
+    async fn get_account(&self) -> Result<Account, BrokerClientError> {
+        let result = self.client.issue::<apca_account::Get>(&()).await;
+
+        result
+            .map(|account| alpaca_account(&account))
+            .map_err(|err| {
+                alpaca_error(
+                    err,
+                    unexpected_status!(apca_account::GetError),
+                    BrokerClientError::AlpacaError,
+                )
+            })
+    }
+
+    async fn get_activities(
+        &self,
+        activities_req: ActivitiesRequest,
+    ) -> Result<Vec<Activity>, BrokerClientError> {
+        let activities_req = apca_activities::ActivityReq {
+            // Types Alpaca doesn't know would make it reject the request
+            types: activities_req
+                .activity_types
+                .into_iter()
+                .filter_map(|activity_type| {
+                    serde_json::from_value(Value::String(activity_type)).ok()
+                })
+                .collect(),
+            after: activities_req.after,
+            until: activities_req.until,
+            ..Default::default()
+        };
+        let result = self
+            .client
+            .issue::<apca_activities::Get>(&activities_req)
+            .await;
+        result
+            .map(|activities| {
+                activities
+                    .into_iter()
+                    .map(|activity| alpaca_activity(&activity))
+                    .collect()
+            })
+            .map_err(|err| {
+                alpaca_error(
+                    err,
+                    unexpected_status!(apca_activities::GetError),
+                    BrokerClientError::AlpacaError,
+                )
+            })
+    }
+
+    async fn get_asset(&self, symbol: String) -> Result<Asset, BrokerClientError> {
+        let result = self
+            .client
+            .issue::<apca_asset::Get>(&apca_asset::Symbol::Sym(symbol))
+            .await;
+
+        result.map(|asset| alpaca_asset(&asset)).map_err(|err| {
+            alpaca_error(
+                err,
+                unexpected_status!(apca_asset::GetError),
+                BrokerClientError::AlpacaError,
+            )
+        })
+    }
+
+    async fn get_assets(&self, class: AssetClass) -> Result<Vec<Asset>, BrokerClientError> {
+        let asset_req = apca_assets::AssetsReq {
+            status: apca_asset::Status::Active,
+            class: class.into(),
+        };
+
+        let result = self.client.issue::<apca_assets::Get>(&asset_req).await;
+
+        result
+            .map(|assets| assets.iter().map(alpaca_asset).collect())
+            .map_err(|err| {
+                alpaca_error(
+                    err,
+                    unexpected_status!(apca_assets::GetError),
+                    BrokerClientError::AlpacaError,
+                )
+            })
+    }
+
+    async fn get_position(&self, symbol: String) -> Result<Position, BrokerClientError> {
+        let result = self
+            .client
+            .issue::<apca_position::Get>(&apca_asset::Symbol::Sym(symbol.clone()))
+            .await;
+        match result {
+            Ok(position) => Ok(alpaca_position(&position)),
+            Err(RequestError::Endpoint(apca_position::GetError::NotFound(_))) => {
+                Err(BrokerClientError::PositionNotFound(symbol))
+            }
+            Err(err) => Err(alpaca_error(
+                err,
+                unexpected_status!(apca_position::GetError),
+                BrokerClientError::AlpacaError,
+            )),
+        }
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, BrokerClientError> {
+        let result = self.client.issue::<apca_positions::Get>(&()).await;
+        result
+            .map(|positions| positions.iter().map(alpaca_position).collect())
+            .map_err(|err| {
+                alpaca_error(
+                    err,
+                    unexpected_status!(apca_positions::GetError),
+                    BrokerClientError::AlpacaError,
+                )
+            })
+    }
+
+    async fn delete_position(&self, symbol: String) -> Result<Order, BrokerClientError> {
+        let result = self
+            .client
+            .issue::<apca_position::Delete>(&apca_asset::Symbol::Sym(symbol.clone()))
+            .await;
+        match result {
+            Ok(order) => Ok(alpaca_order(&order)),
+            Err(RequestError::Endpoint(apca_position::DeleteError::NotFound(_))) => {
+                Err(BrokerClientError::PositionNotFound(symbol))
+            }
+            Err(err) => Err(alpaca_error(
+                err,
+                unexpected_status!(apca_position::DeleteError),
+                BrokerClientError::AlpacaError,
+            )),
+        }
+    }
+
+    async fn get_order_by_client_id(&self, client_id: String) -> Result<Order, BrokerClientError> {
+        let result = self
+            .client
+            .issue::<apca_order::GetByClientId>(&client_id)
+            .await;
+        match result {
+            Ok(order) => Ok(alpaca_order(&order)),
+            Err(RequestError::Endpoint(apca_order::GetByClientIdError::NotFound(_))) => {
+                Err(BrokerClientError::OrderNotFound(client_id))
+            }
+            Err(err) => Err(alpaca_error(
+                err,
+                unexpected_status!(apca_order::GetByClientIdError),
+                BrokerClientError::AlpacaError,
+            )),
+        }
+    }
+
+    async fn get_orders(&self, orders_req: OrdersRequest) -> Result<Vec<Order>, BrokerClientError> {
+        let orders_req = apca_orders::OrdersReq {
+            symbols: orders_req.symbol.into_iter().collect(),
+            status: if orders_req.open_only {
+                apca_orders::Status::Open
+            } else {
+                apca_orders::Status::All
+            },
+            nested: true,
+            ..Default::default()
+        };
+        let result = self.client.issue::<apca_orders::Get>(&orders_req).await;
+
+        result
+            .map(|orders| orders.iter().map(alpaca_order).collect())
+            .map_err(|err| {
+                alpaca_error(
+                    err,
+                    unexpected_status!(apca_orders::GetError),
+                    BrokerClientError::AlpacaError,
+                )
+            })
+    }
+
+    async fn create_order(&self, new_order_req: OrderRequest) -> Result<Order, BrokerClientError> {
+        let new_order_req = alpaca_order_request(new_order_req)?;
+        match self.client.issue::<apca_order::Post>(&new_order_req).await {
+            Ok(order) => Ok(alpaca_order(&order)),
+            Err(err) => Err(alpaca_error(
+                err,
+                unexpected_status!(apca_order::PostError),
+                BrokerClientError::OrderRejected,
+            )),
+        }
+    }
+
+    async fn update_order(
+        &self,
+        order_id: &str,
+        update_req: OrderUpdate,
+    ) -> Result<Order, BrokerClientError> {
+        let update_req = apca_order::ChangeReq {
+            stop_price: update_req.stop_price.map(to_num).transpose()?,
+            limit_price: update_req.limit_price.map(to_num).transpose()?,
+            client_order_id: update_req.client_order_id,
+            ..Default::default()
+        };
+        match self
+            .client
+            .issue::<Patch>(&(alpaca_order_id(order_id)?, update_req))
+            .await
+        {
+            Ok(order) => Ok(alpaca_order(&order)),
+            Err(err) => Err(alpaca_error(
+                err,
+                unexpected_status!(apca_order::PatchError),
+                BrokerClientError::OrderRejected,
+            )),
+        }
+    }
+
+    async fn delete_order(&self, order_id: &str) -> Result<(), BrokerClientError> {
+        let result = self
+            .client
+            .issue::<apca_order::Delete>(&alpaca_order_id(order_id)?)
+            .await;
+
+        result.map(|_| ()).map_err(|err| {
+            alpaca_error(
+                err,
+                unexpected_status!(apca_order::DeleteError),
+                BrokerClientError::AlpacaError,
+            )
+        })
+    }
+
+    fn new_order_request(
+        &self,
+        trade_signal: &TradeSignal,
+        entry: &EntryOrder,
+    ) -> Result<OrderRequest, BrokerClientError> {
+        let side = match trade_signal.signal_type.entry_side() {
+            Some(PositionSide::Long) => OrderSide::Buy,
+            Some(PositionSide::Short) => OrderSide::Sell,
+            None => {
+                return Err(BrokerClientError::InvalidOrderRequest(format!(
+                    "{} signal doesn't open a position",
+                    trade_signal.signal_type.as_ref()
+                )))
+            }
+        };
+
+        Ok(OrderRequest {
+            client_order_id: entry.client_order_id.clone(),
+            symbol: trade_signal.ticker.clone(),
+            side,
+            order_type: OrderType::Market,
+            quantity: entry.quantity,
+            limit_price: None,
+            stop_price: None,
+            time_in_force: alpaca_time_in_force(&trade_signal.strategy.currency_type),
+            stop_loss: entry.stop_loss,
+            take_profit: entry.take_profit,
+            reduce_only: false,
+        })
+    }
+
+    fn exit_order_request(&self, exit: &ExitOrder) -> Result<OrderRequest, BrokerClientError> {
+        let side = match exit.position_side {
+            PositionSide::Long => OrderSide::Sell,
+            PositionSide::Short => OrderSide::Buy,
+        };
+        let (order_type, limit_price, stop_price) = match (exit.kind, &exit.currency_type) {
+            // Crypto doesn't support plain stop orders
+            (ExitKind::StopLoss(price), CurrencyType::Crypto) => (
+                OrderType::StopLimit,
+                Some(self.crypto_stop_limit(side, price)),
+                Some(price),
+            ),
+            (ExitKind::StopLoss(price), CurrencyType::Stock) => {
+                (OrderType::Stop, None, Some(price))
+            }
+            (ExitKind::TakeProfit(price), _) => (OrderType::Limit, Some(price), None),
+            (ExitKind::Market, _) => (OrderType::Market, None, None),
+        };
+        let time_in_force = match exit.kind {
+            ExitKind::Market => alpaca_time_in_force(&exit.currency_type),
+            _ => TimeInForce::Gtc,
+        };
+
+        Ok(OrderRequest {
+            client_order_id: exit.client_order_id.clone(),
+            symbol: exit.ticker.clone(),
+            side,
+            order_type,
+            quantity: exit.quantity,
+            limit_price,
+            stop_price,
+            time_in_force,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: true,
+        })
+    }
+
+    // Fractional quantities are only accepted for simple orders
+    fn quantity_scale(&self, asset: &Asset, bracket: bool) -> u32 {
+        if !bracket && asset.fractionable {
+            ALPACA_FRACTIONAL_SCALE
+        } else {
+            0
+        }
+    }
+
+    // Alpaca supports bracket and OTO orders for equities only
+    fn supports_bracket_orders(&self, currency_type: &CurrencyType) -> bool {
+        *currency_type == CurrencyType::Stock
+    }
+
+    // Alpaca has no reduce only orders, a second exit would open an opposite position
+    fn supports_reduce_only(&self, _currency_type: &CurrencyType) -> bool {
+        false
+    }
+}
+
+/// Maximum decimal places of fractional quantities accepted by Alpaca.
+const ALPACA_FRACTIONAL_SCALE: u32 = 9;
+
+fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Maps a failed request. Transport errors, rate limits and 5xx responses are transient, other
+/// responses of the endpoint final and mapped by `rejected`.
+fn alpaca_error<E: fmt::Debug>(
+    err: RequestError<E>,
+    status: impl Fn(&E) -> Option<u16>,
+    rejected: impl FnOnce(String) -> BrokerClientError,
+) -> BrokerClientError {
+    match err {
+        RequestError::Endpoint(err) if status(&err).is_some_and(is_retryable_status) => {
+            BrokerClientError::Unavailable(format!("{err:?}"))
+        }
+        RequestError::Endpoint(err) => rejected(format!("{err:?}")),
+        err => BrokerClientError::Unavailable(format!("{err:?}")),
+    }
+}
+
+// Alpaca rejects `day` orders for crypto assets.
+fn alpaca_time_in_force(currency_type: &CurrencyType) -> TimeInForce {
+    match currency_type {
+        CurrencyType::Crypto => TimeInForce::Gtc,
+        CurrencyType::Stock => TimeInForce::Day,
+    }
+}
+
+fn alpaca_order_id(order_id: &str) -> Result<apca_order::Id, BrokerClientError> {
+    Uuid::parse_str(order_id)
+        .map(apca_order::Id)
+        .map_err(|err| BrokerClientError::InvalidOrderRequest(format!("{order_id}: {err}")))
+}
+
+// Alpaca has no reduce only flag, exits are sized to the position instead.
+fn alpaca_order_request(request: OrderRequest) -> Result<apca_order::OrderReq, BrokerClientError> {
+    let stop_loss = match request.stop_loss {
+        Some(price) => Some(apca_order::StopLoss::Stop(to_num(price)?)),
+        None => None,
+    };
+    let take_profit = match request.take_profit {
+        Some(price) => Some(apca_order::TakeProfit::Limit(to_num(price)?)),
+        None => None,
+    };
+    // Exits are triggered by the entry fill, both of them make a bracket
+    let class = match (&stop_loss, &take_profit) {
+        (Some(_), Some(_)) => apca_order::Class::Bracket,
+        (Some(_), None) | (None, Some(_)) => apca_order::Class::OneTriggersOther,
+        (None, None) => apca_order::Class::Simple,
+    };
+
+    Ok(apca_order::OrderReq {
+        symbol: apca_asset::Symbol::Sym(request.symbol),
+        amount: apca_order::Amount::quantity(to_num(request.quantity)?),
+        side: match request.side {
+            OrderSide::Buy => apca_order::Side::Buy,
+            OrderSide::Sell => apca_order::Side::Sell,
+        },
+        class,
+        type_: match request.order_type {
+            OrderType::Market => apca_order::Type::Market,
+            OrderType::Limit => apca_order::Type::Limit,
+            OrderType::Stop => apca_order::Type::Stop,
+            OrderType::StopLimit => apca_order::Type::StopLimit,
+            OrderType::TrailingStop => apca_order::Type::TrailingStop,
+        },
+        time_in_force: match request.time_in_force {
+            TimeInForce::Day => apca_order::TimeInForce::Day,
+            TimeInForce::Gtc => apca_order::TimeInForce::UntilCanceled,
+            TimeInForce::Ioc => apca_order::TimeInForce::ImmediateOrCancel,
+        },
+        limit_price: request.limit_price.map(to_num).transpose()?,
+        stop_price: request.stop_price.map(to_num).transpose()?,
+        take_profit,
+        stop_loss,
+        client_order_id: Some(request.client_order_id),
+        ..Default::default()
+    })
+}
+
+pub(crate) fn num_to_decimal(num: &Num) -> Option<Decimal> {
+    Decimal::from_str(&num.to_string()).ok()
+}
+
+fn to_num(value: Decimal) -> Result<Num, BrokerClientError> {
+    Num::from_str(&value.to_string())
+        .map_err(|err| BrokerClientError::InvalidOrderRequest(format!("{value}: {err}")))
+}
+
+fn alpaca_account(account: &apca_account::Account) -> Account {
+    Account {
+        broker: Broker::Alpaca,
+        currency: account.currency.clone(),
+        cash: num_to_decimal(&account.cash).unwrap_or_default(),
+        equity: num_to_decimal(&account.equity).unwrap_or_default(),
+        last_equity: num_to_decimal(&account.last_equity).unwrap_or_default(),
+        buying_power: num_to_decimal(&account.buying_power).unwrap_or_default(),
+        raw: serde_json::to_value(account).ok(),
+    }
+}
+
+// Trade and non trade activities have different shapes, common fields are read from the JSON.
+fn alpaca_activity(activity: &apca_activities::Activity) -> Activity {
+    let raw = serde_json::to_value(activity).unwrap_or_default();
+    let text = |key: &str| raw.get(key).and_then(Value::as_str).map(str::to_owned);
+    let decimal = |key: &str| text(key).and_then(|value| Decimal::from_str(&value).ok());
+
+    Activity {
+        broker: Broker::Alpaca,
+        activity_id: text("id").unwrap_or_default(),
+        activity_type: text("activity_type").unwrap_or_default(),
+        symbol: text("symbol"),
+        side: text("side").map(|side| match side.as_str() {
+            "buy" => OrderSide::Buy,
+            _ => OrderSide::Sell,
+        }),
+        quantity: decimal("qty"),
+        price: decimal("price"),
+        net_amount: decimal("net_amount"),
+        time: text("transaction_time")
+            .and_then(|time| time.parse::<DateTime<Utc>>().ok())
+            .or_else(|| {
+                text("date")
+                    .and_then(|date| date.parse::<NaiveDate>().ok())
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|time| time.and_utc())
+            }),
+        raw: Some(raw),
+    }
+}
+
+fn alpaca_asset(asset: &apca_asset::Asset) -> Asset {
+    Asset {
+        broker: Broker::Alpaca,
+        symbol: asset.symbol.clone(),
+        class: match serde_name(&asset.class).as_str() {
+            "crypto" => AssetClass::Crypto,
+            _ => AssetClass::UsEquity,
+        },
+        tradable: asset.tradable,
+        fractionable: asset.fractionable,
+        quantity_increment: None,
+        min_order_quantity: None,
+        raw: serde_json::to_value(asset).ok(),
+    }
+}
+
+fn alpaca_position(position: &apca_position::Position) -> Position {
+    let side = match position.side {
+        apca_position::Side::Long => PositionSide::Long,
+        apca_position::Side::Short => PositionSide::Short,
+    };
+    let market_value = position
+        .market_value
+        .as_ref()
+        .and_then(num_to_decimal)
+        .unwrap_or_default()
+        .abs();
+
+    Position {
+        broker: Broker::Alpaca,
+        symbol: position.symbol.clone(),
+        side,
+        quantity: num_to_decimal(&position.quantity).unwrap_or_default().abs(),
+        average_entry_price: num_to_decimal(&position.average_entry_price).unwrap_or_default(),
+        current_price: position.current_price.as_ref().and_then(num_to_decimal),
+        market_value: match side {
+            PositionSide::Long => market_value,
+            PositionSide::Short => -market_value,
+        },
+        unrealized_intraday_pl: position
+            .unrealized_gain_today
+            .as_ref()
+            .and_then(num_to_decimal)
+            .unwrap_or_default(),
+        raw: serde_json::to_value(position).ok(),
+    }
+}
+
+fn alpaca_order(order: &apca_order::Order) -> Order {
+    Order {
+        broker: Broker::Alpaca,
+        broker_order_id: order.id.0.to_string(),
+        client_order_id: order.client_order_id.clone(),
+        symbol: order.symbol.clone(),
+        side: match order.side {
+            apca_order::Side::Buy => OrderSide::Buy,
+            apca_order::Side::Sell => OrderSide::Sell,
+        },
+        order_type: match serde_name(&order.type_).as_str() {
+            "limit" => OrderType::Limit,
+            "stop" => OrderType::Stop,
+            "stop_limit" => OrderType::StopLimit,
+            "trailing_stop" => OrderType::TrailingStop,
+            _ => OrderType::Market,
+        },
+        quantity: match &order.amount {
+            apca_order::Amount::Quantity { quantity } => num_to_decimal(quantity),
+            apca_order::Amount::Notional { .. } => None,
+        },
+        limit_price: order.limit_price.as_ref().and_then(num_to_decimal),
+        stop_price: order.stop_price.as_ref().and_then(num_to_decimal),
+        status: alpaca_order_status(&order.status),
+        filled_quantity: num_to_decimal(&order.filled_quantity).unwrap_or_default(),
+        average_fill_price: order.average_fill_price.as_ref().and_then(num_to_decimal),
+        created_at: Some(order.created_at),
+        updated_at: order.updated_at,
+        filled_at: order.filled_at,
+        legs: order.legs.iter().map(alpaca_order).collect(),
+        raw: serde_json::to_value(order).ok(),
+    }
+}
+
+// Statuses of orders still working at Alpaca, e.g. `accepted` or `done_for_day`, map to `New`.
+fn alpaca_order_status(status: &apca_order::Status) -> OrderStatus {
+    match serde_name(status).as_str() {
+        "held" => OrderStatus::Held,
+        "partially_filled" => OrderStatus::PartiallyFilled,
+        "filled" => OrderStatus::Filled,
+        "pending_cancel" => OrderStatus::PendingCancel,
+        "canceled" => OrderStatus::Canceled,
+        "expired" => OrderStatus::Expired,
+        "replaced" => OrderStatus::Replaced,
+        "rejected" => OrderStatus::Rejected,
+        _ => OrderStatus::New,
+    }
+}
+
+// Name of the enum variant as Alpaca serializes it, e.g. `partially_filled`.
+fn serde_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_owned))
+        .unwrap_or_default()
+}
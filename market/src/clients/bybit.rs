@@ -0,0 +1,847 @@
+use std::{collections::HashSet, fmt::Debug, str::FromStr, sync::Mutex};
+
+use chrono::{TimeZone, Utc};
+use crypto_botters::{
+    bybit::{BybitHandleError, BybitHttpAuth, BybitHttpUrl, BybitOption},
+    generic_api_client::http::RequestError,
+    Client,
+};
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{BrokerClient, BrokerClientError};
+use crate::{
+    api::{
+        alert::PositionSide,
+        objects::{
+            Account, ActivitiesRequest, Activity, Asset, AssetClass, Broker, Order, OrderRequest,
+            OrderSide, OrderStatus, OrderType, OrderUpdate, OrdersRequest, Position, TimeInForce,
+        },
+    },
+    app_config::Bybit as BybitConfig,
+    order::{EntryOrder, ExitKind, ExitOrder},
+    strategy::CurrencyType,
+    trade_signal::TradeSignal,
+};
+
+/// Only USDT settled linear perpetuals are traded.
+const CATEGORY: &str = "linear";
+/// Return codes of settings requests which didn't change anything because the setting was
+/// already in place.
+const NOT_MODIFIED_CODES: [i64; 2] = [110025, 110043];
+/// Return codes of rate limits and temporary server side failures.
+const RETRYABLE_CODES: [i64; 3] = [10000, 10006, 10016];
+
+/// Client of the Bybit v5 REST API for linear perpetual contracts.
+pub struct BybitClient {
+    client: Client,
+    base_url: String,
+    settle_coin: String,
+    leverage: Option<Decimal>,
+    hedge_mode: bool,
+    /// Symbols whose position mode and leverage were already set.
+    prepared_symbols: Mutex<HashSet<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BybitSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BybitOrderType {
+    Market,
+    Limit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitPosition {
+    pub symbol: String,
+    /// `Buy` for long, `Sell` for short, empty when there is no position.
+    pub side: String,
+    pub size: String,
+    pub avg_price: String,
+    pub mark_price: String,
+    pub unrealised_pnl: String,
+    pub leverage: String,
+    pub position_idx: u8,
+    #[serde(default)]
+    pub created_time: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrder {
+    pub order_id: String,
+    pub order_link_id: String,
+    pub symbol: String,
+    pub side: BybitSide,
+    pub order_type: BybitOrderType,
+    pub price: String,
+    pub qty: String,
+    pub trigger_price: String,
+    pub order_status: String,
+    pub cum_exec_qty: String,
+    pub avg_price: String,
+    pub reduce_only: bool,
+    pub updated_time: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitInstrument {
+    pub symbol: String,
+    pub lot_size_filter: BybitLotSizeFilter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitLotSizeFilter {
+    pub qty_step: String,
+    pub min_order_qty: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrderRequest {
+    pub symbol: String,
+    pub side: BybitSide,
+    pub order_type: BybitOrderType,
+    pub qty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+    /// Makes a conditional order, e.g. a stop.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<String>,
+    /// 1 triggers when the price rises to the trigger price, 2 when it falls to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_direction: Option<u8>,
+    pub time_in_force: String,
+    pub position_idx: u8,
+    pub order_link_id: String,
+    pub reduce_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitResponse<T> {
+    result: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct BybitList<T> {
+    list: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitWallet {
+    total_equity: String,
+    #[serde(default)]
+    total_wallet_balance: String,
+    total_available_balance: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitClosedPnl {
+    closed_pnl: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitOrderId {
+    order_id: String,
+}
+
+impl BybitClient {
+    pub fn new(config: &BybitConfig) -> Self {
+        let mut client = Client::new();
+        client.update_default_option(BybitOption::Key(config.api_key.clone()));
+        client.update_default_option(BybitOption::Secret(config.api_secret.clone()));
+        client.update_default_option(BybitOption::HttpAuth(BybitHttpAuth::V3AndAbove));
+        // Requests are made to full URLs so the API can be swapped for a local stand-in
+        client.update_default_option(BybitOption::HttpUrl(BybitHttpUrl::None));
+
+        Self {
+            client,
+            base_url: config.base_url.trim_end_matches('/').to_owned(),
+            settle_coin: config.settle_coin.clone(),
+            leverage: config.leverage,
+            hedge_mode: config.hedge_mode,
+            prepared_symbols: Mutex::new(HashSet::new()),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, BrokerClientError> {
+        let url = format!("{}{}", self.base_url, path);
+        let response: BybitResponse<T> = self
+            .client
+            .get(&url, Some(&query), [BybitOption::Default])
+            .await
+            .map_err(bybit_error)?;
+        Ok(response.result)
+    }
+
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Value,
+    ) -> Result<T, BrokerClientError> {
+        let url = format!("{}{}", self.base_url, path);
+        let response: BybitResponse<T> = self
+            .client
+            .post(&url, Some(body), [BybitOption::Default])
+            .await
+            .map_err(bybit_error)?;
+        Ok(response.result)
+    }
+
+    // Settings requests fail when there is nothing to change, which is fine
+    async fn post_setting(&self, path: &str, body: Value) -> Result<(), BrokerClientError> {
+        let url = format!("{}{}", self.base_url, path);
+        let result: Result<Value, _> = self
+            .client
+            .post(&url, Some(body), [BybitOption::Default])
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(RequestError::ResponseHandleError(BybitHandleError::ApiError(value)))
+                if NOT_MODIFIED_CODES.contains(&ret_code(&value)) =>
+            {
+                Ok(())
+            }
+            Err(err) => Err(bybit_error(err)),
+        }
+    }
+
+    /// Sets position mode and leverage of the symbol before its first entry.
+    async fn prepare_symbol(&self, symbol: &str) -> Result<(), BrokerClientError> {
+        if self.prepared_symbols.lock().unwrap().contains(symbol) {
+            return Ok(());
+        }
+
+        // 0 is one-way mode, 3 is hedge mode with separate long and short positions
+        let mode = if self.hedge_mode { 3 } else { 0 };
+        self.post_setting(
+            "/v5/position/switch-mode",
+            json!({ "category": CATEGORY, "symbol": symbol, "mode": mode }),
+        )
+        .await?;
+        if let Some(leverage) = self.leverage {
+            self.post_setting(
+                "/v5/position/set-leverage",
+                json!({
+                    "category": CATEGORY,
+                    "symbol": symbol,
+                    "buyLeverage": leverage.to_string(),
+                    "sellLeverage": leverage.to_string(),
+                }),
+            )
+            .await?;
+        }
+
+        self.prepared_symbols
+            .lock()
+            .unwrap()
+            .insert(symbol.to_owned());
+        Ok(())
+    }
+
+    /// Position index orders of the side are placed with. In hedge mode 1 is the long and 2 the
+    /// short position, in one-way mode there is only position 0.
+    fn position_idx(&self, position_side: PositionSide) -> u8 {
+        match (self.hedge_mode, position_side) {
+            (false, _) => 0,
+            (true, PositionSide::Long) => 1,
+            (true, PositionSide::Short) => 2,
+        }
+    }
+
+    /// Maps the order onto Bybit's. Stops are conditional orders triggered when the price
+    /// crosses the stop price in the direction of the stop.
+    fn bybit_order_request(
+        &self,
+        request: OrderRequest,
+    ) -> Result<BybitOrderRequest, BrokerClientError> {
+        let (order_type, conditional) = match request.order_type {
+            OrderType::Market => (BybitOrderType::Market, false),
+            OrderType::Limit => (BybitOrderType::Limit, false),
+            OrderType::Stop => (BybitOrderType::Market, true),
+            OrderType::StopLimit => (BybitOrderType::Limit, true),
+            OrderType::TrailingStop => {
+                return Err(BrokerClientError::InvalidOrderRequest(
+                    "Trailing stop orders are not supported".to_owned(),
+                ))
+            }
+        };
+        // Reduce only orders close the position on the opposite side
+        let position_side = match (request.side, request.reduce_only) {
+            (OrderSide::Buy, false) | (OrderSide::Sell, true) => PositionSide::Long,
+            (OrderSide::Sell, false) | (OrderSide::Buy, true) => PositionSide::Short,
+        };
+
+        Ok(BybitOrderRequest {
+            symbol: request.symbol,
+            side: match request.side {
+                OrderSide::Buy => BybitSide::Buy,
+                OrderSide::Sell => BybitSide::Sell,
+            },
+            order_type,
+            qty: request.quantity.to_string(),
+            price: request.limit_price.map(|price| price.to_string()),
+            trigger_price: request
+                .stop_price
+                .filter(|_| conditional)
+                .map(|price| price.to_string()),
+            // Sell stops trigger when the price falls to them, buy stops when it rises
+            trigger_direction: conditional.then_some(match request.side {
+                OrderSide::Buy => 1,
+                OrderSide::Sell => 2,
+            }),
+            time_in_force: match request.time_in_force {
+                TimeInForce::Day | TimeInForce::Gtc => "GTC",
+                TimeInForce::Ioc => "IOC",
+            }
+            .to_owned(),
+            position_idx: self.position_idx(position_side),
+            order_link_id: request.client_order_id,
+            reduce_only: request.reduce_only,
+        })
+    }
+
+    async fn find_orders(
+        &self,
+        query: &[(&str, String)],
+    ) -> Result<Vec<BybitOrder>, BrokerClientError> {
+        let mut query = query.to_vec();
+        query.push(("category", CATEGORY.to_owned()));
+        query.push(("settleCoin", self.settle_coin.clone()));
+
+        let open: BybitList<BybitOrder> = self.get("/v5/order/realtime", &query).await?;
+        if !open.list.is_empty() {
+            return Ok(open.list);
+        }
+        let history: BybitList<BybitOrder> = self.get("/v5/order/history", &query).await?;
+        Ok(history.list)
+    }
+
+    async fn find_order(&self, query: &[(&str, String)]) -> Result<BybitOrder, BrokerClientError> {
+        self.find_orders(query)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| BrokerClientError::OrderNotFound(format!("{query:?}")))
+    }
+
+    async fn open_positions(&self) -> Result<Vec<BybitPosition>, BrokerClientError> {
+        let positions: BybitList<BybitPosition> = self
+            .get(
+                "/v5/position/list",
+                &[
+                    ("category", CATEGORY.to_owned()),
+                    ("settleCoin", self.settle_coin.clone()),
+                ],
+            )
+            .await?;
+
+        Ok(positions
+            .list
+            .into_iter()
+            .filter(|position| decimal(&position.size).unwrap_or_default() > Decimal::ZERO)
+            .collect())
+    }
+
+    /// Open position in the symbol, on the given side if set. In hedge mode the symbol may have
+    /// a long and a short position, which needs the side to tell apart.
+    async fn find_position(
+        &self,
+        symbol: &str,
+        side: Option<PositionSide>,
+    ) -> Result<BybitPosition, BrokerClientError> {
+        let mut positions: Vec<BybitPosition> = self
+            .open_positions()
+            .await?
+            .into_iter()
+            .filter(|position| position.symbol == symbol)
+            .filter(|position| {
+                side.map_or(true, |side| {
+                    position.side() == side && position.position_idx == self.position_idx(side)
+                })
+            })
+            .collect();
+
+        match positions.len() {
+            0 => Err(BrokerClientError::PositionNotFound(symbol.to_owned())),
+            1 => Ok(positions.remove(0)),
+            _ => Err(BrokerClientError::OrderRejected(format!(
+                "Long and short positions for {symbol}, side needed"
+            ))),
+        }
+    }
+
+    /// Closes the position with a reduce only market order. Its id is derived from the position,
+    /// so a retried close resolves to the order placed before instead of closing twice.
+    async fn close_position(&self, position: BybitPosition) -> Result<Order, BrokerClientError> {
+        let client_order_id = Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!(
+                "{}/{}/{}/{}/close",
+                position.symbol, position.position_idx, position.created_time, position.size
+            )
+            .as_bytes(),
+        )
+        .to_string();
+        let request = OrderRequest {
+            client_order_id: client_order_id.clone(),
+            symbol: position.symbol.clone(),
+            side: match position.side() {
+                PositionSide::Long => OrderSide::Sell,
+                PositionSide::Short => OrderSide::Buy,
+            },
+            order_type: OrderType::Market,
+            quantity: position.size(),
+            limit_price: None,
+            stop_price: None,
+            time_in_force: TimeInForce::Ioc,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: true,
+        };
+
+        match self.create_order(request).await {
+            Ok(order) => Ok(order),
+            // Rejected as a duplicate if the earlier close went through
+            Err(err) => self
+                .get_order_by_client_id(client_order_id)
+                .await
+                .map_err(|_| err),
+        }
+    }
+}
+
+impl BybitPosition {
+    pub fn side(&self) -> PositionSide {
+        match self.side.as_str() {
+            "Sell" => PositionSide::Short,
+            _ => PositionSide::Long,
+        }
+    }
+
+    pub fn size(&self) -> Decimal {
+        decimal(&self.size).unwrap_or_default()
+    }
+
+    pub fn unrealised_pnl(&self) -> Decimal {
+        decimal(&self.unrealised_pnl).unwrap_or_default()
+    }
+
+    pub fn to_position(&self) -> Position {
+        let side = self.side();
+        let market_value = self.size() * decimal(&self.mark_price).unwrap_or_default();
+
+        Position {
+            broker: Broker::Bybit,
+            symbol: self.symbol.clone(),
+            side,
+            quantity: self.size(),
+            average_entry_price: decimal(&self.avg_price).unwrap_or_default(),
+            current_price: decimal(&self.mark_price),
+            market_value: match side {
+                PositionSide::Long => market_value,
+                PositionSide::Short => -market_value,
+            },
+            // Bybit reports open PnL since entry only
+            unrealized_intraday_pl: self.unrealised_pnl(),
+            raw: serde_json::to_value(self).ok(),
+        }
+    }
+}
+
+impl BybitInstrument {
+    pub fn to_asset(&self) -> Asset {
+        let quantity_increment =
+            decimal(&self.lot_size_filter.qty_step).map(|step| step.normalize());
+
+        Asset {
+            broker: Broker::Bybit,
+            symbol: self.symbol.clone(),
+            class: AssetClass::Crypto,
+            tradable: true,
+            fractionable: quantity_increment.is_some_and(|step| step.scale() > 0),
+            quantity_increment,
+            min_order_quantity: decimal(&self.lot_size_filter.min_order_qty),
+            raw: serde_json::to_value(self).ok(),
+        }
+    }
+}
+
+impl BybitOrder {
+    pub fn to_order(&self) -> Order {
+        let conditional = !self.trigger_price.is_empty() && self.trigger_price != "0";
+        let order_type = match (self.order_type, conditional) {
+            (BybitOrderType::Market, false) => OrderType::Market,
+            (BybitOrderType::Limit, false) => OrderType::Limit,
+            (BybitOrderType::Market, true) => OrderType::Stop,
+            (BybitOrderType::Limit, true) => OrderType::StopLimit,
+        };
+        let status = match self.order_status.as_str() {
+            "PartiallyFilled" => OrderStatus::PartiallyFilled,
+            "Filled" => OrderStatus::Filled,
+            "Rejected" => OrderStatus::Rejected,
+            "Cancelled" | "Deactivated" | "PartiallyFilledCanceled" => OrderStatus::Canceled,
+            // Created, New, Untriggered and Triggered orders are still working
+            _ => OrderStatus::New,
+        };
+        let updated_at = self
+            .updated_time
+            .parse::<i64>()
+            .ok()
+            .and_then(|millis| Utc.timestamp_millis_opt(millis).single());
+
+        Order {
+            broker: Broker::Bybit,
+            broker_order_id: self.order_id.clone(),
+            client_order_id: self.order_link_id.clone(),
+            symbol: self.symbol.clone(),
+            side: match self.side {
+                BybitSide::Buy => OrderSide::Buy,
+                BybitSide::Sell => OrderSide::Sell,
+            },
+            order_type,
+            quantity: decimal(&self.qty),
+            limit_price: decimal(&self.price).filter(|_| self.order_type == BybitOrderType::Limit),
+            stop_price: decimal(&self.trigger_price).filter(|_| conditional),
+            status,
+            filled_quantity: decimal(&self.cum_exec_qty).unwrap_or_default(),
+            average_fill_price: decimal(&self.avg_price).filter(|price| !price.is_zero()),
+            created_at: None,
+            updated_at,
+            filled_at: updated_at.filter(|_| status == OrderStatus::Filled),
+            legs: Vec::new(),
+            raw: serde_json::to_value(self).ok(),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl BrokerClient for BybitClient {
+    async fn get_account(&self) -> Result<Account, BrokerClientError> {
+        let wallets: BybitList<BybitWallet> = self
+            .get(
+                "/v5/account/wallet-balance",
+                &[("accountType", "UNIFIED".to_owned())],
+            )
+            .await?;
+        let wallet = wallets.list.into_iter().next().ok_or_else(|| {
+            BrokerClientError::Unavailable("Unified wallet not returned".to_owned())
+        })?;
+
+        let start_of_day = Utc::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc();
+        let closed: BybitList<BybitClosedPnl> = self
+            .get(
+                "/v5/position/closed-pnl",
+                &[
+                    ("category", CATEGORY.to_owned()),
+                    ("startTime", start_of_day.timestamp_millis().to_string()),
+                ],
+            )
+            .await?;
+        let positions = self.open_positions().await?;
+
+        let equity = decimal(&wallet.total_equity).unwrap_or_default();
+        let realized_pnl_today: Decimal = closed
+            .list
+            .iter()
+            .filter_map(|pnl| decimal(&pnl.closed_pnl))
+            .sum();
+        let unrealized_pnl: Decimal = positions.iter().map(BybitPosition::unrealised_pnl).sum();
+
+        Ok(Account {
+            broker: Broker::Bybit,
+            currency: self.settle_coin.clone(),
+            cash: decimal(&wallet.total_wallet_balance).unwrap_or(equity - unrealized_pnl),
+            equity,
+            // Not reported by Bybit, derived from today's realized and open PnL
+            last_equity: equity - realized_pnl_today - unrealized_pnl,
+            buying_power: decimal(&wallet.total_available_balance).unwrap_or_default(),
+            raw: serde_json::to_value(&wallet).ok(),
+        })
+    }
+
+    async fn get_activities(
+        &self,
+        _activities_req: ActivitiesRequest,
+    ) -> Result<Vec<Activity>, BrokerClientError> {
+        Ok(Vec::new())
+    }
+
+    async fn get_asset(&self, symbol: String) -> Result<Asset, BrokerClientError> {
+        let instruments: BybitList<BybitInstrument> = self
+            .get(
+                "/v5/market/instruments-info",
+                &[
+                    ("category", CATEGORY.to_owned()),
+                    ("symbol", symbol.clone()),
+                ],
+            )
+            .await?;
+
+        instruments
+            .list
+            .into_iter()
+            .next()
+            .map(|instrument| instrument.to_asset())
+            .ok_or_else(|| BrokerClientError::OrderRejected(format!("Unknown symbol {symbol}")))
+    }
+
+    // Linear perpetuals are the only class traded on Bybit
+    async fn get_assets(&self, _class: AssetClass) -> Result<Vec<Asset>, BrokerClientError> {
+        let instruments: BybitList<BybitInstrument> = self
+            .get(
+                "/v5/market/instruments-info",
+                &[("category", CATEGORY.to_owned())],
+            )
+            .await?;
+        Ok(instruments
+            .list
+            .iter()
+            .map(BybitInstrument::to_asset)
+            .collect())
+    }
+
+    async fn get_position(&self, symbol: String) -> Result<Position, BrokerClientError> {
+        self.find_position(&symbol, None)
+            .await
+            .map(|position| position.to_position())
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, BrokerClientError> {
+        Ok(self
+            .open_positions()
+            .await?
+            .iter()
+            .map(BybitPosition::to_position)
+            .collect())
+    }
+
+    async fn delete_position(&self, symbol: String) -> Result<Order, BrokerClientError> {
+        let position = self.find_position(&symbol, None).await?;
+        self.close_position(position).await
+    }
+
+    async fn get_side_position(
+        &self,
+        symbol: String,
+        side: PositionSide,
+    ) -> Result<Position, BrokerClientError> {
+        self.find_position(&symbol, Some(side))
+            .await
+            .map(|position| position.to_position())
+    }
+
+    async fn delete_side_position(
+        &self,
+        symbol: String,
+        side: PositionSide,
+    ) -> Result<Order, BrokerClientError> {
+        let position = self.find_position(&symbol, Some(side)).await?;
+        self.close_position(position).await
+    }
+
+    async fn get_order_by_client_id(&self, client_id: String) -> Result<Order, BrokerClientError> {
+        self.find_order(&[("orderLinkId", client_id)])
+            .await
+            .map(|order| order.to_order())
+    }
+
+    async fn get_orders(&self, orders_req: OrdersRequest) -> Result<Vec<Order>, BrokerClientError> {
+        let mut query = vec![
+            ("category", CATEGORY.to_owned()),
+            ("settleCoin", self.settle_coin.clone()),
+        ];
+        if let Some(symbol) = orders_req.symbol {
+            query.push(("symbol", symbol));
+        }
+        let path = if orders_req.open_only {
+            "/v5/order/realtime"
+        } else {
+            "/v5/order/history"
+        };
+
+        let orders: BybitList<BybitOrder> = self.get(path, &query).await?;
+        Ok(orders.list.iter().map(BybitOrder::to_order).collect())
+    }
+
+    async fn create_order(&self, new_order_req: OrderRequest) -> Result<Order, BrokerClientError> {
+        let new_order_req = self.bybit_order_request(new_order_req)?;
+        if !new_order_req.reduce_only {
+            self.prepare_symbol(&new_order_req.symbol).await?;
+        }
+
+        let mut body = serde_json::to_value(&new_order_req)
+            .map_err(|err| BrokerClientError::InvalidOrderRequest(err.to_string()))?;
+        body["category"] = json!(CATEGORY);
+        let created: BybitOrderId = self.post("/v5/order/create", body).await?;
+
+        self.find_order(&[("orderId", created.order_id)])
+            .await
+            .map(|order| order.to_order())
+    }
+
+    async fn update_order(
+        &self,
+        order_id: &str,
+        update_req: OrderUpdate,
+    ) -> Result<Order, BrokerClientError> {
+        // Amending requires the symbol of the order
+        let order = self.find_order(&[("orderId", order_id.to_owned())]).await?;
+        let mut body = json!({
+            "category": CATEGORY,
+            "symbol": order.symbol,
+            "orderId": order.order_id,
+        });
+        if let Some(stop_price) = update_req.stop_price {
+            body["triggerPrice"] = json!(stop_price.to_string());
+        }
+        if let Some(limit_price) = update_req.limit_price {
+            body["price"] = json!(limit_price.to_string());
+        }
+        let amended: BybitOrderId = self.post("/v5/order/amend", body).await?;
+
+        self.find_order(&[("orderId", amended.order_id)])
+            .await
+            .map(|order| order.to_order())
+    }
+
+    async fn delete_order(&self, order_id: &str) -> Result<(), BrokerClientError> {
+        let order = self.find_order(&[("orderId", order_id.to_owned())]).await?;
+        let _: BybitOrderId = self
+            .post(
+                "/v5/order/cancel",
+                json!({
+                    "category": CATEGORY,
+                    "symbol": order.symbol,
+                    "orderId": order.order_id,
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn new_order_request(
+        &self,
+        trade_signal: &TradeSignal,
+        entry: &EntryOrder,
+    ) -> Result<OrderRequest, BrokerClientError> {
+        let side = match trade_signal.signal_type.entry_side() {
+            Some(PositionSide::Long) => OrderSide::Buy,
+            Some(PositionSide::Short) => OrderSide::Sell,
+            None => {
+                return Err(BrokerClientError::InvalidOrderRequest(format!(
+                    "{} signal doesn't open a position",
+                    trade_signal.signal_type.as_ref()
+                )))
+            }
+        };
+
+        Ok(OrderRequest {
+            client_order_id: entry.client_order_id.clone(),
+            symbol: trade_signal.ticker.clone(),
+            side,
+            order_type: OrderType::Market,
+            quantity: entry.quantity,
+            limit_price: None,
+            stop_price: None,
+            time_in_force: TimeInForce::Ioc,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: false,
+        })
+    }
+
+    fn exit_order_request(&self, exit: &ExitOrder) -> Result<OrderRequest, BrokerClientError> {
+        let side = match exit.position_side {
+            PositionSide::Long => OrderSide::Sell,
+            PositionSide::Short => OrderSide::Buy,
+        };
+        let (order_type, limit_price, stop_price) = match exit.kind {
+            ExitKind::StopLoss(price) => (OrderType::Stop, None, Some(price)),
+            ExitKind::TakeProfit(price) => (OrderType::Limit, Some(price), None),
+            ExitKind::Market => (OrderType::Market, None, None),
+        };
+        // Market exits are immediate or cancel like entries
+        let time_in_force = match exit.kind {
+            ExitKind::Market => TimeInForce::Ioc,
+            _ => TimeInForce::Gtc,
+        };
+
+        Ok(OrderRequest {
+            client_order_id: exit.client_order_id.clone(),
+            symbol: exit.ticker.clone(),
+            side,
+            order_type,
+            quantity: exit.quantity,
+            limit_price,
+            stop_price,
+            time_in_force,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: true,
+        })
+    }
+
+    fn quantity_scale(&self, asset: &Asset, _bracket: bool) -> u32 {
+        asset
+            .quantity_increment
+            .map(|step| step.normalize().scale())
+            .unwrap_or_default()
+    }
+
+    // Position level take profit and stop loss are not separate orders which could be tracked
+    fn supports_bracket_orders(&self, _currency_type: &CurrencyType) -> bool {
+        false
+    }
+
+    fn supports_reduce_only(&self, _currency_type: &CurrencyType) -> bool {
+        true
+    }
+}
+
+fn bybit_error<E: Debug>(err: RequestError<E, BybitHandleError>) -> BrokerClientError {
+    match err {
+        RequestError::ResponseHandleError(BybitHandleError::ApiError(value))
+            if RETRYABLE_CODES.contains(&ret_code(&value)) =>
+        {
+            BrokerClientError::Unavailable(value.to_string())
+        }
+        RequestError::ResponseHandleError(BybitHandleError::ApiError(value)) => {
+            BrokerClientError::OrderRejected(value.to_string())
+        }
+        RequestError::SendRequest(err) | RequestError::ReceiveResponse(err) => {
+            BrokerClientError::Unavailable(err.to_string())
+        }
+        err => BrokerClientError::BybitError(format!("{err:?}")),
+    }
+}
+
+fn ret_code(value: &Value) -> i64 {
+    value["retCode"].as_i64().unwrap_or_default()
+}
+
+// Bybit sends numbers as strings, empty when not applicable
+fn decimal(value: &str) -> Option<Decimal> {
+    Decimal::from_str(value).ok()
+}
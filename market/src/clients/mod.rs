@@ -0,0 +1,195 @@
+mod alpaca;
+#[cfg(feature = "bybit")]
+pub mod bybit;
+pub mod paper;
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use anyhow::Result;
+use thiserror::Error as ThisError;
+
+pub(crate) use alpaca::num_to_decimal;
+pub use alpaca::AlpacaClient;
+#[cfg(feature = "bybit")]
+pub use bybit::BybitClient;
+pub use paper::PaperBroker;
+
+use crate::{
+    api::{
+        alert::{BarData, PositionSide},
+        objects::{
+            Account, ActivitiesRequest, Activity, Asset, AssetClass, Broker, Order, OrderRequest,
+            OrderUpdate, OrdersRequest, Position,
+        },
+    },
+    order::{EntryOrder, ExitOrder},
+    strategy::CurrencyType,
+    trade_signal::TradeSignal,
+};
+
+/// Account name of brokers configured with a single set of credentials.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Broker account a client trades on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BrokerAccount {
+    pub broker: Broker,
+    pub account: String,
+}
+
+impl BrokerAccount {
+    pub fn new(broker: Broker, account: &str) -> Self {
+        Self {
+            broker,
+            account: account.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for BrokerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.broker.as_ref(), self.account)
+    }
+}
+
+/// Clients of all configured broker accounts, built from config at startup.
+#[derive(Default)]
+pub struct BrokerRegistry {
+    clients: HashMap<BrokerAccount, Arc<dyn BrokerClient>>,
+}
+
+impl BrokerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, broker: Broker, account: &str, client: Arc<dyn BrokerClient>) {
+        self.clients
+            .insert(BrokerAccount::new(broker, account), client);
+    }
+
+    pub fn get(
+        &self,
+        broker: &Broker,
+        account: &str,
+    ) -> Result<Arc<dyn BrokerClient>, BrokerClientError> {
+        let key = BrokerAccount::new(broker.clone(), account);
+        self.clients
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| BrokerClientError::NotConfigured(key.to_string()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&BrokerAccount, &Arc<dyn BrokerClient>)> {
+        self.clients.iter()
+    }
+
+    /// Feeds the bar to clients of all accounts, see `BrokerClient::on_bar`.
+    pub fn on_bar(&self, symbol: &str, bar: &BarData) {
+        for client in self.clients.values() {
+            client.on_bar(symbol, bar);
+        }
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum BrokerClientError {
+    #[error("Alpaca request error: {0}")]
+    AlpacaError(String),
+    #[error("Bybit request error: {0}")]
+    BybitError(String),
+    #[error("Broker is temporarily unavailable: {0}")]
+    Unavailable(String),
+    #[error("Order rejected by broker: {0}")]
+    OrderRejected(String),
+    #[error("Invalid order request: {0}")]
+    InvalidOrderRequest(String),
+    /// Broker doesn't know the order, e.g. the paper broker after a restart.
+    #[error("Order not found: {0}")]
+    OrderNotFound(String),
+    /// Broker holds no position in the symbol, or none on the requested side.
+    #[error("No position for {0}")]
+    PositionNotFound(String),
+    #[error("Broker {0} is not configured")]
+    NotConfigured(String),
+}
+
+impl BrokerClientError {
+    /// Whether the same request may succeed later, e.g. after a timeout, rate limit or 5xx
+    /// response. Rejected orders (insufficient funds, unknown symbol, etc.) are final.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Unavailable(_))
+    }
+}
+
+/// Broker API used by `Core` and the REST handlers. Requests and responses are broker
+/// independent, each client maps them onto its native API.
+#[axum::async_trait]
+pub trait BrokerClient: Send + Sync {
+    async fn get_account(&self) -> Result<Account, BrokerClientError>;
+    async fn get_activities(
+        &self,
+        activities_req: ActivitiesRequest,
+    ) -> Result<Vec<Activity>, BrokerClientError>;
+    async fn get_asset(&self, symbol: String) -> Result<Asset, BrokerClientError>;
+    async fn get_assets(&self, class: AssetClass) -> Result<Vec<Asset>, BrokerClientError>;
+    async fn get_position(&self, symbol: String) -> Result<Position, BrokerClientError>;
+    async fn get_positions(&self) -> Result<Vec<Position>, BrokerClientError>;
+    async fn delete_position(&self, symbol: String) -> Result<Order, BrokerClientError>;
+    /// Position on the given side of the symbol, for brokers holding long and short positions
+    /// in a symbol at once. Others hold one position per symbol, which has to be on that side.
+    async fn get_side_position(
+        &self,
+        symbol: String,
+        side: PositionSide,
+    ) -> Result<Position, BrokerClientError> {
+        let position = self.get_position(symbol.clone()).await?;
+        if position.side != side {
+            return Err(BrokerClientError::PositionNotFound(format!(
+                "{} on the {:?} side",
+                symbol, side
+            )));
+        }
+        Ok(position)
+    }
+    /// Closes the position on the given side of the symbol, see `get_side_position`.
+    async fn delete_side_position(
+        &self,
+        symbol: String,
+        side: PositionSide,
+    ) -> Result<Order, BrokerClientError> {
+        self.get_side_position(symbol.clone(), side).await?;
+        self.delete_position(symbol).await
+    }
+    async fn get_order_by_client_id(&self, client_id: String) -> Result<Order, BrokerClientError>;
+    async fn get_orders(&self, orders_req: OrdersRequest) -> Result<Vec<Order>, BrokerClientError>;
+    async fn create_order(&self, new_order_req: OrderRequest) -> Result<Order, BrokerClientError>;
+    /// Amends the order with the broker assigned `order_id`.
+    async fn update_order(
+        &self,
+        order_id: &str,
+        update_req: OrderUpdate,
+    ) -> Result<Order, BrokerClientError>;
+    async fn delete_order(&self, order_id: &str) -> Result<(), BrokerClientError>;
+
+    /// Builds an entry order request for `OpenLong`/`OpenShort` signals.
+    fn new_order_request(
+        &self,
+        trade_signal: &TradeSignal,
+        entry: &EntryOrder,
+    ) -> Result<OrderRequest, BrokerClientError>;
+    /// Builds an order closing the position at the stop loss or take profit price.
+    fn exit_order_request(&self, exit: &ExitOrder) -> Result<OrderRequest, BrokerClientError>;
+    /// Decimal places allowed in the quantity of an order for the asset.
+    fn quantity_scale(&self, asset: &Asset, bracket: bool) -> u32;
+    /// Whether stop loss and take profit can be attached to the entry order itself. Otherwise
+    /// `Core` emulates the bracket by placing exits once the entry is filled.
+    fn supports_bracket_orders(&self, currency_type: &CurrencyType) -> bool;
+    /// Whether exits can be reduce only, so both exits of an emulated bracket can rest at once
+    /// without reversing the position. Otherwise `Core` places only the stop loss and watches the
+    /// take profit price on sync.
+    fn supports_reduce_only(&self, currency_type: &CurrencyType) -> bool;
+    /// Market data of the symbol received with an alert. Only brokers simulating fills, e.g.
+    /// `PaperBroker`, need it.
+    fn on_bar(&self, _symbol: &str, _bar: &BarData) {}
+}
@@ -0,0 +1,668 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use super::{BrokerClient, BrokerClientError};
+use crate::{
+    api::{
+        alert::{BarData, PositionSide},
+        objects::{
+            Account, ActivitiesRequest, Activity, Asset, AssetClass, Broker, Order, OrderRequest,
+            OrderSide, OrderStatus, OrderType, OrderUpdate, OrdersRequest, Position, TimeInForce,
+        },
+    },
+    order::{EntryOrder, ExitKind, ExitOrder},
+    strategy::CurrencyType,
+    trade_signal::TradeSignal,
+};
+
+/// Decimal places of quantities accepted by the paper broker.
+const PAPER_QUANTITY_SCALE: u32 = 9;
+
+/// Currency the paper account is kept in.
+const PAPER_CURRENCY: &str = "USD";
+
+/// In-process broker simulating an account without network access. Market orders fill at the
+/// close of the last bar of the symbol, resting stop and limit orders fill when a later bar
+/// crosses their price. Bars come from webhook alerts, see `PaperBroker::on_bar`. Reduce only
+/// orders never fill beyond the open position and are canceled once it is closed.
+#[derive(Debug)]
+pub struct PaperBroker {
+    state: Mutex<PaperState>,
+}
+
+#[derive(Debug, Clone)]
+struct PaperOrder {
+    id: Uuid,
+    client_order_id: String,
+    symbol: String,
+    side: OrderSide,
+    order_type: OrderType,
+    quantity: Decimal,
+    limit_price: Option<Decimal>,
+    stop_price: Option<Decimal>,
+    /// Fills only reduce the position, see `PaperState::reducible`.
+    reduce_only: bool,
+    status: OrderStatus,
+    filled_quantity: Decimal,
+    average_fill_price: Option<Decimal>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    filled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct PaperPosition {
+    symbol: String,
+    /// Signed size of the position, negative for short positions.
+    quantity: Decimal,
+    average_entry_price: Decimal,
+    current_price: Decimal,
+    /// Price the intraday profit or loss is measured from: the price at the start of the day, or
+    /// the entry price for positions opened during the day.
+    day_reference_price: Decimal,
+}
+
+#[derive(Debug, Default)]
+struct PaperState {
+    cash: Decimal,
+    last_equity: Decimal,
+    day: Option<NaiveDate>,
+    /// Time of the last bar, used as the time of fills.
+    now: Option<DateTime<Utc>>,
+    prices: HashMap<String, Decimal>,
+    positions: BTreeMap<String, PaperPosition>,
+    orders: Vec<PaperOrder>,
+}
+
+impl PaperOrder {
+    fn to_order(&self) -> Order {
+        Order {
+            broker: Broker::Paper,
+            broker_order_id: self.id.to_string(),
+            client_order_id: self.client_order_id.clone(),
+            symbol: self.symbol.clone(),
+            side: self.side,
+            order_type: self.order_type,
+            quantity: Some(self.quantity),
+            limit_price: self.limit_price,
+            stop_price: self.stop_price,
+            status: self.status,
+            filled_quantity: self.filled_quantity,
+            average_fill_price: self.average_fill_price,
+            created_at: Some(self.created_at),
+            updated_at: self.updated_at,
+            filled_at: self.filled_at,
+            legs: Vec::new(),
+            raw: None,
+        }
+    }
+}
+
+impl PaperPosition {
+    fn to_position(&self) -> Position {
+        Position {
+            broker: Broker::Paper,
+            symbol: self.symbol.clone(),
+            side: if self.quantity < Decimal::ZERO {
+                PositionSide::Short
+            } else {
+                PositionSide::Long
+            },
+            quantity: self.quantity.abs(),
+            average_entry_price: self.average_entry_price,
+            current_price: Some(self.current_price),
+            market_value: self.quantity * self.current_price,
+            unrealized_intraday_pl: self.quantity * (self.current_price - self.day_reference_price),
+            raw: None,
+        }
+    }
+}
+
+impl PaperBroker {
+    pub fn new(cash: Decimal) -> Self {
+        Self {
+            state: Mutex::new(PaperState {
+                cash,
+                last_equity: cash,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Feeds a new bar of the symbol: fills resting orders whose price the bar crossed and moves
+    /// the symbol's price to the bar close.
+    pub fn on_bar(&self, symbol: &str, bar: &BarData) {
+        let mut state = self.state();
+        state.roll_day(bar.time);
+
+        let open = *bar.open.as_ref();
+        let high = *bar.high.as_ref();
+        let low = *bar.low.as_ref();
+        for index in 0..state.orders.len() {
+            let order = &state.orders[index];
+            if order.symbol != symbol || order.status != OrderStatus::New {
+                continue;
+            }
+            if let Some(price) = fill_price(order, open, high, low) {
+                state.fill(index, price);
+            }
+        }
+
+        let close = *bar.close.as_ref();
+        state.prices.insert(symbol.to_owned(), close);
+        if let Some(position) = state.positions.get_mut(symbol) {
+            position.current_price = close;
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PaperState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl PaperState {
+    fn equity(&self) -> Decimal {
+        self.cash
+            + self
+                .positions
+                .values()
+                .map(|position| position.quantity * position.current_price)
+                .sum::<Decimal>()
+    }
+
+    fn gross_exposure(&self) -> Decimal {
+        self.positions
+            .values()
+            .map(|position| (position.quantity * position.current_price).abs())
+            .sum()
+    }
+
+    fn buying_power(&self) -> Decimal {
+        (self.equity() - self.gross_exposure()).max(Decimal::ZERO)
+    }
+
+    // Start of day equity and intraday reference prices are reset by the first bar of a day.
+    fn roll_day(&mut self, time: DateTime<Utc>) {
+        self.now = Some(time);
+        let day = time.date_naive();
+        if self.day == Some(day) {
+            return;
+        }
+
+        self.day = Some(day);
+        self.last_equity = self.equity();
+        for position in self.positions.values_mut() {
+            position.day_reference_price = position.current_price;
+        }
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.now.unwrap_or_else(Utc::now)
+    }
+
+    fn order(&self, order_id: &str) -> Result<usize, BrokerClientError> {
+        self.orders
+            .iter()
+            .position(|order| order.id.to_string() == order_id)
+            .ok_or_else(|| BrokerClientError::OrderRejected(format!("Order {order_id} not found")))
+    }
+
+    fn open_order(&self, order_id: &str) -> Result<usize, BrokerClientError> {
+        let index = self.order(order_id)?;
+        if self.orders[index].status != OrderStatus::New {
+            return Err(BrokerClientError::OrderRejected(format!(
+                "Order {order_id} is not open"
+            )));
+        }
+        Ok(index)
+    }
+
+    fn submit(&mut self, request: OrderRequest) -> Result<PaperOrder, BrokerClientError> {
+        validate(&request)?;
+        if self
+            .orders
+            .iter()
+            .any(|order| order.client_order_id == request.client_order_id)
+        {
+            return Err(BrokerClientError::OrderRejected(format!(
+                "Client order id {} already exists",
+                request.client_order_id
+            )));
+        }
+
+        let market_price = if request.order_type == OrderType::Market {
+            let price = *self.prices.get(&request.symbol).ok_or_else(|| {
+                BrokerClientError::OrderRejected(format!("No price for {} yet", request.symbol))
+            })?;
+            self.check_buying_power(&request, price)?;
+            Some(price)
+        } else {
+            None
+        };
+
+        self.orders.push(PaperOrder {
+            id: Uuid::new_v4(),
+            client_order_id: request.client_order_id,
+            symbol: request.symbol,
+            side: request.side,
+            order_type: request.order_type,
+            quantity: request.quantity,
+            limit_price: request.limit_price,
+            stop_price: request.stop_price,
+            reduce_only: request.reduce_only,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            average_fill_price: None,
+            created_at: self.now(),
+            updated_at: None,
+            filled_at: None,
+        });
+
+        let index = self.orders.len() - 1;
+        if let Some(price) = market_price {
+            self.fill(index, price);
+        }
+        Ok(self.orders[index].clone())
+    }
+
+    // Only orders adding to the position need buying power.
+    fn check_buying_power(
+        &self,
+        request: &OrderRequest,
+        price: Decimal,
+    ) -> Result<(), BrokerClientError> {
+        let position = self
+            .positions
+            .get(&request.symbol)
+            .map(|position| position.quantity)
+            .unwrap_or_default();
+        let reduces = match request.side {
+            OrderSide::Buy => position < Decimal::ZERO && request.quantity <= -position,
+            OrderSide::Sell => position > Decimal::ZERO && request.quantity <= position,
+        };
+
+        let notional = request.quantity * price;
+        if !reduces && notional > self.buying_power() {
+            return Err(BrokerClientError::OrderRejected(format!(
+                "Insufficient buying power for {} {} at {}",
+                request.quantity, request.symbol, price
+            )));
+        }
+        Ok(())
+    }
+
+    // Reduce only orders fill at most the open position, without one there is nothing to fill and
+    // the order is canceled.
+    fn fill(&mut self, index: usize, price: Decimal) {
+        let now = self.now();
+        let filled_quantity = if self.orders[index].reduce_only {
+            self.reducible(&self.orders[index])
+        } else {
+            self.orders[index].quantity
+        };
+        let order = &mut self.orders[index];
+        order.updated_at = Some(now);
+        if filled_quantity.is_zero() {
+            order.status = OrderStatus::Canceled;
+            return;
+        }
+        order.status = OrderStatus::Filled;
+        order.filled_quantity = filled_quantity;
+        order.average_fill_price = Some(price);
+        order.filled_at = Some(now);
+
+        let quantity = match order.side {
+            OrderSide::Buy => filled_quantity,
+            OrderSide::Sell => -filled_quantity,
+        };
+        let symbol = order.symbol.clone();
+        self.cash -= quantity * price;
+
+        let position = self
+            .positions
+            .entry(symbol.clone())
+            .or_insert_with(|| PaperPosition {
+                symbol: symbol.clone(),
+                quantity: Decimal::ZERO,
+                average_entry_price: price,
+                current_price: price,
+                day_reference_price: price,
+            });
+        let new_quantity = position.quantity + quantity;
+        if position.quantity.is_zero()
+            || position.quantity.is_sign_positive() == quantity.is_sign_positive()
+        {
+            // Adding to the position averages the entry price
+            let weight = |price: Decimal, size: Decimal| price * size.abs();
+            position.average_entry_price =
+                (weight(position.average_entry_price, position.quantity) + weight(price, quantity))
+                    / new_quantity.abs();
+            position.day_reference_price =
+                (weight(position.day_reference_price, position.quantity) + weight(price, quantity))
+                    / new_quantity.abs();
+        } else if !new_quantity.is_zero()
+            && new_quantity.is_sign_positive() != position.quantity.is_sign_positive()
+        {
+            // Reversed through zero, the rest is a new position
+            position.average_entry_price = price;
+            position.day_reference_price = price;
+        }
+        position.quantity = new_quantity;
+        position.current_price = price;
+
+        if new_quantity.is_zero() {
+            self.positions.remove(&symbol);
+            self.cancel_reduce_only(&symbol, now);
+        }
+    }
+
+    /// Quantity of the order which reduces the open position of its symbol without reversing it.
+    fn reducible(&self, order: &PaperOrder) -> Decimal {
+        let position = self
+            .positions
+            .get(&order.symbol)
+            .map(|position| position.quantity)
+            .unwrap_or_default();
+        match order.side {
+            OrderSide::Buy if position < Decimal::ZERO => order.quantity.min(-position),
+            OrderSide::Sell if position > Decimal::ZERO => order.quantity.min(position),
+            _ => Decimal::ZERO,
+        }
+    }
+
+    // Once the position is closed its remaining exits, e.g. the other leg of an emulated
+    // bracket, have nothing left to reduce.
+    fn cancel_reduce_only(&mut self, symbol: &str, now: DateTime<Utc>) {
+        for order in self.orders.iter_mut() {
+            if order.symbol == symbol && order.reduce_only && order.status == OrderStatus::New {
+                order.status = OrderStatus::Canceled;
+                order.updated_at = Some(now);
+            }
+        }
+    }
+}
+
+fn validate(request: &OrderRequest) -> Result<(), BrokerClientError> {
+    let has_prices = match request.order_type {
+        OrderType::Market => true,
+        OrderType::Limit => request.limit_price.is_some(),
+        OrderType::Stop => request.stop_price.is_some(),
+        OrderType::StopLimit => request.stop_price.is_some() && request.limit_price.is_some(),
+        OrderType::TrailingStop => {
+            return Err(BrokerClientError::InvalidOrderRequest(
+                "Trailing stop orders are not supported".to_owned(),
+            ))
+        }
+    };
+
+    if request.quantity <= Decimal::ZERO || !has_prices {
+        return Err(BrokerClientError::InvalidOrderRequest(format!(
+            "{:?} order for {} {} is missing prices or has invalid quantity",
+            request.order_type, request.quantity, request.symbol
+        )));
+    }
+    Ok(())
+}
+
+// Any symbol can be traded on paper, fractional quantities included.
+fn paper_asset(symbol: String, class: AssetClass) -> Asset {
+    Asset {
+        broker: Broker::Paper,
+        symbol,
+        class,
+        tradable: true,
+        fractionable: true,
+        quantity_increment: None,
+        min_order_quantity: None,
+        raw: None,
+    }
+}
+
+/// Price a resting order fills at within the bar, if the bar reaches it. Gaps through the price
+/// fill at the bar open.
+fn fill_price(order: &PaperOrder, open: Decimal, high: Decimal, low: Decimal) -> Option<Decimal> {
+    let limit = order.limit_price.unwrap_or_default();
+    let stop = order.stop_price.unwrap_or_default();
+
+    match (order.order_type, order.side) {
+        (OrderType::Market, _) => Some(open),
+        (OrderType::Limit, OrderSide::Buy) => (low <= limit).then(|| limit.min(open)),
+        (OrderType::Limit, OrderSide::Sell) => (high >= limit).then(|| limit.max(open)),
+        (OrderType::Stop, OrderSide::Buy) => (high >= stop).then(|| stop.max(open)),
+        (OrderType::Stop, OrderSide::Sell) => (low <= stop).then(|| stop.min(open)),
+        // Triggered stop limits fill only if the trigger price is within the limit
+        (OrderType::StopLimit, OrderSide::Buy) => (high >= stop)
+            .then(|| stop.max(open))
+            .filter(|price| *price <= limit),
+        (OrderType::StopLimit, OrderSide::Sell) => (low <= stop)
+            .then(|| stop.min(open))
+            .filter(|price| *price >= limit),
+        // Rejected when submitted
+        (OrderType::TrailingStop, _) => None,
+    }
+}
+
+#[axum::async_trait]
+impl BrokerClient for PaperBroker {
+    async fn get_account(&self) -> Result<Account, BrokerClientError> {
+        let state = self.state();
+        Ok(Account {
+            broker: Broker::Paper,
+            currency: PAPER_CURRENCY.to_owned(),
+            cash: state.cash,
+            equity: state.equity(),
+            last_equity: state.last_equity,
+            buying_power: state.buying_power(),
+            raw: None,
+        })
+    }
+
+    async fn get_activities(
+        &self,
+        _activities_req: ActivitiesRequest,
+    ) -> Result<Vec<Activity>, BrokerClientError> {
+        Ok(Vec::new())
+    }
+
+    async fn get_asset(&self, symbol: String) -> Result<Asset, BrokerClientError> {
+        Ok(paper_asset(symbol, AssetClass::UsEquity))
+    }
+
+    // Symbols with a known price, regardless of class
+    async fn get_assets(&self, class: AssetClass) -> Result<Vec<Asset>, BrokerClientError> {
+        Ok(self
+            .state()
+            .prices
+            .keys()
+            .map(|symbol| paper_asset(symbol.clone(), class.clone()))
+            .collect())
+    }
+
+    async fn get_position(&self, symbol: String) -> Result<Position, BrokerClientError> {
+        self.state()
+            .positions
+            .get(&symbol)
+            .map(PaperPosition::to_position)
+            .ok_or_else(|| BrokerClientError::PositionNotFound(symbol.clone()))
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, BrokerClientError> {
+        Ok(self
+            .state()
+            .positions
+            .values()
+            .map(PaperPosition::to_position)
+            .collect())
+    }
+
+    async fn delete_position(&self, symbol: String) -> Result<Order, BrokerClientError> {
+        let mut state = self.state();
+        let quantity = state
+            .positions
+            .get(&symbol)
+            .map(|position| position.quantity)
+            .ok_or_else(|| BrokerClientError::PositionNotFound(symbol.clone()))?;
+
+        let order = state.submit(OrderRequest {
+            client_order_id: Uuid::new_v4().to_string(),
+            symbol,
+            side: if quantity > Decimal::ZERO {
+                OrderSide::Sell
+            } else {
+                OrderSide::Buy
+            },
+            order_type: OrderType::Market,
+            quantity: quantity.abs(),
+            limit_price: None,
+            stop_price: None,
+            time_in_force: TimeInForce::Day,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: true,
+        })?;
+        Ok(order.to_order())
+    }
+
+    async fn get_order_by_client_id(&self, client_id: String) -> Result<Order, BrokerClientError> {
+        self.state()
+            .orders
+            .iter()
+            .find(|order| order.client_order_id == client_id)
+            .map(PaperOrder::to_order)
+            .ok_or(BrokerClientError::OrderNotFound(client_id))
+    }
+
+    async fn get_orders(&self, orders_req: OrdersRequest) -> Result<Vec<Order>, BrokerClientError> {
+        Ok(self
+            .state()
+            .orders
+            .iter()
+            .filter(|order| {
+                orders_req
+                    .symbol
+                    .iter()
+                    .all(|symbol| *symbol == order.symbol)
+                    && (!orders_req.open_only || order.status == OrderStatus::New)
+            })
+            .map(PaperOrder::to_order)
+            .collect())
+    }
+
+    async fn create_order(&self, new_order_req: OrderRequest) -> Result<Order, BrokerClientError> {
+        self.state()
+            .submit(new_order_req)
+            .map(|order| order.to_order())
+    }
+
+    async fn update_order(
+        &self,
+        order_id: &str,
+        update_req: OrderUpdate,
+    ) -> Result<Order, BrokerClientError> {
+        let mut state = self.state();
+        let index = state.open_order(order_id)?;
+        let now = state.now();
+        let order = &mut state.orders[index];
+        order.updated_at = Some(now);
+        if let Some(stop_price) = update_req.stop_price {
+            order.stop_price = Some(stop_price);
+        }
+        if let Some(limit_price) = update_req.limit_price {
+            order.limit_price = Some(limit_price);
+        }
+        Ok(order.to_order())
+    }
+
+    async fn delete_order(&self, order_id: &str) -> Result<(), BrokerClientError> {
+        let mut state = self.state();
+        let index = state.open_order(order_id)?;
+        let now = state.now();
+        state.orders[index].status = OrderStatus::Canceled;
+        state.orders[index].updated_at = Some(now);
+        Ok(())
+    }
+
+    fn new_order_request(
+        &self,
+        trade_signal: &TradeSignal,
+        entry: &EntryOrder,
+    ) -> Result<OrderRequest, BrokerClientError> {
+        let side = match trade_signal.signal_type.entry_side() {
+            Some(PositionSide::Long) => OrderSide::Buy,
+            Some(PositionSide::Short) => OrderSide::Sell,
+            None => {
+                return Err(BrokerClientError::InvalidOrderRequest(format!(
+                    "{} signal doesn't open a position",
+                    trade_signal.signal_type.as_ref()
+                )))
+            }
+        };
+
+        Ok(OrderRequest {
+            client_order_id: entry.client_order_id.clone(),
+            symbol: trade_signal.ticker.clone(),
+            side,
+            order_type: OrderType::Market,
+            quantity: entry.quantity,
+            limit_price: None,
+            stop_price: None,
+            time_in_force: TimeInForce::Day,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: false,
+        })
+    }
+
+    fn exit_order_request(&self, exit: &ExitOrder) -> Result<OrderRequest, BrokerClientError> {
+        let side = match exit.position_side {
+            PositionSide::Long => OrderSide::Sell,
+            PositionSide::Short => OrderSide::Buy,
+        };
+        let (order_type, limit_price, stop_price) = match exit.kind {
+            ExitKind::StopLoss(price) => (OrderType::Stop, None, Some(price)),
+            ExitKind::TakeProfit(price) => (OrderType::Limit, Some(price), None),
+            ExitKind::Market => (OrderType::Market, None, None),
+        };
+
+        Ok(OrderRequest {
+            client_order_id: exit.client_order_id.clone(),
+            symbol: exit.ticker.clone(),
+            side,
+            order_type,
+            quantity: exit.quantity,
+            limit_price,
+            stop_price,
+            time_in_force: TimeInForce::Gtc,
+            stop_loss: None,
+            take_profit: None,
+            reduce_only: true,
+        })
+    }
+
+    fn quantity_scale(&self, asset: &Asset, _bracket: bool) -> u32 {
+        if asset.fractionable {
+            PAPER_QUANTITY_SCALE
+        } else {
+            0
+        }
+    }
+
+    // Brackets are emulated by `Core`, which exercises the same path as brokers without them
+    fn supports_bracket_orders(&self, _currency_type: &CurrencyType) -> bool {
+        false
+    }
+
+    fn supports_reduce_only(&self, _currency_type: &CurrencyType) -> bool {
+        true
+    }
+
+    fn on_bar(&self, symbol: &str, bar: &BarData) {
+        PaperBroker::on_bar(self, symbol, bar);
+    }
+}
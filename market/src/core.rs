@@ -1,61 +1,1388 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, RwLock},
+};
 
-use apca::{ApiInfo, Client as AlpacaClient};
-use config::{Config, ConfigError, File};
-use serde::Deserialize;
+use chrono::{NaiveDate, Utc};
+use config::ConfigError;
+use rust_decimal::{Decimal, RoundingStrategy};
+use sqlx::PgPool;
 use thiserror::Error as ThisError;
-use tokio::{
-    sync::mpsc::UnboundedReceiver,
-    time::{sleep, Duration},
-};
-use tracing::{error, info};
+use tokio::time::{interval, sleep, Duration, Instant};
+use tracing::{error, info, warn};
 use uuid::Uuid;
-use uuid7::uuid7;
 
 use crate::{
     api::{
-        alert::{SignalType, WebhookAlertData},
-        objects::Broker,
+        alert::{BarData, PositionSide, SignalType, TrailStopPrice},
+        objects::{Account, Order, OrderStatus, OrderUpdate, OrdersRequest, Position},
+    },
+    clients::{BrokerAccount, BrokerClient, BrokerClientError, BrokerRegistry},
+    halt::{Halt, HaltReport, HaltRequest},
+    order::{
+        BracketStatus, EmulatedBracket, EntryOrder, ExitKind, ExitOrder, OrderEvent, OrderRecord,
+        TERMINAL_STATUSES,
     },
-    clients::{BrokerClient, Clients},
+    risk::{Exposure, ProposedEntry, RiskBreach, RiskLimits},
+    strategy::{round_quantity, Strategy},
     trade_signal::TradeSignal,
-    App,
 };
 
-pub struct Core;
+/// Upper bound for the delay between order retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// How often locally tracked orders are reconciled with the broker.
+const ORDER_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait for a canceled or closing order to settle before the next order is placed.
+const ORDER_SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a settling order is polled.
+const ORDER_SETTLE_POLL: Duration = Duration::from_millis(250);
+
+pub struct Core {
+    db: PgPool,
+    /// Replaced when config is reloaded.
+    risk_limits: RwLock<RiskLimits>,
+    /// Day on which the circuit breaker of each account last flattened positions.
+    breaker_tripped_on: Mutex<HashMap<BrokerAccount, NaiveDate>>,
+}
 
 impl Core {
-    // NOTE: ?scheduled tasks
-    pub async fn run(&self) -> Result<(), anyhow::Error> {
-        Ok(())
+    pub fn new(db: PgPool, risk_limits: RiskLimits) -> Self {
+        Self {
+            db,
+            risk_limits: RwLock::new(risk_limits),
+            breaker_tripped_on: Mutex::new(HashMap::new()),
+        }
     }
 
-    pub async fn process_trade_signal<C: BrokerClient>(
+    pub fn risk_limits(&self) -> RiskLimits {
+        self.risk_limits.read().unwrap().clone()
+    }
+
+    /// Applies reloaded limits to the entries and breaker checks which start afterwards.
+    pub fn set_risk_limits(&self, risk_limits: RiskLimits) {
+        *self.risk_limits.write().unwrap() = risk_limits;
+    }
+
+    /// Runs scheduled tasks: keeps local order records in sync with brokers and watches the
+    /// circuit breaker limits.
+    pub async fn run(&self, brokers: Arc<BrokerRegistry>) -> Result<(), anyhow::Error> {
+        let mut sync_interval = interval(ORDER_SYNC_INTERVAL);
+
+        loop {
+            sync_interval.tick().await;
+            for (account, client) in brokers.iter() {
+                self.run_scheduled(account, client.as_ref()).await;
+            }
+        }
+    }
+
+    async fn run_scheduled(&self, account: &BrokerAccount, client: &dyn BrokerClient) {
+        if let Err(err) = self.sync_orders(account, client).await {
+            error!("Failed to sync {} orders, error: {:?}", account, err);
+        }
+        if self.risk_limits().has_breaker() {
+            if let Err(err) = self.check_breaker(account, client).await {
+                error!(
+                    "Failed to check {} circuit breaker, error: {:?}",
+                    account, err
+                );
+            }
+        }
+    }
+
+    /// Turns trade signal into broker orders. Entry signals submit a new order, stop loss updates
+    /// amend the stop price of the open stop order for the ticker. Exit signals cancel the
+    /// strategy's open orders for the ticker before closing the position.
+    pub async fn process_trade_signal(
         &self,
-        client: C,
+        client: Arc<dyn BrokerClient>,
         trade_signal: TradeSignal,
-    ) -> Result<(), ()> {
-        // let order_request = client.create_order_request
-        // let order = client.create_order(new_order_req);
-        // match trade_signal.signal_type {
-        //     SignalType::OpenLong => {}
-        //     SignalType::OpenShort => {}
-        //     SignalType::StopLossUpdate => {}
-        // };
+    ) -> Result<Order, TradeError> {
+        if let Some(halt) = Halt::find_active_for(&self.db, trade_signal.strategy.id).await? {
+            return Err(TradeError::Halted(halt.describe()));
+        }
+
+        match trade_signal.signal_type {
+            SignalType::OpenLong(_) | SignalType::OpenShort(_) => {
+                self.open_position(client.as_ref(), &trade_signal).await
+            }
+            SignalType::StopLossUpdate(stop_price) => {
+                self.update_stop_loss(client.as_ref(), &trade_signal, stop_price.0)
+                    .await
+            }
+            SignalType::CloseLong => {
+                self.close_position(client.as_ref(), &trade_signal, Some(PositionSide::Long))
+                    .await
+            }
+            SignalType::CloseShort => {
+                self.close_position(client.as_ref(), &trade_signal, Some(PositionSide::Short))
+                    .await
+            }
+            SignalType::CloseAll => {
+                self.close_position(client.as_ref(), &trade_signal, None)
+                    .await
+            }
+            SignalType::Reverse(stop_price) => {
+                self.reverse_position(client.as_ref(), &trade_signal, stop_price)
+                    .await
+            }
+            SignalType::PartialClose(percent) => {
+                self.partial_close(client.as_ref(), &trade_signal, percent.0)
+                    .await
+            }
+        }
+    }
+
+    /// Activates the kill switch for the strategy, or for all strategies if not set. Open orders
+    /// are canceled and positions closed on request, on every account for a global halt and
+    /// only the strategy's orders and positions otherwise. Exits of emulated brackets are still
+    /// placed while halted as they reduce risk, unless positions are closed: then brackets are
+    /// closed and their exits canceled first, so they can't open a position in reverse.
+    pub async fn halt(
+        &self,
+        brokers: &BrokerRegistry,
+        strategy: Option<&Strategy>,
+        request: &HaltRequest,
+    ) -> Result<HaltReport, sqlx::Error> {
+        let halt = Halt::activate(
+            &self.db,
+            strategy.map(|strategy| strategy.id),
+            request.reason.as_deref(),
+        )
+        .await?;
+        error!("Kill switch activated. {}", halt.describe());
+
+        let mut report = HaltReport {
+            halt,
+            canceled_orders: vec![],
+            closed_positions: vec![],
+            errors: vec![],
+        };
+        if !request.cancel_orders && !request.flatten {
+            return Ok(report);
+        }
+
+        let accounts = match strategy {
+            Some(strategy) => match brokers.get(&strategy.broker, &strategy.account) {
+                Ok(client) => vec![(strategy.broker_account(), client)],
+                Err(err) => {
+                    report.errors.push(err.to_string());
+                    vec![]
+                }
+            },
+            None => brokers
+                .iter()
+                .map(|(account, client)| (account.clone(), Arc::clone(client)))
+                .collect(),
+        };
+        for (account, client) in accounts {
+            if request.flatten {
+                let strategy_id = strategy.map(|strategy| strategy.id);
+                if let Err(err) =
+                    EmulatedBracket::close_for_account(&self.db, &account, strategy_id).await
+                {
+                    report
+                        .errors
+                        .push(format!("Failed to close {} brackets - {}", account, err));
+                }
+            }
+            if request.cancel_orders || request.flatten {
+                if let Err(err) = self
+                    .cancel_open_orders(&account, client.as_ref(), strategy, &mut report)
+                    .await
+                {
+                    report
+                        .errors
+                        .push(format!("Failed to cancel {} orders - {}", account, err));
+                }
+            }
+            if request.flatten {
+                if let Err(err) = self
+                    .close_positions(&account, client.as_ref(), strategy, &mut report)
+                    .await
+                {
+                    report
+                        .errors
+                        .push(format!("Failed to close {} positions - {}", account, err));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Cancels open orders of the strategy on the account, or all open orders of the account.
+    async fn cancel_open_orders(
+        &self,
+        account: &BrokerAccount,
+        client: &dyn BrokerClient,
+        strategy: Option<&Strategy>,
+        report: &mut HaltReport,
+    ) -> Result<(), TradeError> {
+        let order_ids: Vec<String> = match strategy {
+            Some(strategy) => OrderRecord::open_for_strategy(&self.db, strategy.id, account)
+                .await?
+                .into_iter()
+                .filter_map(|record| record.broker_order_id)
+                .collect(),
+            None => client
+                .get_orders(OrdersRequest {
+                    symbol: None,
+                    open_only: true,
+                })
+                .await?
+                .into_iter()
+                .map(|order| order.broker_order_id)
+                .collect(),
+        };
+
+        for order_id in order_ids {
+            match self.cancel_order(client, account, &order_id).await {
+                Ok(()) => report.canceled_orders.push(order_id),
+                Err(err) => report
+                    .errors
+                    .push(format!("Failed to cancel order {} - {}", order_id, err)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes the strategy's positions on the account, or all positions of the account.
+    /// Positions of other strategies in the same tickers are left open.
+    async fn close_positions(
+        &self,
+        account: &BrokerAccount,
+        client: &dyn BrokerClient,
+        strategy: Option<&Strategy>,
+        report: &mut HaltReport,
+    ) -> Result<(), TradeError> {
+        let positions = match strategy {
+            Some(strategy) => OrderRecord::positions(&self.db, strategy.id, account).await?,
+            None => client
+                .get_positions()
+                .await?
+                .into_iter()
+                .map(|position| match position.side {
+                    PositionSide::Long => (position.symbol, position.quantity),
+                    PositionSide::Short => (position.symbol, -position.quantity),
+                })
+                .collect(),
+        };
+
+        for (ticker, quantity) in positions {
+            let closed = match strategy {
+                Some(strategy) => {
+                    self.close_strategy_position(client, strategy, &report.halt, &ticker, quantity)
+                        .await
+                }
+                None => {
+                    let side = if quantity < Decimal::ZERO {
+                        PositionSide::Short
+                    } else {
+                        PositionSide::Long
+                    };
+                    client
+                        .delete_side_position(ticker.clone(), side)
+                        .await
+                        .map_err(TradeError::from)
+                }
+            };
+            match closed {
+                Ok(order) => {
+                    info!(
+                        "Closed position {} with order {}",
+                        ticker, order.broker_order_id
+                    );
+                    report.closed_positions.push(ticker);
+                }
+                Err(err) => report
+                    .errors
+                    .push(format!("Failed to close position {} - {}", ticker, err)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes the strategy's signed `quantity` of the ticker with a reduce only market order,
+    /// at most the position held at the broker. Repeating the halt doesn't close it twice.
+    async fn close_strategy_position(
+        &self,
+        client: &dyn BrokerClient,
+        strategy: &Strategy,
+        halt: &Halt,
+        ticker: &str,
+        quantity: Decimal,
+    ) -> Result<Order, TradeError> {
+        let position_side = if quantity < Decimal::ZERO {
+            PositionSide::Short
+        } else {
+            PositionSide::Long
+        };
+        let position = client
+            .get_side_position(ticker.to_owned(), position_side)
+            .await
+            .map_err(|err| match err {
+                BrokerClientError::PositionNotFound(_) => {
+                    TradeError::NoOpenPosition(ticker.to_owned())
+                }
+                err => TradeError::BrokerClientError(err),
+            })?;
+
+        let order_id = Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("{}/{}/flatten", halt.halt_id, ticker).as_bytes(),
+        );
+        let exit = ExitOrder {
+            client_order_id: order_id.to_string(),
+            ticker: ticker.to_owned(),
+            position_side,
+            quantity: quantity.abs().min(position.quantity),
+            currency_type: strategy.currency_type.clone(),
+            kind: ExitKind::Market,
+        };
+        let order = match client
+            .get_order_by_client_id(exit.client_order_id.clone())
+            .await
+        {
+            Ok(order) => order,
+            Err(_) => {
+                client
+                    .create_order(client.exit_order_request(&exit)?)
+                    .await?
+            }
+        };
+        OrderRecord::insert_for_strategy(&self.db, strategy, order_id, &order).await?;
+
+        Ok(order)
+    }
+
+    /// Cancels the order at the broker and marks local record of it as pending cancel. Final
+    /// status is picked up by the order sync.
+    pub async fn cancel_order(
+        &self,
+        client: &dyn BrokerClient,
+        account: &BrokerAccount,
+        order_id: &str,
+    ) -> Result<(), TradeError> {
+        client.delete_order(order_id).await?;
+
+        if let Some(record) =
+            OrderRecord::find_by_broker_order_id(&self.db, account, order_id).await?
+        {
+            record
+                .set_status(&self.db, "pending_cancel", OrderEvent::Canceled)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reconciles open local orders of the broker account with their state at the broker,
+    /// recording status changes and fills. Orders failing to sync are retried on the next sync
+    /// and don't hold up the others.
+    pub async fn sync_orders(
+        &self,
+        account: &BrokerAccount,
+        client: &dyn BrokerClient,
+    ) -> Result<(), TradeError> {
+        for record in OrderRecord::open(&self.db, account).await? {
+            if let Err(err) = self.sync_order(client, &record).await {
+                warn!(
+                    "Failed to sync order {} on {}, error: {}",
+                    record.order_id, account, err
+                );
+            }
+        }
+
+        self.manage_emulated_brackets(account, client).await
+    }
+
+    /// Orders the broker doesn't know, e.g. ones of the paper broker before a restart, are
+    /// marked `unknown` as they won't change anymore.
+    async fn sync_order(
+        &self,
+        client: &dyn BrokerClient,
+        record: &OrderRecord,
+    ) -> Result<(), TradeError> {
+        let order = match client
+            .get_order_by_client_id(record.client_order_id.clone())
+            .await
+        {
+            Ok(order) => order,
+            Err(BrokerClientError::OrderNotFound(_)) => {
+                warn!(
+                    "Order {} is unknown to the broker, giving up on it",
+                    record.order_id
+                );
+                record
+                    .set_status(&self.db, "unknown", OrderEvent::Updated)
+                    .await?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Some(event) = record.change_event(&order) {
+            record.apply(&self.db, &order, event, None).await?;
+        }
+
         Ok(())
     }
 
-    async fn order_request(&self, broker: Broker) -> Result<(), ()> {
+    /// Places exits of emulated brackets whose entry is filled and cancels the remaining exit
+    /// once the other one is filled. Failed brackets are retried on the next sync and don't hold
+    /// up the others.
+    async fn manage_emulated_brackets(
+        &self,
+        account: &BrokerAccount,
+        client: &dyn BrokerClient,
+    ) -> Result<(), TradeError> {
+        for bracket in EmulatedBracket::open(&self.db, account).await? {
+            if let Err(err) = self
+                .manage_emulated_bracket(account, client, &bracket)
+                .await
+            {
+                warn!(
+                    "Failed to manage emulated bracket of {}: {}",
+                    bracket.entry_order_id, err
+                );
+            }
+        }
+
         Ok(())
     }
+
+    // Without reduce only exits both exits can fill and reverse the position, so only the stop
+    // loss rests at the broker. Once the take profit price is reached the stop loss is canceled
+    // and, after the cancel went through, the position is closed at market.
+    async fn manage_emulated_bracket(
+        &self,
+        account: &BrokerAccount,
+        client: &dyn BrokerClient,
+        bracket: &EmulatedBracket,
+    ) -> Result<(), TradeError> {
+        let Some(entry) = OrderRecord::find(&self.db, bracket.entry_order_id).await? else {
+            return Ok(());
+        };
+
+        match bracket.status() {
+            BracketStatus::Pending if entry.is_terminal() => {
+                if entry.filled_quantity.is_zero() {
+                    bracket.close(&self.db).await?;
+                    return Ok(());
+                }
+
+                let stop_loss_order_id = self
+                    .submit_exit(
+                        client,
+                        &entry,
+                        bracket,
+                        ExitKind::StopLoss(bracket.stop_price),
+                    )
+                    .await?;
+                let take_profit_order_id = match bracket.take_profit_price {
+                    Some(price) if client.supports_reduce_only(&bracket.currency_type()) => Some(
+                        self.submit_exit(client, &entry, bracket, ExitKind::TakeProfit(price))
+                            .await?,
+                    ),
+                    _ => None,
+                };
+                bracket
+                    .activate(&self.db, stop_loss_order_id, take_profit_order_id)
+                    .await?;
+            }
+            BracketStatus::Active => {
+                let exits = bracket.exits(&self.db).await?;
+                if exits.iter().any(|exit| exit.status == "filled") {
+                    self.cancel_exits(client, account, &exits).await?;
+                    bracket.close(&self.db).await?;
+                } else if exits.iter().all(OrderRecord::is_terminal) {
+                    bracket.close(&self.db).await?;
+                } else if let (Some(price), None) =
+                    (bracket.take_profit_price, bracket.take_profit_order_id)
+                {
+                    if self.take_profit_reached(client, &entry, price).await? {
+                        self.cancel_exits(client, account, &exits).await?;
+                        bracket
+                            .set_status(&self.db, BracketStatus::TakingProfit)
+                            .await?;
+                    }
+                }
+            }
+            BracketStatus::TakingProfit => {
+                let exits = bracket.exits(&self.db).await?;
+                if exits.iter().any(|exit| exit.status == "filled") {
+                    // Stop loss filled before it was canceled
+                    bracket.close(&self.db).await?;
+                } else if let (Some(stop_loss_order_id), true) = (
+                    bracket.stop_loss_order_id,
+                    exits.iter().all(OrderRecord::is_terminal),
+                ) {
+                    let close_order_id = self
+                        .submit_exit(client, &entry, bracket, ExitKind::Market)
+                        .await?;
+                    bracket
+                        .activate(&self.db, stop_loss_order_id, Some(close_order_id))
+                        .await?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn cancel_exits(
+        &self,
+        client: &dyn BrokerClient,
+        account: &BrokerAccount,
+        exits: &[OrderRecord],
+    ) -> Result<(), TradeError> {
+        for exit in exits.iter().filter(|exit| !exit.is_terminal()) {
+            if let Some(broker_order_id) = exit.broker_order_id.as_deref() {
+                self.cancel_order(client, account, broker_order_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the price of the entry's position reached the take profit price.
+    async fn take_profit_reached(
+        &self,
+        client: &dyn BrokerClient,
+        entry: &OrderRecord,
+        price: Decimal,
+    ) -> Result<bool, TradeError> {
+        let position = client
+            .get_side_position(entry.ticker.clone(), entry.position_side())
+            .await?;
+        Ok(match (position.current_price, entry.position_side()) {
+            (Some(current), PositionSide::Long) => current >= price,
+            (Some(current), PositionSide::Short) => current <= price,
+            (None, _) => false,
+        })
+    }
+
+    async fn submit_exit(
+        &self,
+        client: &dyn BrokerClient,
+        entry: &OrderRecord,
+        bracket: &EmulatedBracket,
+        kind: ExitKind,
+    ) -> Result<Uuid, TradeError> {
+        let purpose = kind.purpose();
+        let order_id = Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("{}/{}", entry.order_id, purpose).as_bytes(),
+        );
+        let exit = ExitOrder {
+            client_order_id: order_id.to_string(),
+            ticker: entry.ticker.clone(),
+            position_side: entry.position_side(),
+            quantity: entry.filled_quantity,
+            currency_type: bracket.currency_type(),
+            kind,
+        };
+
+        let order = match client
+            .get_order_by_client_id(exit.client_order_id.clone())
+            .await
+        {
+            Ok(order) => order,
+            Err(_) => {
+                client
+                    .create_order(client.exit_order_request(&exit)?)
+                    .await?
+            }
+        };
+        entry.insert_child(&self.db, order_id, &order).await?;
+        info!(
+            "Placed {} order {} for emulated bracket of {}",
+            purpose, order.broker_order_id, entry.order_id
+        );
+
+        Ok(order_id)
+    }
+
+    async fn open_position(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+    ) -> Result<Order, TradeError> {
+        let order_id = trade_signal.order_id("entry");
+        let stop_loss = trade_signal.signal_type.trail_stop_price().ok_or_else(|| {
+            TradeError::InvalidSignal(format!(
+                "{} signal has no stop price",
+                trade_signal.signal_type.as_ref()
+            ))
+        })?;
+        check_stop_side(trade_signal, stop_loss)?;
+        let take_profit = self.take_profit_price(trade_signal, stop_loss).await?;
+        let native_bracket = client.supports_bracket_orders(&trade_signal.strategy.currency_type);
+        let account = client.get_account().await?;
+        let quantity = self
+            .position_size(client, &account, trade_signal, stop_loss, native_bracket)
+            .await?;
+        self.check_risk(client, &account, trade_signal, quantity)
+            .await?;
+        let entry = &EntryOrder {
+            client_order_id: order_id.to_string(),
+            quantity,
+            stop_loss: native_bracket.then_some(stop_loss),
+            take_profit: take_profit.filter(|_| native_bracket),
+        };
+
+        let result = self
+            .with_retries(&trade_signal.strategy, move || async move {
+                // Previous attempt may have reached the broker even though it failed for us
+                if let Ok(order) = client
+                    .get_order_by_client_id(entry.client_order_id.clone())
+                    .await
+                {
+                    info!(
+                        "Order {} already exists at the broker",
+                        entry.client_order_id
+                    );
+                    return Ok(order);
+                }
+
+                client
+                    .create_order(client.new_order_request(trade_signal, entry)?)
+                    .await
+            })
+            .await;
+
+        let order = match result {
+            Ok(order) => order,
+            Err(err) => {
+                let side = match trade_signal.signal_type {
+                    SignalType::OpenShort(_) => "sell",
+                    _ => "buy",
+                };
+                OrderRecord::insert_failed(
+                    &self.db,
+                    trade_signal,
+                    order_id,
+                    side,
+                    quantity,
+                    &err.to_string(),
+                )
+                .await?;
+                return Err(err);
+            }
+        };
+
+        OrderRecord::insert_submitted(&self.db, trade_signal, order_id, &order).await?;
+        if !native_bracket {
+            EmulatedBracket::insert(
+                &self.db,
+                order_id,
+                &trade_signal.strategy.currency_type,
+                stop_loss,
+                take_profit,
+            )
+            .await?;
+        }
+        info!(
+            "Submitted {} order {} for {} (strategy {})",
+            trade_signal.signal_type.as_ref(),
+            order.broker_order_id,
+            trade_signal.ticker,
+            trade_signal.strategy.name
+        );
+
+        Ok(order)
+    }
+
+    /// Quantity of the entry per strategy's sizing mode, capped by the buying power and rounded to
+    /// what the broker accepts for the asset. Entries below the broker's minimum order are
+    /// rejected. Entry price is estimated by the close of the signal bar.
+    async fn position_size(
+        &self,
+        client: &dyn BrokerClient,
+        account: &Account,
+        trade_signal: &TradeSignal,
+        stop: Decimal,
+        bracket: bool,
+    ) -> Result<Decimal, TradeError> {
+        let asset = client.get_asset(trade_signal.ticker.clone()).await?;
+
+        let entry = *trade_signal.bar_data.close.as_ref();
+        let side = trade_signal
+            .signal_type
+            .entry_side()
+            .unwrap_or(PositionSide::Long);
+        let quantity = trade_signal
+            .strategy
+            .position_sizing
+            .quantity(side, entry, stop, account.equity)
+            .ok_or_else(|| {
+                TradeError::InvalidSignal(format!(
+                    "Can't calculate position size for entry at {} with stop at {}",
+                    entry, stop
+                ))
+            })?;
+
+        let scale = client.quantity_scale(&asset, bracket);
+        let rounded =
+            asset.round_to_increment(round_quantity(quantity, entry, account.buying_power, scale));
+        if rounded <= Decimal::ZERO {
+            return Err(TradeError::InsufficientFunds(format!(
+                "Position size {} of {} rounds to zero with buying power {}",
+                quantity, trade_signal.ticker, account.buying_power
+            )));
+        }
+        if asset.below_min_order(rounded) {
+            return Err(TradeError::InsufficientFunds(format!(
+                "Position size {} of {} is below the minimum order quantity {}",
+                rounded,
+                trade_signal.ticker,
+                asset.min_order_quantity.unwrap_or_default()
+            )));
+        }
+
+        Ok(rounded)
+    }
+
+    /// Rejects the entry if it would breach account risk limits. Breaching the daily loss or
+    /// drawdown limit trips the circuit breaker.
+    async fn check_risk(
+        &self,
+        client: &dyn BrokerClient,
+        account: &Account,
+        trade_signal: &TradeSignal,
+        quantity: Decimal,
+    ) -> Result<(), TradeError> {
+        let positions = client.get_positions().await?;
+        let exposure = Exposure::new(account, &positions);
+        let entry = ProposedEntry {
+            ticker: &trade_signal.ticker,
+            side: trade_signal
+                .signal_type
+                .entry_side()
+                .unwrap_or(PositionSide::Long),
+            notional: quantity * trade_signal.bar_data.close.as_ref(),
+        };
+
+        if let Err(breach) = self.risk_limits().check_entry(&exposure, &entry) {
+            if breach.trips_breaker() {
+                let broker_account = trade_signal.strategy.broker_account();
+                self.trip_breaker(&broker_account, client, &positions, &breach)
+                    .await;
+            }
+            return Err(TradeError::RiskLimitBreached(breach.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Trips the circuit breaker if the account is past the daily loss or drawdown limit.
+    async fn check_breaker(
+        &self,
+        broker_account: &BrokerAccount,
+        client: &dyn BrokerClient,
+    ) -> Result<(), TradeError> {
+        let account = client.get_account().await?;
+        let positions = client.get_positions().await?;
+
+        if let Err(breach) = self
+            .risk_limits()
+            .check_breaker(&Exposure::new(&account, &positions))
+        {
+            self.trip_breaker(broker_account, client, &positions, &breach)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Closes all positions of the account once a day if the breaker is configured to flatten.
+    /// Entries are rejected for as long as the breach lasts either way.
+    async fn trip_breaker(
+        &self,
+        broker_account: &BrokerAccount,
+        client: &dyn BrokerClient,
+        positions: &[Position],
+        breach: &RiskBreach,
+    ) {
+        let today = Utc::now().date_naive();
+        {
+            let mut tripped_on = self.breaker_tripped_on.lock().unwrap();
+            if tripped_on.get(broker_account) == Some(&today) {
+                return;
+            }
+            tripped_on.insert(broker_account.clone(), today);
+        }
+
+        error!("Circuit breaker of {} tripped: {}", broker_account, breach);
+        if !self.risk_limits().flatten_on_breach {
+            return;
+        }
+
+        for position in positions {
+            match client
+                .delete_side_position(position.symbol.clone(), position.side)
+                .await
+            {
+                Ok(order) => info!(
+                    "Closed position {} with order {}",
+                    position.symbol, order.broker_order_id
+                ),
+                Err(err) => error!(
+                    "Failed to close position {}, error: {:?}",
+                    position.symbol, err
+                ),
+            }
+        }
+    }
+
+    /// Take profit for the entry signal per strategy's rule. Entry price is estimated by the
+    /// close of the signal bar.
+    async fn take_profit_price(
+        &self,
+        trade_signal: &TradeSignal,
+        stop: Decimal,
+    ) -> Result<Option<Decimal>, TradeError> {
+        let (Some(rule), Some(side)) = (
+            &trade_signal.strategy.take_profit,
+            trade_signal.signal_type.entry_side(),
+        ) else {
+            return Ok(None);
+        };
+
+        let bars = match rule.bars_required() {
+            1 => vec![trade_signal.bar_data.clone()],
+            limit => {
+                BarData::recent(
+                    &self.db,
+                    trade_signal.strategy.id,
+                    &trade_signal.ticker,
+                    &trade_signal.timeframe,
+                    trade_signal.bar_data.time,
+                    limit,
+                )
+                .await?
+            }
+        };
+
+        let entry = *trade_signal.bar_data.close.as_ref();
+        rule.price(side, entry, stop, &bars)
+            .map(Some)
+            .ok_or_else(|| {
+                TradeError::InvalidSignal(format!(
+                    "Can't calculate take profit for {:?} entry at {} with stop at {}",
+                    side, entry, stop
+                ))
+            })
+    }
+
+    /// Moves the open stop order of the strategy's position to the new stop price, or places one
+    /// if the position has no stop. Stops are only tightened unless the strategy allows
+    /// loosening them.
+    async fn update_stop_loss(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        stop_price: Decimal,
+    ) -> Result<Order, TradeError> {
+        let strategy = &trade_signal.strategy;
+        let Some(stop) = OrderRecord::open_stop(
+            &self.db,
+            strategy.id,
+            &strategy.broker_account(),
+            &trade_signal.ticker,
+        )
+        .await?
+        else {
+            return self
+                .create_stop_loss(client, trade_signal, stop_price)
+                .await;
+        };
+
+        let broker_order_id = stop.broker_order_id.as_deref().ok_or_else(|| {
+            TradeError::InvalidSignal(format!("Stop order {} has no broker id", stop.order_id))
+        })?;
+
+        if let Some(current) = stop.stop_price {
+            // Exit of a long position is a sell stop, which is tightened by moving it up
+            let tightens = match stop.side.as_str() {
+                "sell" => stop_price > current,
+                _ => stop_price < current,
+            };
+            if !tightens && !strategy.allow_stop_loosening {
+                return Err(TradeError::InvalidSignal(format!(
+                    "Stop loss update from {} to {} for {} would loosen the stop",
+                    current, stop_price, trade_signal.ticker
+                )));
+            }
+        }
+
+        let limit_price = (stop.order_type == "stop_limit").then_some(stop_price);
+        // Brokers replacing the order on change give the replacement this id, which the record
+        // tracks from now on
+        let client_order_id = trade_signal.order_id("stop_loss_update").to_string();
+        let client_order_id = &client_order_id;
+        let order = self
+            .with_retries(strategy, move || async move {
+                // Previous attempt may have replaced the order even though it failed for us
+                if let Ok(order) = client.get_order_by_client_id(client_order_id.clone()).await {
+                    return Ok(order);
+                }
+
+                client
+                    .update_order(
+                        broker_order_id,
+                        OrderUpdate {
+                            stop_price: Some(stop_price),
+                            limit_price,
+                            client_order_id: Some(client_order_id.clone()),
+                        },
+                    )
+                    .await
+            })
+            .await?;
+
+        let details = format!(
+            "stop price {} -> {} (alert {})",
+            stop.stop_price.unwrap_or_default(),
+            stop_price,
+            trade_signal.alert_id
+        );
+        stop.apply(&self.db, &order, OrderEvent::Updated, Some(&details))
+            .await?;
+
+        info!(
+            "Moved stop order {} for {} to {} (strategy {})",
+            broker_order_id, trade_signal.ticker, stop_price, strategy.name
+        );
+        Ok(order)
+    }
+
+    async fn create_stop_loss(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        stop_price: Decimal,
+    ) -> Result<Order, TradeError> {
+        let position = self.signal_position(client, trade_signal, None).await?;
+        let kind = ExitKind::StopLoss(stop_price);
+        let order = self
+            .place_exit(
+                client,
+                trade_signal,
+                trade_signal.order_id(kind.purpose()),
+                &position,
+                position.quantity,
+                kind,
+            )
+            .await?;
+
+        info!(
+            "Placed missing stop order {} for {} at {} (strategy {})",
+            order.broker_order_id, trade_signal.ticker, stop_price, trade_signal.strategy.name
+        );
+        Ok(order)
+    }
+
+    /// Closes the position in the signal's ticker, provided it is on the given side if set.
+    async fn close_position(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        side: Option<PositionSide>,
+    ) -> Result<Order, TradeError> {
+        let position = self.signal_position(client, trade_signal, side).await?;
+        self.exit_position(client, trade_signal, &position).await
+    }
+
+    /// Closes the position in the signal's ticker and opens one on the opposite side, sized per
+    /// strategy with the signal's stop.
+    async fn reverse_position(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        stop_price: TrailStopPrice,
+    ) -> Result<Order, TradeError> {
+        let (entry_side, close_order_id) =
+            match OrderRecord::find(&self.db, trade_signal.order_id("close")).await? {
+                // Closed by an earlier processing of the signal which failed to open the entry.
+                // Exit orders are on the opposite side of the position they close.
+                Some(close) => (close.position_side(), close.client_order_id),
+                None => {
+                    let position = self.signal_position(client, trade_signal, None).await?;
+                    let close = self.exit_position(client, trade_signal, &position).await?;
+                    let entry_side = match position.side {
+                        PositionSide::Long => PositionSide::Short,
+                        PositionSide::Short => PositionSide::Long,
+                    };
+                    (entry_side, close.client_order_id)
+                }
+            };
+
+        // Entry placed while the close is working would only add to the old position
+        let close = self.await_settled(client, &close_order_id).await?;
+        if close.status != OrderStatus::Filled {
+            return Err(TradeError::InvalidSignal(format!(
+                "Close order {} of {} ended {}, not reversing",
+                close.broker_order_id,
+                trade_signal.ticker,
+                close.status.as_ref()
+            )));
+        }
+
+        let mut entry_signal = trade_signal.clone();
+        entry_signal.signal_type = match entry_side {
+            PositionSide::Long => SignalType::OpenLong(stop_price),
+            PositionSide::Short => SignalType::OpenShort(stop_price),
+        };
+        self.open_position(client, &entry_signal).await
+    }
+
+    /// Closes the percentage of the position in the signal's ticker with a reduce only market
+    /// order. Stop loss and take profit of the position are placed again for the remaining
+    /// quantity, or for the whole position if the close fails.
+    async fn partial_close(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        percent: Decimal,
+    ) -> Result<Order, TradeError> {
+        let position = self.signal_position(client, trade_signal, None).await?;
+        let asset = client.get_asset(trade_signal.ticker.clone()).await?;
+        let quantity = asset.round_to_increment(
+            (position.quantity * percent / Decimal::ONE_HUNDRED).round_dp_with_strategy(
+                client.quantity_scale(&asset, false),
+                RoundingStrategy::ToZero,
+            ),
+        );
+        if quantity <= Decimal::ZERO || asset.below_min_order(quantity) {
+            return Err(TradeError::InvalidSignal(format!(
+                "Closing {}% of {} {} rounds to {}, below the minimum order",
+                percent, position.quantity, trade_signal.ticker, quantity
+            )));
+        }
+        if quantity >= position.quantity {
+            return self.exit_position(client, trade_signal, &position).await;
+        }
+
+        let canceled = self.cancel_ticker_orders(client, trade_signal).await?;
+        let stop_loss = canceled
+            .iter()
+            .filter(|record| matches!(record.order_type.as_str(), "stop" | "stop_limit"))
+            .find_map(|record| record.stop_price);
+        let take_profit = canceled
+            .iter()
+            .filter(|record| record.order_type == "limit")
+            .find_map(|record| record.limit_price);
+        let exits = [
+            stop_loss.map(ExitKind::StopLoss),
+            take_profit.map(ExitKind::TakeProfit),
+        ];
+
+        // Brokers reject exits while the canceled ones still hold the position's quantity
+        let closed = async {
+            for record in &canceled {
+                self.await_settled(client, &record.client_order_id).await?;
+            }
+            let order_id = trade_signal.order_id(ExitKind::Market.purpose());
+            self.place_exit(
+                client,
+                trade_signal,
+                order_id,
+                &position,
+                quantity,
+                ExitKind::Market,
+            )
+            .await
+        }
+        .await;
+
+        let order = match closed {
+            Ok(order) => order,
+            Err(err) => {
+                warn!(
+                    "Failed to close {} of {} {}, restoring its exits. Error: {}",
+                    quantity, position.quantity, trade_signal.ticker, err
+                );
+                for kind in exits.into_iter().flatten() {
+                    let order_id = self.restore_order_id(client, trade_signal, kind).await?;
+                    self.place_exit(
+                        client,
+                        trade_signal,
+                        order_id,
+                        &position,
+                        position.quantity,
+                        kind,
+                    )
+                    .await?;
+                }
+                return Err(err);
+            }
+        };
+        info!(
+            "Closed {} of {} {} with order {} (strategy {})",
+            quantity,
+            position.quantity,
+            trade_signal.ticker,
+            order.broker_order_id,
+            trade_signal.strategy.name
+        );
+
+        let remaining = position.quantity - quantity;
+        for kind in exits.into_iter().flatten() {
+            let order_id = trade_signal.order_id(kind.purpose());
+            self.place_exit(client, trade_signal, order_id, &position, remaining, kind)
+                .await?;
+        }
+
+        Ok(order)
+    }
+
+    /// Position in the signal's ticker, which has to be on the given side if set.
+    async fn signal_position(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        side: Option<PositionSide>,
+    ) -> Result<Position, TradeError> {
+        let ticker = trade_signal.ticker.clone();
+        let position = match side {
+            Some(side) => client.get_side_position(ticker, side).await,
+            None => client.get_position(ticker).await,
+        };
+
+        position.map_err(|err| match err {
+            BrokerClientError::PositionNotFound(_) => {
+                TradeError::NoOpenPosition(trade_signal.ticker.clone())
+            }
+            err => TradeError::BrokerClientError(err),
+        })
+    }
+
+    /// Cancels the strategy's orders for the signal's ticker and closes the whole position.
+    async fn exit_position(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        position: &Position,
+    ) -> Result<Order, TradeError> {
+        self.cancel_ticker_orders(client, trade_signal).await?;
+
+        let ticker = &trade_signal.ticker;
+        let order = self
+            .with_retries(&trade_signal.strategy, move || async move {
+                client
+                    .delete_side_position(ticker.clone(), position.side)
+                    .await
+            })
+            .await?;
+        OrderRecord::insert_submitted(
+            &self.db,
+            trade_signal,
+            trade_signal.order_id("close"),
+            &order,
+        )
+        .await?;
+
+        info!(
+            "Closed {:?} position in {} with order {} (strategy {})",
+            position.side, ticker, order.broker_order_id, trade_signal.strategy.name
+        );
+        Ok(order)
+    }
+
+    /// Cancels open orders of the strategy for the signal's ticker, i.e. stop loss and take
+    /// profit exits and entries which aren't filled yet, and closes its emulated brackets so
+    /// their exits aren't placed anymore. Returns the canceled orders.
+    async fn cancel_ticker_orders(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+    ) -> Result<Vec<OrderRecord>, TradeError> {
+        let strategy = &trade_signal.strategy;
+        let account = strategy.broker_account();
+        EmulatedBracket::close_for_ticker(&self.db, strategy.id, &account, &trade_signal.ticker)
+            .await?;
+
+        let mut canceled = vec![];
+        for record in OrderRecord::open_for_strategy(&self.db, strategy.id, &account).await? {
+            if record.ticker != trade_signal.ticker || record.status == "pending_cancel" {
+                continue;
+            }
+            let Some(broker_order_id) = record.broker_order_id.as_deref() else {
+                continue;
+            };
+
+            if let Err(err) = self.cancel_order(client, &account, broker_order_id).await {
+                // Legs of a bracket may be canceled along with the other leg
+                let order = client
+                    .get_order_by_client_id(record.client_order_id.clone())
+                    .await?;
+                if !TERMINAL_STATUSES.contains(&order.status.as_ref()) {
+                    return Err(err);
+                }
+            }
+            canceled.push(record);
+        }
+
+        Ok(canceled)
+    }
+
+    /// Places exit of the given quantity of the position in the signal's ticker. Reprocessing the
+    /// signal doesn't place the exit with the same id twice.
+    async fn place_exit(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        order_id: Uuid,
+        position: &Position,
+        quantity: Decimal,
+        kind: ExitKind,
+    ) -> Result<Order, TradeError> {
+        let exit = &ExitOrder {
+            client_order_id: order_id.to_string(),
+            ticker: trade_signal.ticker.clone(),
+            position_side: position.side,
+            quantity,
+            currency_type: trade_signal.strategy.currency_type.clone(),
+            kind,
+        };
+
+        let order = self
+            .with_retries(&trade_signal.strategy, move || async move {
+                // Previous attempt may have reached the broker even though it failed for us
+                if let Ok(order) = client
+                    .get_order_by_client_id(exit.client_order_id.clone())
+                    .await
+                {
+                    return Ok(order);
+                }
+
+                client.create_order(client.exit_order_request(exit)?).await
+            })
+            .await?;
+        OrderRecord::insert_submitted(&self.db, trade_signal, order_id, &order).await?;
+
+        Ok(order)
+    }
+
+    /// Id of the exit restored after a failed partial close. A retry of the signal cancels the
+    /// exits restored by earlier attempts, so the first id of the sequence which isn't taken by a
+    /// done order is used.
+    async fn restore_order_id(
+        &self,
+        client: &dyn BrokerClient,
+        trade_signal: &TradeSignal,
+        kind: ExitKind,
+    ) -> Result<Uuid, TradeError> {
+        let mut attempt: u32 = 0;
+        loop {
+            let purpose = format!("restore_{}_{}", kind.purpose(), attempt);
+            let order_id = trade_signal.order_id(&purpose);
+            match client.get_order_by_client_id(order_id.to_string()).await {
+                Ok(order) if TERMINAL_STATUSES.contains(&order.status.as_ref()) => attempt += 1,
+                Ok(_) | Err(BrokerClientError::OrderNotFound(_)) => return Ok(order_id),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Polls the order until it reaches a terminal status, for at most `ORDER_SETTLE_TIMEOUT`.
+    async fn await_settled(
+        &self,
+        client: &dyn BrokerClient,
+        client_order_id: &str,
+    ) -> Result<Order, TradeError> {
+        let started = Instant::now();
+        loop {
+            let order = client
+                .get_order_by_client_id(client_order_id.to_owned())
+                .await?;
+            if TERMINAL_STATUSES.contains(&order.status.as_ref()) {
+                return Ok(order);
+            }
+            if started.elapsed() >= ORDER_SETTLE_TIMEOUT {
+                return Err(TradeError::OrderPending(order.broker_order_id));
+            }
+            sleep(ORDER_SETTLE_POLL).await;
+        }
+    }
+
+    /// Sends broker request until it succeeds, fails with non retryable error or strategy's
+    /// `max_order_retries` are exhausted. Delay between attempts starts at `order_retry_delay`
+    /// seconds and doubles after every attempt.
+    async fn with_retries<T, F, Fut>(
+        &self,
+        strategy: &Strategy,
+        mut request: F,
+    ) -> Result<T, TradeError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, BrokerClientError>>,
+    {
+        let mut retries: u8 = 0;
+
+        loop {
+            match request().await {
+                Ok(value) => return Ok(value),
+                Err(err) if !err.is_retryable() => return Err(err.into()),
+                Err(err) if retries >= strategy.max_order_retries => {
+                    let attempts = u16::from(retries) + 1;
+                    error!(
+                        "Order max retries reached for strategy {} after {} attempts, error: {}",
+                        strategy.name, attempts, err
+                    );
+                    return Err(TradeError::MaxRetriesReached(attempts, err));
+                }
+                Err(err) => {
+                    let delay = retry_delay(strategy.order_retry_delay, retries);
+                    warn!(
+                        "Order request failed for strategy {}, retrying in {:?}, error: {}",
+                        strategy.name, delay, err
+                    );
+                    sleep(delay).await;
+                    retries += 1;
+
+                    if let Some(halt) = Halt::find_active_for(&self.db, strategy.id).await? {
+                        return Err(TradeError::Halted(halt.describe()));
+                    }
+                }
+            }
+        }
+    }
 }
 
-// #[derive(Debug)]
-// pub struct Order {
-//     pub id: Uuid,
-//     pub ticker: String,
-//     pub order_type: AlertType,
-// }
+/// Rejects entry whose stop is at or beyond the entry price, estimated by the close of the
+/// signal bar, e.g. a long with the stop above the close.
+fn check_stop_side(trade_signal: &TradeSignal, stop: Decimal) -> Result<(), TradeError> {
+    let entry = *trade_signal.bar_data.close.as_ref();
+    let valid = match trade_signal.signal_type.entry_side() {
+        Some(PositionSide::Long) => stop < entry,
+        Some(PositionSide::Short) => stop > entry,
+        None => true,
+    };
+    if !valid {
+        return Err(TradeError::InvalidSignal(format!(
+            "Stop at {} is on the wrong side of {} entry at {} for {}",
+            stop,
+            trade_signal.signal_type.as_ref(),
+            entry,
+            trade_signal.ticker
+        )));
+    }
+
+    Ok(())
+}
+
+fn retry_delay(base_secs: f64, retries: u8) -> Duration {
+    let secs = base_secs.max(0.0) * 2f64.powi(i32::from(retries.min(16)));
+    Duration::try_from_secs_f64(secs)
+        .unwrap_or(MAX_RETRY_DELAY)
+        .min(MAX_RETRY_DELAY)
+}
 
 #[derive(Debug, ThisError)]
 pub enum StrategyManagerError {
@@ -75,12 +1402,31 @@ pub enum StrategyManagerError {
 pub enum TradeError {
     #[error("{0}")]
     InsufficientFunds(String),
-    // #[error("Order max retries reached. {0}")]
-    // MaxRetriesReached(Order),
+    #[error("No open position found for {0}")]
+    NoOpenPosition(String),
+    #[error("{0}")]
+    InvalidSignal(String),
+    #[error("Risk limit breached. {0}")]
+    RiskLimitBreached(String),
+    #[error("{0}")]
+    Halted(String),
+    #[error("Order {0} didn't settle in time")]
+    OrderPending(String),
+    #[error("Order max retries reached after {0} attempts. {1}")]
+    MaxRetriesReached(u16, BrokerClientError),
+    #[error(transparent)]
+    BrokerClientError(#[from] BrokerClientError),
+    #[error(transparent)]
+    DatabaseError(#[from] sqlx::Error),
 }
 
-// impl std::fmt::Display for Order {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         write!(f, "{{ id: {} }}", self.id)
-//     }
-// }
+impl TradeError {
+    /// Whether processing the signal again later may succeed, e.g. once the broker is reachable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::MaxRetriesReached(..) | Self::OrderPending(_) | Self::DatabaseError(_) => true,
+            Self::BrokerClientError(err) => err.is_retryable(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Kill switch stopping `Core` from placing orders, either for all strategies or for one.
+/// Stored so it survives restarts.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Halt {
+    pub halt_id: Uuid,
+    /// Strategy the halt applies to, all strategies if not set.
+    pub strategy_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub lifted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HaltRequest {
+    /// Halts all strategies if not set.
+    pub strategy_id: Option<Uuid>,
+    pub reason: Option<String>,
+    /// Whether to cancel open orders, of the strategy if set.
+    #[serde(default)]
+    pub cancel_orders: bool,
+    /// Whether to close positions, only the strategy's own if set. Open orders are canceled
+    /// first either way.
+    #[serde(default)]
+    pub flatten: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResumeRequest {
+    /// Lifts the global halt if not set.
+    pub strategy_id: Option<Uuid>,
+}
+
+/// Outcome of a halt. Failed cancels and closes are reported rather than failing the halt, which
+/// is in effect either way.
+#[derive(Debug, Clone, Serialize)]
+pub struct HaltReport {
+    pub halt: Halt,
+    pub canceled_orders: Vec<String>,
+    pub closed_positions: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl Halt {
+    /// Stores new halt, or returns the active one for the same scope.
+    pub async fn activate(
+        db: &PgPool,
+        strategy_id: Option<Uuid>,
+        reason: Option<&str>,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO trading_halts (halt_id, strategy_id, reason, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(uuid7::new_v7())
+        .bind(strategy_id)
+        .bind(reason)
+        .execute(db)
+        .await?;
+
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM trading_halts
+            WHERE strategy_id IS NOT DISTINCT FROM $1 AND lifted_at IS NULL
+            "#,
+        )
+        .bind(strategy_id)
+        .fetch_one(db)
+        .await
+    }
+
+    /// Lifts the active halt of the scope. Returns `false` if there is none.
+    pub async fn lift(db: &PgPool, strategy_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE trading_halts
+            SET lifted_at = NOW()
+            WHERE strategy_id IS NOT DISTINCT FROM $1 AND lifted_at IS NULL
+            "#,
+        )
+        .bind(strategy_id)
+        .execute(db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn active(db: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM trading_halts WHERE lifted_at IS NULL ORDER BY created_at")
+            .fetch_all(db)
+            .await
+    }
+
+    /// Active halt stopping the strategy, the global one or its own.
+    pub async fn find_active_for(
+        db: &PgPool,
+        strategy_id: Uuid,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM trading_halts
+            WHERE (strategy_id IS NULL OR strategy_id = $1) AND lifted_at IS NULL
+            ORDER BY strategy_id NULLS FIRST
+            LIMIT 1
+            "#,
+        )
+        .bind(strategy_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    pub fn describe(&self) -> String {
+        let scope = match self.strategy_id {
+            Some(strategy_id) => format!("Strategy {} is halted", strategy_id),
+            None => "Trading is halted".to_owned(),
+        };
+        match &self.reason {
+            Some(reason) => format!("{} - {}", scope, reason),
+            None => scope,
+        }
+    }
+}
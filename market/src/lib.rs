@@ -1,38 +1,122 @@
 pub mod api;
 pub mod app_config;
 pub mod clients;
+pub mod core;
+pub mod halt;
+pub mod metrics;
 pub mod middleware;
+pub mod order;
+pub mod queue;
+pub mod rate_limit;
+pub mod risk;
 pub mod strategy;
-pub mod core;
 pub mod trade_signal;
 
-use std::{error::Error, sync::Arc, time::Duration};
+use std::{
+    error::Error,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
-use apca::{ApiInfo, Client as AlpacaClient};
+use api::objects::Broker;
 use api::*;
-use app_config::AppConfig;
+use app_config::{AppConfig, ConfigDiff, Paper};
 use axum::{
     middleware::{from_fn, from_fn_with_state},
     routing::{delete, get, patch, post},
     Router,
 };
-use clients::Clients;
-use sqlx::{postgres::PgConnectOptions, Error as SqlxError, PgPool};
+use clients::{AlpacaClient, BrokerRegistry, PaperBroker, DEFAULT_ACCOUNT};
 use core::Core;
+use error::ApiError;
+use metrics::Metrics;
+use rate_limit::RateLimiter;
+use sqlx::{postgres::PgConnectOptions, Error as SqlxError, PgPool};
+use strategy::Strategy;
 use tower::ServiceBuilder;
 
 pub struct App {
     pub db: PgPool,
-    pub clients: Arc<Clients>,
+    pub brokers: Arc<BrokerRegistry>,
     pub core: Arc<Core>,
-    pub config: AppConfig,
+    /// Replaced when config is reloaded, see `App::reload_config`.
+    pub config: RwLock<AppConfig>,
+    /// Held while config is reloaded, so concurrent reloads don't diff against the same config.
+    pub reload_lock: tokio::sync::Mutex<()>,
+    pub webhook_limiter: RateLimiter,
+    pub metrics: Metrics,
+}
+
+impl App {
+    /// Validates reloaded config and applies its strategies and risk limits. Strategies are
+    /// changed in a single transaction, so signals see either the old or the new set.
+    pub async fn reload_config(&self, config: AppConfig) -> Result<ConfigDiff, ApiError> {
+        let _reloading = self.reload_lock.lock().await;
+        config
+            .validate()
+            .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        for strategy in config.strategies.iter().filter(|strategy| strategy.enabled) {
+            self.brokers
+                .get(&strategy.broker, &strategy.account)
+                .map_err(|err| {
+                    ApiError::BadRequest(format!("Strategy {} - {}", strategy.name, err))
+                })?;
+        }
+        self.validate_webhook_names(&config).await?;
+
+        let (stored, _) = Strategy::list(&self.db, None, None).await?;
+        let diff = ConfigDiff::new(&self.config.read().unwrap(), &stored, &config);
+        let strategies: Vec<_> = config
+            .strategies
+            .iter()
+            .filter(|strategy| {
+                diff.added_strategies.contains(&strategy.id)
+                    || diff.changed_strategies.contains(&strategy.id)
+            })
+            .collect();
+        Strategy::apply_config(&self.db, &strategies, &diff.removed_strategies).await?;
+        self.core.set_risk_limits(config.risk.clone());
+        *self.config.write().unwrap() = config;
+
+        tracing::info!("Reloaded config: {}", diff);
+        Ok(diff)
+    }
+
+    /// Webhook sources and endpoint slugs share `/webhook/:name`, so names in the reloaded config
+    /// can't be taken by stored strategies it doesn't define. Those it defines are checked by
+    /// `AppConfig::validate`.
+    async fn validate_webhook_names(&self, config: &AppConfig) -> Result<(), ApiError> {
+        let slugs = config
+            .strategies
+            .iter()
+            .filter_map(|strategy| strategy.endpoint.as_ref())
+            .map(|endpoint| (&endpoint.slug, "Webhook endpoint slug"));
+        let sources = config
+            .webhook_sources
+            .keys()
+            .map(|name| (name, "Webhook source"));
+
+        for (name, kind) in slugs.chain(sources) {
+            let Some(other) = Strategy::find_by_slug(&self.db, name).await? else {
+                continue;
+            };
+            if !config.strategies.iter().any(|strategy| strategy.id == other.id) {
+                return Err(ApiError::BadRequest(format!(
+                    "{} {} is the endpoint slug of strategy {}",
+                    kind, name, other.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
-pub async fn build_app(config: AppConfig, clients: Arc<Clients>) -> Result<App, SqlxError> {
+pub async fn build_app(config: AppConfig, brokers: Arc<BrokerRegistry>) -> Result<App, SqlxError> {
     let opts = config.database.url.parse::<PgConnectOptions>()?;
 
     let pool = sqlx::pool::PoolOptions::new()
-        .max_connections(10)
+        .max_connections(config.database.max_connections)
         .acquire_timeout(Duration::from_secs(5))
         .idle_timeout(None)
         .min_connections(1)
@@ -47,31 +131,68 @@ pub async fn build_app(config: AppConfig, clients: Arc<Clients>) -> Result<App,
         }
     }
 
+    if let Err(err) = Strategy::seed(&pool, &config.strategies).await {
+        tracing::error!("failed to seed strategies, error: {:?}", err);
+        std::process::exit(1);
+    }
+
     let app = App {
+        core: Arc::new(Core::new(pool.clone(), config.risk.clone())),
         db: pool,
-        clients,
-        core: Arc::new(Core),
-        config,
+        brokers,
+        config: RwLock::new(config),
+        reload_lock: Default::default(),
+        webhook_limiter: RateLimiter::default(),
+        metrics: Metrics::default(),
     };
 
     Ok(app)
 }
 
-pub fn build_clients(config: &AppConfig) -> Result<Arc<Clients>, Box<dyn Error>> {
-    let alpaca = AlpacaClient::new(ApiInfo::from_parts(
-        &config.brokers.alpaca.apca_api_base_url,
-        &config.brokers.alpaca.apca_api_key_id,
-        &config.brokers.alpaca.apca_api_secret_key,
-    )?);
+/// Registers a client for every broker configured in `config.brokers`.
+pub fn build_brokers(config: &AppConfig) -> Result<Arc<BrokerRegistry>, Box<dyn Error>> {
+    let mut brokers = BrokerRegistry::new();
+
+    for (account, alpaca) in config.brokers.alpaca.iter() {
+        let client = AlpacaClient::new(alpaca)?;
+        brokers.register(Broker::Alpaca, account, Arc::new(client));
+    }
+    if config.brokers.paper.is_empty() {
+        brokers.register(
+            Broker::Paper,
+            DEFAULT_ACCOUNT,
+            Arc::new(PaperBroker::new(Paper::default().starting_cash)),
+        );
+    }
+    for (account, paper) in config.brokers.paper.iter() {
+        brokers.register(
+            Broker::Paper,
+            account,
+            Arc::new(PaperBroker::new(paper.starting_cash)),
+        );
+    }
+    #[cfg(feature = "bybit")]
+    for (account, bybit) in config.brokers.bybit.iter() {
+        brokers.register(
+            Broker::Bybit,
+            account,
+            Arc::new(clients::BybitClient::new(bybit)),
+        );
+    }
 
-    Ok(Arc::new(Clients {
-        alpaca: Arc::new(alpaca),
-    }))
+    for strategy in config.strategies.iter().filter(|strategy| strategy.enabled) {
+        if let Err(err) = brokers.get(&strategy.broker, &strategy.account) {
+            tracing::warn!("strategy {} can't trade: {}", strategy.name, err);
+        }
+    }
+
+    Ok(Arc::new(brokers))
 }
 
 pub fn build_routes(app_state: Arc<App>) -> Router {
     Router::new()
         .route("/webhook", post(handlers::receive_webhook_alert))
+        .route("/webhook/:name", post(handlers::receive_named_alert))
         .route("/account", get(handlers::get_account))
         .route("/activities", post(handlers::get_activities))
         // .route("/asset/:symbol", get(handlers::get_asset)) // NOTE: Algorithmically get assets
@@ -89,7 +210,28 @@ pub fn build_routes(app_state: Arc<App>) -> Router {
         //     get(handlers::get_position).delete(handlers::delete_position),
         // ) // NOTE: Get specific position algorithmically
         .route("/positions", get(handlers::get_positions))
+        .route(
+            "/strategies",
+            get(handlers::get_strategies).post(handlers::create_strategy),
+        )
+        .route(
+            "/strategy/:id",
+            get(handlers::get_strategy)
+                .patch(handlers::update_strategy)
+                .delete(handlers::delete_strategy),
+        )
+        .route("/strategy/:id/enable", post(handlers::enable_strategy))
+        .route("/strategy/:id/disable", post(handlers::disable_strategy))
         .route("/health", get(handlers::check_health))
+        .route("/admin/reload", post(handlers::reload_config))
+        .route("/admin/halt", post(handlers::halt_trading))
+        .route("/admin/resume", post(handlers::resume_trading))
+        .route("/admin/halts", get(handlers::get_halts))
+        .route("/jobs", get(handlers::get_jobs))
+        .route("/lanes", get(handlers::get_lanes))
+        .route("/metrics", get(handlers::get_metrics))
+        .route("/job/:id", get(handlers::get_job))
+        .route("/job/:id/replay", post(handlers::replay_job))
         .layer(
             ServiceBuilder::new()
                 .layer(from_fn_with_state(app_state.clone(), middleware::auth))
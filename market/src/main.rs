@@ -4,7 +4,7 @@ use std::{
     sync::Arc,
 };
 
-use market::{app_config::AppConfig, build_clients, build_routes, build_app, App};
+use market::{app_config::AppConfig, build_app, build_brokers, build_routes, queue, App};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -14,11 +14,30 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Build apps config
     let config = AppConfig::build()?;
 
-    // Initialize clients
-    let clients = build_clients(&config)?;
+    // Initialize broker clients
+    let brokers = build_brokers(&config)?;
 
     // Build app state
-    let app: Arc<App> = build_app(config, clients).await?.into();
+    let app: Arc<App> = build_app(config, brokers).await?.into();
+
+    // Start background tasks
+    let core = Arc::clone(&app.core);
+    let brokers = Arc::clone(&app.brokers);
+    tokio::spawn(async move {
+        if let Err(err) = core.run(brokers).await {
+            tracing::error!("Core stopped, error: {:?}", err);
+        }
+    });
+
+    let queue_settings = app.config.read().unwrap().queue.clone();
+    for _ in 0..queue_settings.workers {
+        tokio::spawn(queue::run_worker(
+            app.db.clone(),
+            Arc::clone(&app.core),
+            Arc::clone(&app.brokers),
+            queue_settings.clone(),
+        ));
+    }
 
     // Start server
     let routes = build_routes(app);
@@ -0,0 +1,124 @@
+use std::{fmt::Write, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::queue::SignalJob;
+
+/// Upper bounds of the alert latency buckets in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0];
+
+/// Metrics kept in memory, they start over when the server restarts.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    alert_latency: Mutex<Histogram>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    /// Records the time from the source firing an accepted alert to the server receiving it.
+    /// Alerts fired ahead of the server clock have a negative latency.
+    pub fn observe_alert_latency(&self, fired_at: DateTime<Utc>, received_at: DateTime<Utc>) {
+        let latency = (received_at - fired_at).num_milliseconds() as f64 / 1000.0;
+        let mut histogram = self.alert_latency.lock().unwrap();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| latency <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += latency;
+        histogram.count += 1;
+    }
+
+    /// Renders metrics in the Prometheus text format, served at `GET /metrics`.
+    pub async fn render(&self, db: &PgPool) -> Result<String, sqlx::Error> {
+        let mut text = render_lanes(db).await?;
+        self.render_alert_latency(&mut text);
+        Ok(text)
+    }
+
+    fn render_alert_latency(&self, text: &mut String) {
+        let histogram = self.alert_latency.lock().unwrap();
+        writeln!(
+            text,
+            "# HELP market_alert_latency_seconds Time from an accepted alert firing to its receipt."
+        )
+        .ok();
+        writeln!(text, "# TYPE market_alert_latency_seconds histogram").ok();
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            writeln!(
+                text,
+                "market_alert_latency_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            )
+            .ok();
+        }
+        writeln!(
+            text,
+            "market_alert_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        )
+        .ok();
+        writeln!(text, "market_alert_latency_seconds_sum {}", histogram.sum).ok();
+        writeln!(
+            text,
+            "market_alert_latency_seconds_count {}",
+            histogram.count
+        )
+        .ok();
+    }
+}
+
+async fn render_lanes(db: &PgPool) -> Result<String, sqlx::Error> {
+    let lanes = SignalJob::lane_depths(db).await?;
+    let now = Utc::now();
+
+    let mut text = String::new();
+    writeln!(
+        text,
+        "# HELP market_signal_lane_depth Pending signal jobs of the lane."
+    )
+    .ok();
+    writeln!(text, "# TYPE market_signal_lane_depth gauge").ok();
+    for lane in &lanes {
+        writeln!(
+            text,
+            "market_signal_lane_depth{{lane=\"{}\"}} {}",
+            escape_label(&lane.lane),
+            lane.depth
+        )
+        .ok();
+    }
+    writeln!(
+        text,
+        "# HELP market_signal_lane_oldest_seconds Age of the oldest pending signal job of the lane."
+    )
+    .ok();
+    writeln!(text, "# TYPE market_signal_lane_oldest_seconds gauge").ok();
+    for lane in &lanes {
+        let age = (now - lane.oldest_created_at).num_milliseconds() as f64 / 1000.0;
+        writeln!(
+            text,
+            "market_signal_lane_oldest_seconds{{lane=\"{}\"}} {}",
+            escape_label(&lane.lane),
+            age.max(0.0)
+        )
+        .ok();
+    }
+
+    Ok(text)
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
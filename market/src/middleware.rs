@@ -2,29 +2,75 @@ use std::{io::Write, sync::Arc};
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{header, Request, StatusCode},
+    extract::{Query, State},
+    http::{header, request::Parts, HeaderMap, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use chrono::{DateTime, FixedOffset, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use uuid::Uuid;
 
-use crate::{error::ApiError, App};
+use crate::{error::ApiError, payload::PayloadMapping, strategy::Strategy, App};
 
-pub async fn auth<B>(
+/// Header carrying hex encoded HMAC-SHA256 of the request body, for webhook sources able to sign
+/// requests. TradingView can't, so it sends the strategy passphrase in the alert body instead.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-signature";
+
+/// Header carrying the token of a strategy's webhook endpoint, which can also be sent in the
+/// `token` query parameter.
+pub const WEBHOOK_TOKEN_HEADER: &str = "x-webhook-token";
+
+pub async fn auth(
     State(app): State<Arc<App>>,
-    req: Request<B>,
-    next: Next<B>,
+    req: Request<Body>,
+    next: Next<Body>,
 ) -> Result<impl IntoResponse, ApiError> {
-    // NOTE: skip auth for post /alert endpoint. We don't need to check auth for tradingview
-    // webhook as we use it's own secret key
-    if req.uri().path() == "/webhook" && req.method() == axum::http::Method::POST {
-        return Ok(next.run(req).await);
+    // NOTE: webhooks can't send our API key, they are authenticated by the secrets of the strategy
+    // they target
+    let path = req.uri().path();
+    if (path == "/webhook" || path.starts_with("/webhook/"))
+        && req.method() == axum::http::Method::POST
+    {
+        let (parts, body) = req.into_parts();
+        let bytes = hyper::body::to_bytes(body)
+            .await
+            .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+
+        match parts.uri.path().strip_prefix("/webhook/") {
+            Some(name) => {
+                let mapping = app
+                    .config
+                    .read()
+                    .unwrap()
+                    .webhook_sources
+                    .get(name)
+                    .cloned();
+                match mapping {
+                    Some(mapping) => {
+                        let credentials = source_credentials(&mapping, &bytes);
+                        authenticate_webhook(&app, credentials, &parts.headers, &bytes).await?;
+                    }
+                    None => authenticate_endpoint(&app, name, &parts).await?,
+                }
+            }
+            None => {
+                let credentials = serde_json::from_slice(&bytes).ok();
+                authenticate_webhook(&app, credentials, &parts.headers, &bytes).await?;
+            }
+        }
+
+        return Ok(next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await);
     }
 
+    let api_key = app.config.read().unwrap().api_key.clone();
     if let Some(auth_value) = req.headers().get(header::AUTHORIZATION) {
-        if let Ok(header) = header::HeaderValue::from_str(&app.config.api_key) {
+        if let Ok(header) = header::HeaderValue::from_str(&api_key) {
             if auth_value == header {
                 return Ok(next.run(req).await);
             }
@@ -36,6 +82,135 @@ pub async fn auth<B>(
     ))
 }
 
+#[derive(Deserialize)]
+struct EndpointQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WebhookCredentials {
+    strategy_id: Uuid,
+    passphrase: Option<String>,
+}
+
+/// Credentials in the payload of a configured webhook source, found by its mapping.
+fn source_credentials(mapping: &PayloadMapping, body: &[u8]) -> Option<WebhookCredentials> {
+    let payload = serde_json::from_slice(body).ok()?;
+    let alert_data = mapping.apply(&payload, Utc::now()).ok()?;
+
+    Some(WebhookCredentials {
+        strategy_id: alert_data.strategy_id,
+        passphrase: alert_data.passphrase,
+    })
+}
+
+/// Accepts webhook if it carries the passphrase of its strategy or, when the strategy has an HMAC
+/// secret, a valid body signature. Payloads which can't be attributed to a known strategy are left
+/// to the handler, which rejects and records them.
+async fn authenticate_webhook(
+    app: &App,
+    credentials: Option<WebhookCredentials>,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), ApiError> {
+    let Some(credentials) = credentials else {
+        return Ok(());
+    };
+    let Some(strategy) = Strategy::find(&app.db, credentials.strategy_id).await? else {
+        return Ok(());
+    };
+
+    let passphrase_valid = credentials.passphrase.is_some_and(|passphrase| {
+        constant_time_eq(
+            passphrase.as_bytes(),
+            strategy.webhook_passphrase.as_bytes(),
+        )
+    });
+
+    let signature = headers
+        .get(WEBHOOK_SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| hex::decode(value).ok());
+    let signature_valid = match (&strategy.webhook_hmac_secret, signature) {
+        (Some(secret), Some(signature)) => Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map(|mac| mac.chain_update(body).verify_slice(&signature).is_ok())
+            .unwrap_or(false),
+        _ => false,
+    };
+
+    if passphrase_valid || signature_valid {
+        return Ok(());
+    }
+
+    let source = headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown");
+    tracing::warn!(
+        target: "security",
+        strategy_id = %strategy.id,
+        source,
+        "Rejected webhook with invalid passphrase or signature"
+    );
+
+    Err(ApiError::Unauthorized(
+        "Webhook passphrase or signature isn't correct or not found".to_string(),
+    ))
+}
+
+/// Accepts alerts at the webhook endpoint of a strategy if they carry the endpoint's token.
+/// Unknown endpoints are left to the handler.
+async fn authenticate_endpoint(app: &App, slug: &str, parts: &Parts) -> Result<(), ApiError> {
+    let Some(endpoint) = Strategy::find_by_slug(&app.db, slug)
+        .await?
+        .and_then(|strategy| strategy.endpoint)
+    else {
+        return Ok(());
+    };
+
+    let token = match parts.headers.get(WEBHOOK_TOKEN_HEADER) {
+        Some(value) => value.to_str().ok().map(str::to_owned),
+        None => Query::<EndpointQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|query| query.0.token),
+    };
+    if token.is_some_and(|token| constant_time_eq(token.as_bytes(), endpoint.token.as_bytes())) {
+        return Ok(());
+    }
+
+    let source = parts
+        .headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown");
+    tracing::warn!(
+        target: "security",
+        endpoint = slug,
+        source,
+        "Rejected webhook with invalid endpoint token"
+    );
+
+    Err(ApiError::Unauthorized(
+        "Webhook endpoint token isn't correct or not found".to_string(),
+    ))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Strategy passphrase and webhook secrets of strategy API requests must not end up in logs.
+fn redact_secrets(json: &mut serde_json::Value) {
+    for field in ["passphrase", "webhook_passphrase", "webhook_hmac_secret"] {
+        if let Some(secret) = json.get_mut(field) {
+            *secret = serde_json::Value::String("********".to_string());
+        }
+    }
+    if let Some(token) = json.pointer_mut("/endpoint/token") {
+        *token = serde_json::Value::String("********".to_string());
+    }
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct JsonResponse {
     message: String,
@@ -53,7 +228,10 @@ pub async fn log_request(
     if method == axum::http::Method::POST {
         bytes = body_to_bytes(body).await?;
         body_string = match serde_json::from_slice::<serde_json::Value>(&bytes) {
-            Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_default(),
+            Ok(mut json) => {
+                redact_secrets(&mut json);
+                serde_json::to_string_pretty(&json).unwrap_or_default()
+            }
             Err(_) => String::from_utf8_lossy(&bytes).into_owned(),
         };
     }
@@ -1 +1,835 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use strum_macros::{AsRefStr, EnumString};
+use uuid::Uuid;
 
+use crate::{
+    api::{
+        alert::PositionSide,
+        objects::{Order, OrderStatus},
+    },
+    clients::BrokerAccount,
+    strategy::{CurrencyType, Strategy},
+    trade_signal::TradeSignal,
+};
+
+/// Statuses after which order can't change anymore. Orders the broker doesn't know are
+/// `unknown`.
+pub const TERMINAL_STATUSES: &[&str] = &[
+    "filled", "canceled", "expired", "replaced", "rejected", "failed", "unknown",
+];
+
+/// Entry order parameters computed by `Core` from a trade signal. Stop loss and take profit are
+/// only set when the broker attaches them to the entry natively.
+#[derive(Debug, Clone)]
+pub struct EntryOrder {
+    pub client_order_id: String,
+    pub quantity: Decimal,
+    pub stop_loss: Option<Decimal>,
+    pub take_profit: Option<Decimal>,
+}
+
+/// Order closing the position, placed separately from the entry.
+#[derive(Debug, Clone)]
+pub struct ExitOrder {
+    pub client_order_id: String,
+    pub ticker: String,
+    /// Side of the position being closed.
+    pub position_side: PositionSide,
+    pub quantity: Decimal,
+    pub currency_type: CurrencyType,
+    pub kind: ExitKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitKind {
+    StopLoss(Decimal),
+    TakeProfit(Decimal),
+    /// Reduce only market order, e.g. for a partial close.
+    Market,
+}
+
+impl ExitKind {
+    /// Purpose the id of the exit order is derived from.
+    pub fn purpose(&self) -> &'static str {
+        match self {
+            ExitKind::StopLoss(_) => "stop_loss",
+            ExitKind::TakeProfit(_) => "take_profit",
+            ExitKind::Market => "close",
+        }
+    }
+}
+
+/// Alert, strategy and broker account an order originates from.
+struct OrderOrigin<'a> {
+    alert_id: Option<Uuid>,
+    strategy_id: Uuid,
+    broker: &'a str,
+    account: &'a str,
+}
+
+impl<'a> From<&'a TradeSignal> for OrderOrigin<'a> {
+    fn from(trade_signal: &'a TradeSignal) -> Self {
+        Self {
+            alert_id: Some(trade_signal.alert_id),
+            strategy_id: trade_signal.strategy.id,
+            broker: trade_signal.strategy.broker.as_ref(),
+            account: &trade_signal.strategy.account,
+        }
+    }
+}
+
+impl<'a> From<&'a OrderRecord> for OrderOrigin<'a> {
+    fn from(record: &'a OrderRecord) -> Self {
+        Self {
+            alert_id: record.alert_id,
+            strategy_id: record.strategy_id,
+            broker: &record.broker,
+            account: &record.account,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderEvent {
+    Submitted,
+    Updated,
+    Canceled,
+    Filled,
+    Failed,
+}
+
+/// Order placed by `Core`, linked to the alert and strategy it originates from.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OrderRecord {
+    pub order_id: Uuid,
+    pub parent_order_id: Option<Uuid>,
+    pub alert_id: Option<Uuid>,
+    pub strategy_id: Uuid,
+    pub broker: String,
+    /// Name of the broker account the order is placed on.
+    pub account: String,
+    pub broker_order_id: Option<String>,
+    pub client_order_id: String,
+    pub ticker: String,
+    pub side: String,
+    pub order_type: String,
+    pub quantity: Option<Decimal>,
+    pub limit_price: Option<Decimal>,
+    pub stop_price: Option<Decimal>,
+    pub status: String,
+    pub filled_quantity: Decimal,
+    pub average_fill_price: Option<Decimal>,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+impl OrderRecord {
+    /// Records order accepted by the broker together with its legs. Orders already recorded by
+    /// an earlier processing of the same signal are left as they are, unless that processing
+    /// failed to place them.
+    pub async fn insert_submitted(
+        db: &PgPool,
+        trade_signal: &TradeSignal,
+        order_id: Uuid,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+
+        let origin = OrderOrigin::from(trade_signal);
+        if !insert(&mut tx, order_id, None, &origin, order).await? {
+            return tx.commit().await;
+        }
+        for leg in &order.legs {
+            insert(&mut tx, uuid7::new_v7(), Some(order_id), &origin, leg).await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Records order placed by `Core` for the strategy without an alert, e.g. when a halt closes
+    /// its positions.
+    pub async fn insert_for_strategy(
+        db: &PgPool,
+        strategy: &Strategy,
+        order_id: Uuid,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        let origin = OrderOrigin {
+            alert_id: None,
+            strategy_id: strategy.id,
+            broker: strategy.broker.as_ref(),
+            account: &strategy.account,
+        };
+
+        let mut tx = db.begin().await?;
+        insert(&mut tx, order_id, None, &origin, order).await?;
+        tx.commit().await
+    }
+
+    /// Records order placed by `Core` on behalf of this one, e.g. exits of an emulated bracket.
+    pub async fn insert_child(
+        &self,
+        db: &PgPool,
+        order_id: Uuid,
+        order: &Order,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+        insert(
+            &mut tx,
+            order_id,
+            Some(self.order_id),
+            &OrderOrigin::from(self),
+            order,
+        )
+        .await?;
+        tx.commit().await
+    }
+
+    pub async fn find(db: &PgPool, order_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM orders WHERE order_id = $1")
+            .bind(order_id)
+            .fetch_optional(db)
+            .await
+    }
+
+    /// Records order which never reached the broker, e.g. because it was rejected or retries
+    /// were exhausted.
+    pub async fn insert_failed(
+        db: &PgPool,
+        trade_signal: &TradeSignal,
+        order_id: Uuid,
+        side: &str,
+        quantity: Decimal,
+        reason: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO orders (
+                order_id,
+                alert_id,
+                strategy_id,
+                broker,
+                account,
+                client_order_id,
+                ticker,
+                side,
+                order_type,
+                quantity,
+                status,
+                created_at,
+                modified_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'market', $9, 'failed', NOW(), NOW())
+            ON CONFLICT (order_id) DO NOTHING
+            "#,
+        )
+        .bind(order_id)
+        .bind(trade_signal.alert_id)
+        .bind(trade_signal.strategy.id)
+        .bind(trade_signal.strategy.broker.as_ref())
+        .bind(&trade_signal.strategy.account)
+        .bind(order_id.to_string())
+        .bind(&trade_signal.ticker)
+        .bind(side)
+        .bind(quantity)
+        .execute(&mut *tx)
+        .await?;
+
+        insert_transition(
+            &mut tx,
+            order_id,
+            OrderEvent::Failed,
+            None,
+            "failed",
+            Some(reason),
+        )
+        .await?;
+
+        tx.commit().await
+    }
+
+    pub async fn find_by_broker_order_id(
+        db: &PgPool,
+        account: &BrokerAccount,
+        broker_order_id: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM orders WHERE broker = $1 AND account = $2 AND broker_order_id = $3",
+        )
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(broker_order_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Latest open stop order of the strategy on the broker account for the ticker, including
+    /// stop legs of brackets.
+    pub async fn open_stop(
+        db: &PgPool,
+        strategy_id: Uuid,
+        account: &BrokerAccount,
+        ticker: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM orders
+            WHERE strategy_id = $1
+                AND broker = $2
+                AND account = $3
+                AND ticker = $4
+                AND order_type IN ('stop', 'stop_limit')
+                AND status <> ALL($5)
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(strategy_id)
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(ticker)
+        .bind(TERMINAL_STATUSES)
+        .fetch_optional(db)
+        .await
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        TERMINAL_STATUSES.contains(&self.status.as_str())
+    }
+
+    /// Side of the position the order opens. Exit orders are on the opposite side of the
+    /// position they close.
+    pub fn position_side(&self) -> PositionSide {
+        match self.side.as_str() {
+            "sell" => PositionSide::Short,
+            _ => PositionSide::Long,
+        }
+    }
+
+    /// Orders of the broker account which can still change their status.
+    pub async fn open(db: &PgPool, account: &BrokerAccount) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM orders WHERE broker = $1 AND account = $2 AND status <> ALL($3)",
+        )
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(TERMINAL_STATUSES)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Orders of the strategy on the broker account which can still change their status.
+    pub async fn open_for_strategy(
+        db: &PgPool,
+        strategy_id: Uuid,
+        account: &BrokerAccount,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM orders
+            WHERE strategy_id = $1 AND broker = $2 AND account = $3 AND status <> ALL($4)
+            "#,
+        )
+        .bind(strategy_id)
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(TERMINAL_STATUSES)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Net position of the strategy on the broker account per ticker, from the fills of its
+    /// orders. Negative for short positions, tickers without a position are left out.
+    pub async fn positions(
+        db: &PgPool,
+        strategy_id: Uuid,
+        account: &BrokerAccount,
+    ) -> Result<Vec<(String, Decimal)>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT ticker, SUM(filled_quantity * CASE side WHEN 'buy' THEN 1 ELSE -1 END)
+            FROM orders
+            WHERE strategy_id = $1 AND broker = $2 AND account = $3
+            GROUP BY ticker
+            HAVING SUM(filled_quantity * CASE side WHEN 'buy' THEN 1 ELSE -1 END) <> 0
+            "#,
+        )
+        .bind(strategy_id)
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Event describing how broker's view of the order differs from the local record, `None` if
+    /// nothing changed.
+    pub fn change_event(&self, order: &Order) -> Option<OrderEvent> {
+        if order.filled_quantity > self.filled_quantity {
+            Some(OrderEvent::Filled)
+        } else if order.status == OrderStatus::Canceled && self.status != "canceled" {
+            Some(OrderEvent::Canceled)
+        } else if order.status.as_ref() != self.status
+            || order.stop_price != self.stop_price
+            || order.limit_price != self.limit_price
+            || Some(&order.broker_order_id) != self.broker_order_id.as_ref()
+            || order.client_order_id != self.client_order_id
+        {
+            Some(OrderEvent::Updated)
+        } else {
+            None
+        }
+    }
+
+    /// Applies broker's view of the order to the local record and logs the transition. Fills are
+    /// derived from the change of filled quantity. Ids follow the order, so a record of an order
+    /// replaced by the broker tracks the replacement.
+    pub async fn apply(
+        &self,
+        db: &PgPool,
+        order: &Order,
+        event: OrderEvent,
+        details: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET broker_order_id = $2,
+                status = $3,
+                limit_price = $4,
+                stop_price = $5,
+                filled_quantity = $6,
+                average_fill_price = $7,
+                client_order_id = $8,
+                modified_at = NOW()
+            WHERE order_id = $1
+            "#,
+        )
+        .bind(self.order_id)
+        .bind(&order.broker_order_id)
+        .bind(order.status.as_ref())
+        .bind(order.limit_price)
+        .bind(order.stop_price)
+        .bind(order.filled_quantity)
+        .bind(order.average_fill_price)
+        .bind(&order.client_order_id)
+        .execute(&mut *tx)
+        .await?;
+
+        insert_transition(
+            &mut tx,
+            self.order_id,
+            event,
+            Some(&self.status),
+            order.status.as_ref(),
+            details,
+        )
+        .await?;
+        insert_fill(
+            &mut tx,
+            self.order_id,
+            (self.filled_quantity, self.average_fill_price),
+            order,
+        )
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Changes status of the order without broker's view of it, e.g. after a cancel request.
+    pub async fn set_status(
+        &self,
+        db: &PgPool,
+        status: &str,
+        event: OrderEvent,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query("UPDATE orders SET status = $2, modified_at = NOW() WHERE order_id = $1")
+            .bind(self.order_id)
+            .bind(status)
+            .execute(&mut *tx)
+            .await?;
+
+        insert_transition(
+            &mut tx,
+            self.order_id,
+            event,
+            Some(&self.status),
+            status,
+            None,
+        )
+        .await?;
+
+        tx.commit().await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum BracketStatus {
+    /// Waiting for the entry to fill.
+    Pending,
+    /// Exits are placed, waiting for one of them to fill.
+    Active,
+    /// Take profit price was reached on a broker without reduce only exits, the stop loss is
+    /// being canceled before the position is closed at market.
+    TakingProfit,
+    Closed,
+}
+
+/// Bracket managed by `Core` for brokers or assets without native bracket orders. Exits are
+/// placed once the entry is filled and the remaining exit is canceled once the other one fills.
+#[derive(Debug, Clone, FromRow)]
+pub struct EmulatedBracket {
+    pub entry_order_id: Uuid,
+    pub stop_loss_order_id: Option<Uuid>,
+    pub take_profit_order_id: Option<Uuid>,
+    pub currency_type: String,
+    pub stop_price: Decimal,
+    pub take_profit_price: Option<Decimal>,
+    pub status: String,
+}
+
+impl EmulatedBracket {
+    pub fn status(&self) -> BracketStatus {
+        self.status.parse().unwrap_or(BracketStatus::Closed)
+    }
+
+    pub fn currency_type(&self) -> CurrencyType {
+        self.currency_type.parse().unwrap_or(CurrencyType::Stock)
+    }
+
+    pub async fn insert(
+        db: &PgPool,
+        entry_order_id: Uuid,
+        currency_type: &CurrencyType,
+        stop_price: Decimal,
+        take_profit_price: Option<Decimal>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO emulated_brackets (
+                entry_order_id,
+                currency_type,
+                stop_price,
+                take_profit_price,
+                status,
+                created_at,
+                modified_at
+            )
+            VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
+            ON CONFLICT (entry_order_id) DO NOTHING
+            "#,
+        )
+        .bind(entry_order_id)
+        .bind(currency_type.as_ref())
+        .bind(stop_price)
+        .bind(take_profit_price)
+        .bind(BracketStatus::Pending.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Brackets of the broker account which aren't closed yet.
+    pub async fn open(db: &PgPool, account: &BrokerAccount) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT brackets.*
+            FROM emulated_brackets brackets
+            JOIN orders ON orders.order_id = brackets.entry_order_id
+            WHERE orders.broker = $1 AND orders.account = $2 AND brackets.status <> $3
+            "#,
+        )
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(BracketStatus::Closed.as_ref())
+        .fetch_all(db)
+        .await
+    }
+
+    pub async fn activate(
+        &self,
+        db: &PgPool,
+        stop_loss_order_id: Uuid,
+        take_profit_order_id: Option<Uuid>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE emulated_brackets
+            SET stop_loss_order_id = $2,
+                take_profit_order_id = $3,
+                status = $4,
+                modified_at = NOW()
+            WHERE entry_order_id = $1
+            "#,
+        )
+        .bind(self.entry_order_id)
+        .bind(stop_loss_order_id)
+        .bind(take_profit_order_id)
+        .bind(BracketStatus::Active.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Closes brackets of the strategy's entries in the ticker, so no more exits are placed for
+    /// them.
+    pub async fn close_for_ticker(
+        db: &PgPool,
+        strategy_id: Uuid,
+        account: &BrokerAccount,
+        ticker: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE emulated_brackets brackets
+            SET status = $5, modified_at = NOW()
+            FROM orders
+            WHERE orders.order_id = brackets.entry_order_id
+                AND orders.strategy_id = $1
+                AND orders.broker = $2
+                AND orders.account = $3
+                AND orders.ticker = $4
+                AND brackets.status <> $5
+            "#,
+        )
+        .bind(strategy_id)
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(ticker)
+        .bind(BracketStatus::Closed.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Placed stop loss and take profit orders of the bracket.
+    pub async fn exits(&self, db: &PgPool) -> Result<Vec<OrderRecord>, sqlx::Error> {
+        let mut exits = Vec::new();
+        for order_id in [self.stop_loss_order_id, self.take_profit_order_id]
+            .into_iter()
+            .flatten()
+        {
+            exits.extend(OrderRecord::find(db, order_id).await?);
+        }
+        Ok(exits)
+    }
+
+    /// Closes brackets on the broker account, only of the strategy's entries if set.
+    pub async fn close_for_account(
+        db: &PgPool,
+        account: &BrokerAccount,
+        strategy_id: Option<Uuid>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE emulated_brackets brackets
+            SET status = $4, modified_at = NOW()
+            FROM orders
+            WHERE orders.order_id = brackets.entry_order_id
+                AND orders.broker = $1
+                AND orders.account = $2
+                AND ($3::uuid IS NULL OR orders.strategy_id = $3)
+                AND brackets.status <> $4
+            "#,
+        )
+        .bind(account.broker.as_ref())
+        .bind(&account.account)
+        .bind(strategy_id)
+        .bind(BracketStatus::Closed.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn close(&self, db: &PgPool) -> Result<(), sqlx::Error> {
+        self.set_status(db, BracketStatus::Closed).await
+    }
+
+    pub async fn set_status(&self, db: &PgPool, status: BracketStatus) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE emulated_brackets
+            SET status = $2, modified_at = NOW()
+            WHERE entry_order_id = $1
+            "#,
+        )
+        .bind(self.entry_order_id)
+        .bind(status.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+async fn insert(
+    tx: &mut Transaction<'_, Postgres>,
+    order_id: Uuid,
+    parent_order_id: Option<Uuid>,
+    origin: &OrderOrigin<'_>,
+    order: &Order,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO orders (
+            order_id,
+            parent_order_id,
+            alert_id,
+            strategy_id,
+            broker,
+            account,
+            broker_order_id,
+            client_order_id,
+            ticker,
+            side,
+            order_type,
+            quantity,
+            limit_price,
+            stop_price,
+            status,
+            filled_quantity,
+            average_fill_price,
+            created_at,
+            modified_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, NOW(),
+            NOW()
+        )
+        ON CONFLICT (order_id) DO UPDATE
+        SET broker_order_id = EXCLUDED.broker_order_id,
+            client_order_id = EXCLUDED.client_order_id,
+            order_type = EXCLUDED.order_type,
+            quantity = EXCLUDED.quantity,
+            limit_price = EXCLUDED.limit_price,
+            stop_price = EXCLUDED.stop_price,
+            status = EXCLUDED.status,
+            filled_quantity = EXCLUDED.filled_quantity,
+            average_fill_price = EXCLUDED.average_fill_price,
+            modified_at = NOW()
+        WHERE orders.status = 'failed'
+        "#,
+    )
+    .bind(order_id)
+    .bind(parent_order_id)
+    .bind(origin.alert_id)
+    .bind(origin.strategy_id)
+    .bind(origin.broker)
+    .bind(origin.account)
+    .bind(&order.broker_order_id)
+    .bind(&order.client_order_id)
+    .bind(&order.symbol)
+    .bind(order.side.as_ref())
+    .bind(order.order_type.as_ref())
+    .bind(order.quantity)
+    .bind(order.limit_price)
+    .bind(order.stop_price)
+    .bind(order.status.as_ref())
+    .bind(order.filled_quantity)
+    .bind(order.average_fill_price)
+    .execute(&mut **tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_transition(
+        tx,
+        order_id,
+        OrderEvent::Submitted,
+        None,
+        order.status.as_ref(),
+        None,
+    )
+    .await?;
+    insert_fill(tx, order_id, (Decimal::ZERO, None), order).await?;
+
+    Ok(true)
+}
+
+async fn insert_transition(
+    tx: &mut Transaction<'_, Postgres>,
+    order_id: Uuid,
+    event: OrderEvent,
+    from_status: Option<&str>,
+    to_status: &str,
+    details: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO order_transitions (
+            order_transition_id,
+            order_id,
+            event,
+            from_status,
+            to_status,
+            details,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, NOW())
+        "#,
+    )
+    .bind(uuid7::new_v7())
+    .bind(order_id)
+    .bind(event.as_ref())
+    .bind(from_status)
+    .bind(to_status)
+    .bind(details)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the part of the order filled since the previously known `(filled_quantity,
+/// average_fill_price)`. Price of the new fill is derived from the change of the average price.
+async fn insert_fill(
+    tx: &mut Transaction<'_, Postgres>,
+    order_id: Uuid,
+    (prev_quantity, prev_average_price): (Decimal, Option<Decimal>),
+    order: &Order,
+) -> Result<(), sqlx::Error> {
+    let quantity = order.filled_quantity - prev_quantity;
+    let Some(average_price) = order.average_fill_price else {
+        return Ok(());
+    };
+    if quantity <= Decimal::ZERO {
+        return Ok(());
+    }
+
+    let prev_notional = prev_quantity * prev_average_price.unwrap_or_default();
+    let price = (order.filled_quantity * average_price - prev_notional) / quantity;
+
+    sqlx::query(
+        r#"
+        INSERT INTO fills (fill_id, order_id, quantity, price, filled_at, created_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        "#,
+    )
+    .bind(uuid7::new_v7())
+    .bind(order_id)
+    .bind(quantity)
+    .bind(price)
+    .bind(order.filled_at.unwrap_or_else(Utc::now))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgExecutor, PgPool};
+use strum_macros::{AsRefStr, EnumString};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    alert::WebhookAlertData, clients::BrokerRegistry, core::Core, error::ApiError,
+    trade_signal::TradeSignal,
+};
+
+/// Signal queue settings, e.g. in config:
+/// `queue = { workers = 4, max_attempts = 5, retry_delay = 5 }`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct QueueSettings {
+    /// Number of jobs processed concurrently. Each worker keeps a database connection while
+    /// processing a job, so less than half of `database.max_connections` are allowed.
+    pub workers: usize,
+    /// Attempts after which a failing job is dead-lettered.
+    pub max_attempts: i32,
+    /// Delay before the first retry of a failed job in seconds, doubled on every further retry.
+    pub retry_delay: f64,
+    /// How often idle workers look for new jobs in milliseconds.
+    pub poll_interval: u64,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            max_attempts: 5,
+            retry_delay: 5.0,
+            poll_interval: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting to be processed, possibly for a retry.
+    Pending,
+    Done,
+    /// Failed for good, kept until replayed.
+    Dead,
+}
+
+/// Accepted alert queued for `Core`.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SignalJob {
+    pub job_id: Uuid,
+    pub alert_id: Uuid,
+    /// Alert without its passphrase.
+    pub payload: Json<WebhookAlertData>,
+    /// Broker account and ticker of the signal, see `TradeSignal::lane`.
+    pub lane: String,
+    pub bar_time: DateTime<Utc>,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+/// Backlog of a lane.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LaneDepth {
+    pub lane: String,
+    pub depth: i64,
+    pub oldest_created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobsQuery {
+    pub status: Option<JobStatus>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl SignalJob {
+    /// Queues the alert in its lane, meant to run in the transaction storing it.
+    pub async fn enqueue<'c, E>(
+        db: E,
+        alert_id: Uuid,
+        alert_data: &WebhookAlertData,
+        lane: &str,
+        max_attempts: i32,
+    ) -> Result<Uuid, sqlx::Error>
+    where
+        E: PgExecutor<'c>,
+    {
+        let job_id = uuid7::new_v7();
+        let payload = WebhookAlertData {
+            passphrase: None,
+            ..alert_data.clone()
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO signal_jobs (
+                job_id,
+                alert_id,
+                payload,
+                lane,
+                bar_time,
+                status,
+                max_attempts,
+                run_at,
+                created_at,
+                modified_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), NOW(), NOW())
+            "#,
+        )
+        .bind(job_id)
+        .bind(alert_id)
+        .bind(Json(&payload))
+        .bind(lane)
+        .bind(payload.bar_data.time)
+        .bind(JobStatus::Pending.as_ref())
+        .bind(max_attempts)
+        .execute(db)
+        .await?;
+
+        Ok(job_id)
+    }
+
+    pub async fn find(db: &PgPool, job_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM signal_jobs WHERE job_id = $1")
+            .bind(job_id)
+            .fetch_optional(db)
+            .await
+    }
+
+    /// Jobs with the status, all if not set, newest first, and their total number.
+    pub async fn list(
+        db: &PgPool,
+        status: Option<JobStatus>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<(Vec<Self>, i64), sqlx::Error> {
+        let status = status.map(|status| status.as_ref().to_owned());
+        let jobs = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM signal_jobs
+            WHERE $1::Text IS NULL OR status = $1
+            ORDER BY created_at DESC
+            OFFSET $2
+            LIMIT $3
+            "#,
+        )
+        .bind(&status)
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(db)
+        .await?;
+        let total = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM signal_jobs WHERE $1::Text IS NULL OR status = $1",
+        )
+        .bind(&status)
+        .fetch_one(db)
+        .await?;
+
+        Ok((jobs, total))
+    }
+
+    /// Pending jobs of every lane which has any.
+    pub async fn lane_depths(db: &PgPool) -> Result<Vec<LaneDepth>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT lane, COUNT(*) AS depth, MIN(created_at) AS oldest_created_at
+            FROM signal_jobs
+            WHERE status = $1
+            GROUP BY lane
+            ORDER BY lane
+            "#,
+        )
+        .bind(JobStatus::Pending.as_ref())
+        .fetch_all(db)
+        .await
+    }
+
+    /// Queues dead job again with a fresh set of attempts. Returns `false` if the job isn't dead.
+    pub async fn replay(db: &PgPool, job_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE signal_jobs
+            SET status = $2, attempts = 0, run_at = NOW(), modified_at = NOW()
+            WHERE job_id = $1 AND status = $3
+            "#,
+        )
+        .bind(job_id)
+        .bind(JobStatus::Pending.as_ref())
+        .bind(JobStatus::Dead.as_ref())
+        .execute(db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Processes queued signals until the process stops. Each job is claimed by a transaction
+/// holding its row lock while the signal is processed, so a job of a crashed worker is picked up
+/// again once its transaction is rolled back.
+///
+/// Jobs of different lanes run in parallel, jobs of a lane strictly one after another in bar
+/// time order, then arrival order. Only the first pending job of a lane can be claimed, so a
+/// retried job holds back the later signals of its lane until it is done or dead.
+pub async fn run_worker(
+    db: PgPool,
+    core: Arc<Core>,
+    brokers: Arc<BrokerRegistry>,
+    settings: QueueSettings,
+) {
+    loop {
+        match process_next(&db, &core, &brokers, &settings).await {
+            Ok(true) => {}
+            Ok(false) => sleep(Duration::from_millis(settings.poll_interval)).await,
+            Err(err) => {
+                error!("Failed to process signal job, error: {:?}", err);
+                sleep(Duration::from_millis(settings.poll_interval)).await;
+            }
+        }
+    }
+}
+
+/// Processes the next due job, one step of `run_worker`. Returns `false` if there is none.
+pub async fn process_next(
+    db: &PgPool,
+    core: &Core,
+    brokers: &BrokerRegistry,
+    settings: &QueueSettings,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let job: Option<SignalJob> = sqlx::query_as(
+        r#"
+        SELECT *
+        FROM signal_jobs job
+        WHERE status = $1
+            AND run_at <= NOW()
+            AND NOT EXISTS (
+                SELECT 1
+                FROM signal_jobs earlier
+                WHERE earlier.lane = job.lane
+                    AND earlier.status = $1
+                    AND (earlier.bar_time, earlier.created_at) < (job.bar_time, job.created_at)
+            )
+        ORDER BY run_at
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .bind(JobStatus::Pending.as_ref())
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(job) = job else {
+        return Ok(false);
+    };
+
+    let attempts = job.attempts + 1;
+    let (status, run_at, last_error) = match process(db, core, brokers, &job).await {
+        Ok(()) => (JobStatus::Done, job.run_at, None),
+        Err((err, retryable)) if retryable && attempts < job.max_attempts => {
+            let delay = retry_delay(settings.retry_delay, attempts);
+            warn!(
+                "Signal job {} failed, retrying in {:?}, error: {}",
+                job.job_id, delay, err
+            );
+            let run_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+            (JobStatus::Pending, run_at, Some(err))
+        }
+        Err((err, _)) => {
+            error!(
+                "Signal job {} dead after {} attempts, error: {}",
+                job.job_id, attempts, err
+            );
+            (JobStatus::Dead, job.run_at, Some(err))
+        }
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE signal_jobs
+        SET status = $2, attempts = $3, run_at = $4, last_error = $5, modified_at = NOW()
+        WHERE job_id = $1
+        "#,
+    )
+    .bind(job.job_id)
+    .bind(status.as_ref())
+    .bind(attempts)
+    .bind(run_at)
+    .bind(last_error)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Turns the job into a trade signal and processes it. Errors come with whether the job may
+/// succeed when retried.
+async fn process(
+    db: &PgPool,
+    core: &Core,
+    brokers: &BrokerRegistry,
+    job: &SignalJob,
+) -> Result<(), (String, bool)> {
+    let trade_signal = TradeSignal::from_alert_data(job.alert_id, job.payload.0.clone(), db)
+        .await
+        .map_err(|err| {
+            let retryable = matches!(err, ApiError::ServiceUnavailable);
+            (err.to_string(), retryable)
+        })?;
+    let client = brokers
+        .get(
+            &trade_signal.strategy.broker,
+            &trade_signal.strategy.account,
+        )
+        .map_err(|err| (err.to_string(), false))?;
+
+    let order = core
+        .process_trade_signal(client, trade_signal)
+        .await
+        .map_err(|err| (err.to_string(), err.is_retryable()))?;
+    info!(
+        "Signal job {} placed order {}",
+        job.job_id, order.broker_order_id
+    );
+
+    Ok(())
+}
+
+fn retry_delay(base_secs: f64, attempts: i32) -> Duration {
+    let secs = base_secs.max(0.0) * 2f64.powi(attempts.clamp(1, 16) - 1);
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::from_secs(3600))
+}
@@ -0,0 +1,37 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Per minute limit of the alerts accepted at the webhook endpoint of each strategy. Counts are
+/// kept in memory, so they start over when the server restarts.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Start of the current window and requests counted in it by strategy.
+    windows: Mutex<HashMap<Uuid, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// Counts request of the strategy. Returns `false` if `limit` requests were already counted
+    /// in the current minute.
+    pub fn check(&self, strategy_id: Uuid, limit: u32) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let (started_at, count) = windows.entry(strategy_id).or_insert((now, 0));
+        if now.duration_since(*started_at) >= WINDOW {
+            *started_at = now;
+            *count = 0;
+        }
+        if *count >= limit {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+}
@@ -0,0 +1,163 @@
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+
+use crate::{
+    alert::PositionSide,
+    objects::{Account, Position},
+};
+
+/// Account level limits checked before every entry, e.g. in config:
+/// `risk = { max_open_positions = 5, max_daily_loss = 500, max_drawdown_percent = 3 }`
+/// Exposure and loss limits are in the account currency. Limits that are not set are not
+/// enforced.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct RiskLimits {
+    pub max_open_positions: Option<usize>,
+    /// Limit for the sum of absolute market values of all positions.
+    pub max_gross_exposure: Option<Decimal>,
+    /// Limit for the absolute difference between long and short market values.
+    pub max_net_exposure: Option<Decimal>,
+    /// Limit for the realized loss since the start of the day.
+    pub max_daily_loss: Option<Decimal>,
+    /// Limit for the equity drop from the start of the day, in percent.
+    pub max_drawdown_percent: Option<Decimal>,
+    /// Whether to close all positions when the daily loss or drawdown limit is breached.
+    #[serde(default)]
+    pub flatten_on_breach: bool,
+}
+
+/// State of the account the limits are checked against.
+#[derive(Debug, Clone, Default)]
+pub struct Exposure {
+    pub equity: Decimal,
+    pub start_of_day_equity: Decimal,
+    /// Signed market value of every open position by ticker.
+    pub positions: Vec<(String, Decimal)>,
+    pub unrealized_intraday_pl: Decimal,
+}
+
+/// Entry order about to be submitted.
+#[derive(Debug, Clone)]
+pub struct ProposedEntry<'a> {
+    pub ticker: &'a str,
+    pub side: PositionSide,
+    pub notional: Decimal,
+}
+
+#[derive(Debug, PartialEq, ThisError)]
+pub enum RiskBreach {
+    #[error("Open positions limit of {0} reached")]
+    MaxOpenPositions(usize),
+    #[error("Gross exposure would be {0}, above the limit")]
+    GrossExposure(Decimal),
+    #[error("Net exposure would be {0}, above the limit")]
+    NetExposure(Decimal),
+    #[error("Daily realized loss of {0} is above the limit")]
+    DailyLoss(Decimal),
+    #[error("Intraday drawdown of {0}% is above the limit")]
+    Drawdown(Decimal),
+}
+
+impl RiskBreach {
+    /// Whether the breach trips the circuit breaker, i.e. stops trading for the day rather than
+    /// rejecting only the entry that would breach it.
+    pub fn trips_breaker(&self) -> bool {
+        matches!(self, RiskBreach::DailyLoss(_) | RiskBreach::Drawdown(_))
+    }
+}
+
+impl Exposure {
+    pub fn new(account: &Account, positions: &[Position]) -> Self {
+        Self {
+            equity: account.equity,
+            start_of_day_equity: account.last_equity,
+            positions: positions
+                .iter()
+                .map(|position| (position.symbol.clone(), position.market_value))
+                .collect(),
+            unrealized_intraday_pl: positions
+                .iter()
+                .map(|position| position.unrealized_intraday_pl)
+                .sum(),
+        }
+    }
+
+    pub fn gross(&self) -> Decimal {
+        self.positions.iter().map(|(_, value)| value.abs()).sum()
+    }
+
+    pub fn net(&self) -> Decimal {
+        self.positions.iter().map(|(_, value)| value).sum()
+    }
+
+    /// Profit or loss realized since the start of the day: equity change that is not explained
+    /// by price moves of open positions.
+    pub fn realized_daily_pl(&self) -> Decimal {
+        self.equity - self.start_of_day_equity - self.unrealized_intraday_pl
+    }
+
+    /// Drop of equity from the start of the day in percent, zero if the equity is up.
+    pub fn drawdown_percent(&self) -> Decimal {
+        if self.start_of_day_equity <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+
+        ((self.start_of_day_equity - self.equity) / self.start_of_day_equity * Decimal::ONE_HUNDRED)
+            .max(Decimal::ZERO)
+    }
+}
+
+impl RiskLimits {
+    /// Whether any limit that trips the circuit breaker is set.
+    pub fn has_breaker(&self) -> bool {
+        self.max_daily_loss.is_some() || self.max_drawdown_percent.is_some()
+    }
+
+    pub fn check_breaker(&self, exposure: &Exposure) -> Result<(), RiskBreach> {
+        let loss = -exposure.realized_daily_pl();
+        if matches!(self.max_daily_loss, Some(max) if loss > max) {
+            return Err(RiskBreach::DailyLoss(loss));
+        }
+
+        let drawdown = exposure.drawdown_percent();
+        if matches!(self.max_drawdown_percent, Some(max) if drawdown > max) {
+            return Err(RiskBreach::Drawdown(drawdown.round_dp(2)));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_entry(
+        &self,
+        exposure: &Exposure,
+        entry: &ProposedEntry,
+    ) -> Result<(), RiskBreach> {
+        self.check_breaker(exposure)?;
+
+        let is_new_position = !exposure
+            .positions
+            .iter()
+            .any(|(ticker, _)| ticker == entry.ticker);
+        if let Some(max) = self.max_open_positions {
+            if is_new_position && exposure.positions.len() >= max {
+                return Err(RiskBreach::MaxOpenPositions(max));
+            }
+        }
+
+        let gross = exposure.gross() + entry.notional.abs();
+        if matches!(self.max_gross_exposure, Some(max) if gross > max) {
+            return Err(RiskBreach::GrossExposure(gross));
+        }
+
+        let net = match entry.side {
+            PositionSide::Long => exposure.net() + entry.notional.abs(),
+            PositionSide::Short => exposure.net() - entry.notional.abs(),
+        };
+        if matches!(self.max_net_exposure, Some(max) if net.abs() > max) {
+            return Err(RiskBreach::NetExposure(net));
+        }
+
+        Ok(())
+    }
+}
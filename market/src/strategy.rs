@@ -1,22 +1,681 @@
-use serde::Deserialize;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgArguments, query::Query, types::Json, FromRow, PgPool, Postgres};
+use strum_macros::{AsRefStr, EnumString};
 use uuid::Uuid;
 
-use crate::objects::Broker;
+use crate::{
+    alert::{BarData, PositionSide, WebhookAlertData},
+    clients::{BrokerAccount, DEFAULT_ACCOUNT},
+    objects::Broker,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Strategy {
     pub id: Uuid,
     pub name: String,
     pub enabled: bool,
     pub broker: Broker,
+    /// Name of the account of the broker the strategy trades on.
+    #[serde(default = "default_account")]
+    pub account: String,
     pub currency_type: CurrencyType,
+    /// How much of the asset to buy or sell on entry signals.
+    pub position_sizing: PositionSizing,
+    /// Rule for the take profit attached to entry orders. No take profit if not set.
+    pub take_profit: Option<TakeProfit>,
+    /// Whether stop loss updates may move the stop away from the price. By default stops are
+    /// only tightened.
+    #[serde(default)]
+    pub allow_stop_loosening: bool,
     pub max_order_retries: u8,
     pub order_retry_delay: f64,
+    /// Seconds an alert may take from firing to reaching the server. Older alerts are rejected,
+    /// e.g. ones replayed hours later.
+    #[serde(default = "default_max_signal_age")]
+    pub max_signal_age: f64,
+    /// Seconds the fire time of an alert may be ahead of the server clock.
+    #[serde(default = "default_max_future_skew")]
+    pub max_future_skew: f64,
+    /// Secret which webhook alerts of the strategy must carry in the `passphrase` field.
+    #[serde(skip_serializing)]
+    pub webhook_passphrase: String,
+    /// Optional key for sources that sign alerts with HMAC-SHA256 instead of sending the
+    /// passphrase.
+    #[serde(skip_serializing)]
+    pub webhook_hmac_secret: Option<String>,
+    /// Dedicated webhook endpoint of the strategy. Alerts of the strategy are only accepted at
+    /// the shared one if not set.
+    pub endpoint: Option<WebhookEndpoint>,
+}
+
+/// Webhook endpoint of a strategy at `POST /webhook/:slug`, e.g. in config:
+/// `endpoint = { slug = "aapl-breakout", token = "<secret>", rate_limit = 10 }`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct WebhookEndpoint {
+    pub slug: String,
+    /// Secret which requests carry in the `token` query parameter or the `X-Webhook-Token`
+    /// header. TradingView can only send it in the query.
+    #[serde(skip_serializing)]
+    pub token: String,
+    #[serde(default = "endpoint_enabled")]
+    pub enabled: bool,
+    /// Alerts accepted per minute, not limited if not set.
+    pub rate_limit: Option<u32>,
+    /// Tickers the endpoint accepts alerts for, any if empty.
+    #[serde(default)]
+    pub allowed_tickers: Vec<String>,
+    /// Timeframes the endpoint accepts alerts for, any if empty.
+    #[serde(default)]
+    pub allowed_timeframes: Vec<String>,
+    /// Signal types the endpoint accepts, e.g. `open_long`, any if empty.
+    #[serde(default)]
+    pub allowed_signal_types: Vec<String>,
+}
+
+impl WebhookEndpoint {
+    /// Slugs are part of the URL, so they are limited to lowercase letters, digits, `-` and `_`.
+    pub fn is_valid_slug(slug: &str) -> bool {
+        !slug.is_empty()
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    }
+
+    /// Rejects alert the endpoint isn't allowed to receive, returning the reason.
+    pub fn check(&self, alert_data: &WebhookAlertData) -> Result<(), String> {
+        let checks = [
+            ("Ticker", &self.allowed_tickers, alert_data.ticker.as_str()),
+            (
+                "Timeframe",
+                &self.allowed_timeframes,
+                alert_data.timeframe.as_str(),
+            ),
+            (
+                "Signal type",
+                &self.allowed_signal_types,
+                alert_data.signal_type.as_ref(),
+            ),
+        ];
+        for (field, allowed, value) in checks {
+            if !allowed.is_empty() && !allowed.iter().any(|allowed| allowed == value) {
+                return Err(format!(
+                    "{} {} isn't allowed at webhook endpoint {}",
+                    field, value, self.slug
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn endpoint_enabled() -> bool {
+    true
+}
+
+impl Strategy {
+    /// Broker account the strategy trades on.
+    pub fn broker_account(&self) -> BrokerAccount {
+        BrokerAccount::new(self.broker.clone(), &self.account)
+    }
+
+    /// Rejects alert fired more than `max_signal_age` before it was received or more than
+    /// `max_future_skew` after, returning the reason.
+    pub fn check_alert_time(
+        &self,
+        fired_at: DateTime<Utc>,
+        received_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let age = (received_at - fired_at).num_milliseconds() as f64 / 1000.0;
+        if age > self.max_signal_age {
+            return Err(format!(
+                "Alert fired at {} is {:.1}s old, strategy {} accepts alerts up to {}s old",
+                fired_at.to_rfc3339(),
+                age,
+                self.name,
+                self.max_signal_age
+            ));
+        }
+        if -age > self.max_future_skew {
+            return Err(format!(
+                "Alert fired at {} is {:.1}s ahead of the server clock, strategy {} accepts {}s",
+                fired_at.to_rfc3339(),
+                -age,
+                self.name,
+                self.max_future_skew
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Strategy which isn't deleted.
+    pub async fn find(db: &PgPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let row: Option<StrategyRow> = sqlx::query_as(
+            "SELECT * FROM strategies WHERE strategy_id = $1 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+
+        row.map(Self::try_from).transpose()
+    }
+
+    /// Strategy which isn't deleted by the slug of its webhook endpoint.
+    pub async fn find_by_slug(db: &PgPool, slug: &str) -> Result<Option<Self>, sqlx::Error> {
+        let row: Option<StrategyRow> = sqlx::query_as(
+            "SELECT * FROM strategies WHERE endpoint_slug = $1 AND deleted_at IS NULL",
+        )
+        .bind(slug)
+        .fetch_optional(db)
+        .await?;
+
+        row.map(Self::try_from).transpose()
+    }
+
+    /// Strategies which aren't deleted, ordered by name, and their total number.
+    pub async fn list(
+        db: &PgPool,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<(Vec<Self>, i64), sqlx::Error> {
+        let rows: Vec<StrategyRow> = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM strategies
+            WHERE deleted_at IS NULL
+            ORDER BY name, strategy_id
+            OFFSET $1
+            LIMIT $2
+            "#,
+        )
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(db)
+        .await?;
+        let total: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM strategies WHERE deleted_at IS NULL")
+                .fetch_one(db)
+                .await?;
+
+        let strategies = rows
+            .into_iter()
+            .map(Self::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok((strategies, total))
+    }
+
+    /// Stores new strategy. Returns `false` if a strategy with the same id exists, even a
+    /// deleted one.
+    pub async fn insert(&self, db: &PgPool) -> Result<bool, sqlx::Error> {
+        let sql = format!("{} ON CONFLICT (strategy_id) DO NOTHING", INSERT_STRATEGY);
+        let result = self.bind_fields(sqlx::query(&sql)).execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores strategies from config which aren't stored yet. Stored ones are left as they are,
+    /// they may have been changed through the API since, with a warning if they differ from
+    /// config. `App::reload_config` applies the config to them.
+    pub async fn seed(db: &PgPool, strategies: &[Strategy]) -> Result<(), sqlx::Error> {
+        for strategy in strategies {
+            if strategy.insert(db).await? {
+                tracing::info!("Seeded strategy {} from config", strategy.name);
+                continue;
+            }
+            match Self::find(db, strategy.id).await? {
+                None => tracing::warn!(
+                    "Strategy {} in config is deleted, reload the config to restore it",
+                    strategy.name
+                ),
+                Some(stored) if stored != *strategy => tracing::warn!(
+                    "Strategy {} differs from config, keeping the stored one. Reload the config \
+                     to apply it",
+                    strategy.name
+                ),
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overwrites stored strategy. Returns `false` if it doesn't exist or is deleted.
+    pub async fn update(&self, db: &PgPool) -> Result<bool, sqlx::Error> {
+        let query = sqlx::query(
+            r#"
+            UPDATE strategies
+            SET name = $2,
+                enabled = $3,
+                broker = $4,
+                account = $5,
+                currency_type = $6,
+                position_sizing = $7,
+                take_profit = $8,
+                allow_stop_loosening = $9,
+                max_order_retries = $10,
+                order_retry_delay = $11,
+                webhook_passphrase = $12,
+                webhook_hmac_secret = $13,
+                endpoint_slug = $14,
+                endpoint_token = $15,
+                endpoint_enabled = $16,
+                endpoint_rate_limit = $17,
+                endpoint_allowed_tickers = $18,
+                endpoint_allowed_timeframes = $19,
+                endpoint_allowed_signal_types = $20,
+                max_signal_age = $21,
+                max_future_skew = $22,
+                modified_at = NOW()
+            WHERE strategy_id = $1 AND deleted_at IS NULL
+            "#,
+        );
+        let result = self.bind_fields(query).execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores strategies of a reloaded config, overwriting stored ones even if they are deleted,
+    /// and deletes the strategies `removed` from the config. Either all changes are applied or
+    /// none.
+    pub async fn apply_config(
+        db: &PgPool,
+        strategies: &[&Strategy],
+        removed: &[Uuid],
+    ) -> Result<(), sqlx::Error> {
+        let sql = format!(
+            r#"
+            {}
+            ON CONFLICT (strategy_id) DO UPDATE
+            SET name = EXCLUDED.name,
+                enabled = EXCLUDED.enabled,
+                broker = EXCLUDED.broker,
+                account = EXCLUDED.account,
+                currency_type = EXCLUDED.currency_type,
+                position_sizing = EXCLUDED.position_sizing,
+                take_profit = EXCLUDED.take_profit,
+                allow_stop_loosening = EXCLUDED.allow_stop_loosening,
+                max_order_retries = EXCLUDED.max_order_retries,
+                order_retry_delay = EXCLUDED.order_retry_delay,
+                webhook_passphrase = EXCLUDED.webhook_passphrase,
+                webhook_hmac_secret = EXCLUDED.webhook_hmac_secret,
+                endpoint_slug = EXCLUDED.endpoint_slug,
+                endpoint_token = EXCLUDED.endpoint_token,
+                endpoint_enabled = EXCLUDED.endpoint_enabled,
+                endpoint_rate_limit = EXCLUDED.endpoint_rate_limit,
+                endpoint_allowed_tickers = EXCLUDED.endpoint_allowed_tickers,
+                endpoint_allowed_timeframes = EXCLUDED.endpoint_allowed_timeframes,
+                endpoint_allowed_signal_types = EXCLUDED.endpoint_allowed_signal_types,
+                max_signal_age = EXCLUDED.max_signal_age,
+                max_future_skew = EXCLUDED.max_future_skew,
+                modified_at = NOW(),
+                deleted_at = NULL
+            "#,
+            INSERT_STRATEGY
+        );
+
+        let mut tx = db.begin().await?;
+        for strategy in strategies {
+            strategy
+                .bind_fields(sqlx::query(&sql))
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query(
+            r#"
+            UPDATE strategies
+            SET enabled = false, deleted_at = NOW(), modified_at = NOW()
+            WHERE strategy_id = ANY($1) AND deleted_at IS NULL
+            "#,
+        )
+        .bind(removed)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// Marks strategy as deleted and disables it. Returns `false` if it doesn't exist or is
+    /// already deleted.
+    pub async fn delete(db: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE strategies
+            SET enabled = false, deleted_at = NOW(), modified_at = NOW()
+            WHERE strategy_id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .execute(db)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Binds fields of the strategy in the column order of `INSERT_STRATEGY`.
+    fn bind_fields<'q>(
+        &'q self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Query<'q, Postgres, PgArguments> {
+        let endpoint = self.endpoint.as_ref();
+        let no_values: &[String] = &[];
+        query
+            .bind(self.id)
+            .bind(&self.name)
+            .bind(self.enabled)
+            .bind(self.broker.as_ref())
+            .bind(&self.account)
+            .bind(self.currency_type.as_ref())
+            .bind(Json(&self.position_sizing))
+            .bind(self.take_profit.as_ref().map(Json))
+            .bind(self.allow_stop_loosening)
+            .bind(i16::from(self.max_order_retries))
+            .bind(self.order_retry_delay)
+            .bind(&self.webhook_passphrase)
+            .bind(&self.webhook_hmac_secret)
+            .bind(endpoint.map(|endpoint| &endpoint.slug))
+            .bind(endpoint.map(|endpoint| &endpoint.token))
+            .bind(endpoint.map(|endpoint| endpoint.enabled).unwrap_or(true))
+            .bind(
+                endpoint
+                    .and_then(|endpoint| endpoint.rate_limit)
+                    .map(i64::from),
+            )
+            .bind(endpoint.map_or(no_values, |endpoint| endpoint.allowed_tickers.as_slice()))
+            .bind(endpoint.map_or(no_values, |endpoint| endpoint.allowed_timeframes.as_slice()))
+            .bind(endpoint.map_or(no_values, |endpoint| {
+                endpoint.allowed_signal_types.as_slice()
+            }))
+            .bind(self.max_signal_age)
+            .bind(self.max_future_skew)
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+const INSERT_STRATEGY: &str = r#"
+    INSERT INTO strategies (
+        strategy_id,
+        name,
+        enabled,
+        broker,
+        account,
+        currency_type,
+        position_sizing,
+        take_profit,
+        allow_stop_loosening,
+        max_order_retries,
+        order_retry_delay,
+        webhook_passphrase,
+        webhook_hmac_secret,
+        endpoint_slug,
+        endpoint_token,
+        endpoint_enabled,
+        endpoint_rate_limit,
+        endpoint_allowed_tickers,
+        endpoint_allowed_timeframes,
+        endpoint_allowed_signal_types,
+        max_signal_age,
+        max_future_skew,
+        created_at,
+        modified_at
+    )
+    VALUES (
+        $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
+        $21, $22, NOW(), NOW()
+    )
+"#;
+
+/// Row of `strategies` table.
+#[derive(FromRow)]
+struct StrategyRow {
+    strategy_id: Uuid,
+    name: String,
+    enabled: bool,
+    broker: String,
+    account: String,
+    currency_type: String,
+    position_sizing: Json<PositionSizing>,
+    take_profit: Option<Json<TakeProfit>>,
+    allow_stop_loosening: bool,
+    max_order_retries: i16,
+    order_retry_delay: f64,
+    webhook_passphrase: String,
+    webhook_hmac_secret: Option<String>,
+    endpoint_slug: Option<String>,
+    endpoint_token: Option<String>,
+    endpoint_enabled: bool,
+    endpoint_rate_limit: Option<i64>,
+    endpoint_allowed_tickers: Vec<String>,
+    endpoint_allowed_timeframes: Vec<String>,
+    endpoint_allowed_signal_types: Vec<String>,
+    max_signal_age: f64,
+    max_future_skew: f64,
+}
+
+impl TryFrom<StrategyRow> for Strategy {
+    type Error = sqlx::Error;
+
+    fn try_from(row: StrategyRow) -> Result<Self, Self::Error> {
+        let endpoint = match (row.endpoint_slug, row.endpoint_token) {
+            (Some(slug), Some(token)) => Some(WebhookEndpoint {
+                slug,
+                token,
+                enabled: row.endpoint_enabled,
+                rate_limit: row
+                    .endpoint_rate_limit
+                    .map(u32::try_from)
+                    .transpose()
+                    .map_err(|err| sqlx::Error::Decode(err.into()))?,
+                allowed_tickers: row.endpoint_allowed_tickers,
+                allowed_timeframes: row.endpoint_allowed_timeframes,
+                allowed_signal_types: row.endpoint_allowed_signal_types,
+            }),
+            _ => None,
+        };
+
+        Ok(Self {
+            id: row.strategy_id,
+            name: row.name,
+            enabled: row.enabled,
+            broker: Broker::from_str(&row.broker).map_err(|err| sqlx::Error::Decode(err.into()))?,
+            account: row.account,
+            currency_type: CurrencyType::from_str(&row.currency_type)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?,
+            position_sizing: row.position_sizing.0,
+            take_profit: row.take_profit.map(|take_profit| take_profit.0),
+            allow_stop_loosening: row.allow_stop_loosening,
+            max_order_retries: u8::try_from(row.max_order_retries)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?,
+            order_retry_delay: row.order_retry_delay,
+            max_signal_age: row.max_signal_age,
+            max_future_skew: row.max_future_skew,
+            webhook_passphrase: row.webhook_passphrase,
+            webhook_hmac_secret: row.webhook_hmac_secret,
+            endpoint,
+        })
+    }
+}
+
+fn default_account() -> String {
+    DEFAULT_ACCOUNT.to_owned()
+}
+
+pub fn default_max_signal_age() -> f64 {
+    300.0
+}
+
+pub fn default_max_future_skew() -> f64 {
+    10.0
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, AsRefStr, EnumString)]
 #[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum CurrencyType {
     Crypto,
     Stock,
 }
+
+/// Position sizing mode of a strategy, e.g. in config:
+/// `position_sizing = { type = "fixed_risk", percent = 1 }`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PositionSizing {
+    /// Same quantity on every entry.
+    FixedQuantity { quantity: Decimal },
+    /// Quantity worth `notional` at the entry price.
+    FixedNotional { notional: Decimal },
+    /// Quantity worth `percent` of account equity at the entry price.
+    PercentOfEquity { percent: Decimal },
+    /// Quantity losing `percent` of account equity if the stop loss is hit.
+    FixedRisk { percent: Decimal },
+}
+
+impl PositionSizing {
+    /// Unrounded quantity for entry of a `side` position at `entry` with stop loss at `stop`.
+    /// Returns `None` if the quantity can't be calculated, e.g. when the stop isn't on the losing
+    /// side of the entry.
+    pub fn quantity(
+        &self,
+        side: PositionSide,
+        entry: Decimal,
+        stop: Decimal,
+        equity: Decimal,
+    ) -> Option<Decimal> {
+        let quantity = match self {
+            PositionSizing::FixedQuantity { quantity } => *quantity,
+            PositionSizing::FixedNotional { notional } => notional.checked_div(entry)?,
+            PositionSizing::PercentOfEquity { percent } => {
+                (equity * percent / Decimal::ONE_HUNDRED).checked_div(entry)?
+            }
+            PositionSizing::FixedRisk { percent } => {
+                let risk_per_unit = match side {
+                    PositionSide::Long => entry - stop,
+                    PositionSide::Short => stop - entry,
+                };
+                if risk_per_unit <= Decimal::ZERO {
+                    return None;
+                }
+                (equity * percent / Decimal::ONE_HUNDRED).checked_div(risk_per_unit)?
+            }
+        };
+
+        (quantity > Decimal::ZERO).then_some(quantity)
+    }
+}
+
+/// Caps quantity by what the buying power allows at the entry price and rounds it down to
+/// `scale` decimal places.
+pub fn round_quantity(
+    quantity: Decimal,
+    entry: Decimal,
+    buying_power: Decimal,
+    scale: u32,
+) -> Decimal {
+    let affordable = buying_power.checked_div(entry).unwrap_or(quantity);
+    quantity
+        .min(affordable)
+        .round_dp_with_strategy(scale, RoundingStrategy::ToZero)
+}
+
+/// Take profit rule of a strategy, e.g. in config:
+/// `take_profit = { type = "risk_reward", ratio = 2 }`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TakeProfit {
+    /// Multiple of the distance between entry and trail stop price.
+    RiskReward { ratio: Decimal },
+    /// Percent away from entry price.
+    Percent { percent: Decimal },
+    /// Multiple of the average true range of the last `period` bars.
+    Atr { multiplier: Decimal, period: usize },
+}
+
+impl TakeProfit {
+    /// Checks the rule can produce a take profit, returning the reason if it can't.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TakeProfit::RiskReward { ratio } if *ratio <= Decimal::ZERO => {
+                Err("take profit ratio must be positive".to_owned())
+            }
+            TakeProfit::Percent { percent } if *percent <= Decimal::ZERO => {
+                Err("take profit percent must be positive".to_owned())
+            }
+            TakeProfit::Atr { period: 0, .. } => {
+                Err("take profit ATR period must be at least 1".to_owned())
+            }
+            TakeProfit::Atr { multiplier, .. } if *multiplier <= Decimal::ZERO => {
+                Err("take profit ATR multiplier must be positive".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of the most recent bars, current one included, the rule needs.
+    pub fn bars_required(&self) -> usize {
+        match self {
+            TakeProfit::Atr { period, .. } => *period,
+            _ => 1,
+        }
+    }
+
+    /// Take profit price for a position entered at `entry` with stop loss at `stop`. `bars` are
+    /// ordered from the oldest to the most recent one. Returns `None` if the stop is on the wrong
+    /// side of the entry or the rule can't produce a positive price.
+    pub fn price(
+        &self,
+        side: PositionSide,
+        entry: Decimal,
+        stop: Decimal,
+        bars: &[BarData],
+    ) -> Option<Decimal> {
+        let risk = match side {
+            PositionSide::Long => entry - stop,
+            PositionSide::Short => stop - entry,
+        };
+        if risk <= Decimal::ZERO {
+            return None;
+        }
+
+        let distance = match self {
+            TakeProfit::RiskReward { ratio } => risk * ratio,
+            TakeProfit::Percent { percent } => entry * percent / Decimal::ONE_HUNDRED,
+            TakeProfit::Atr { multiplier, .. } => average_true_range(bars)? * multiplier,
+        };
+        if distance <= Decimal::ZERO {
+            return None;
+        }
+
+        let price = match side {
+            PositionSide::Long => entry + distance,
+            PositionSide::Short => entry - distance,
+        };
+        (price > Decimal::ZERO).then_some(price)
+    }
+}
+
+/// Average true range of bars ordered from the oldest to the most recent one. True range of the
+/// first bar is its high-low range as there is no previous close.
+pub fn average_true_range(bars: &[BarData]) -> Option<Decimal> {
+    if bars.is_empty() {
+        return None;
+    }
+
+    let mut prev_close: Option<Decimal> = None;
+    let mut total = Decimal::ZERO;
+    for bar in bars {
+        let (high, low) = (*bar.high.as_ref(), *bar.low.as_ref());
+        total += match prev_close {
+            Some(close) => (high - low)
+                .max((high - close).abs())
+                .max((low - close).abs()),
+            None => high - low,
+        };
+        prev_close = Some(*bar.close.as_ref());
+    }
+
+    Some(total / Decimal::from(bars.len()))
+}
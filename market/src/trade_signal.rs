@@ -1,43 +1,42 @@
 use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
     api::{
-        alert::{SignalType, BarData, WebhookAlertData, SignalData},
+        alert::{BarData, SignalType, WebhookAlertData},
         error::ApiError,
     },
-    app_config::AppConfig,
-    clients::BrokerClient,
     strategy::Strategy,
 };
 
 #[derive(Debug, Clone)]
 pub struct TradeSignal {
+    pub alert_id: Uuid,
+    /// `WebhookAlertData::dedup_key` of the originating alert.
+    pub dedup_key: String,
     pub strategy: Strategy,
     pub ticker: String,
     pub timeframe: String,
     pub exchange: String,
     pub signal_type: SignalType,
-    pub trail_stop_price: Option<Decimal>,
     pub bar_data: BarData,
     pub time: DateTime<Utc>,
 }
 
 impl TradeSignal {
-    pub fn from_alert_data(
+    pub async fn from_alert_data(
+        alert_id: Uuid,
         alert_data: WebhookAlertData,
-        config: &AppConfig,
+        db: &PgPool,
     ) -> Result<Self, ApiError> {
         let strategy_id = alert_data.strategy_id;
 
-        let validated_strategy = config
-            .strategies
-            .iter()
-            .find(|strategy| strategy.id == strategy_id)
-            .ok_or_else(|| {
-                let msg = format!("Unknown strategy - {}", strategy_id);
-                tracing::error!(msg);
-                ApiError::BadRequest(msg)
-            })?;
+        let validated_strategy = Strategy::find(db, strategy_id).await?.ok_or_else(|| {
+            let msg = format!("Unknown strategy - {}", strategy_id);
+            tracing::error!(msg);
+            ApiError::BadRequest(msg)
+        })?;
 
         if !validated_strategy.enabled {
             let msg = format!(
@@ -49,13 +48,31 @@ impl TradeSignal {
         }
 
         Ok(Self {
-            strategy: validated_strategy.clone(),
+            alert_id,
+            dedup_key: alert_data.dedup_key(),
+            strategy: validated_strategy,
             ticker: alert_data.ticker,
             timeframe: alert_data.timeframe,
             exchange: alert_data.exchange,
-            signal_data: alert_data.signal_data,
+            signal_type: alert_data.signal_type,
             bar_data: alert_data.bar_data,
             time: alert_data.time,
         })
     }
+
+    /// Key of the queue lane the signal is processed in: its broker account and ticker, e.g.
+    /// `alpaca/default/AAPL`.
+    pub fn lane(&self) -> String {
+        format!("{}/{}", self.strategy.broker_account(), self.ticker)
+    }
+
+    /// Deterministic id of the order placed for this signal with the given purpose, e.g.
+    /// `entry`. Reprocessing the same alert yields the same id, which is also used as the
+    /// broker's client order id.
+    pub fn order_id(&self, purpose: &str) -> Uuid {
+        Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("{}/{}", self.dedup_key, purpose).as_bytes(),
+        )
+    }
 }
@@ -0,0 +1,134 @@
+use axum::{
+    body::Body,
+    http::{method::Method, Request, StatusCode},
+    Router,
+};
+use chrono::{Duration, Utc};
+use market::app_config::AppConfig;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+mod setup;
+use setup::{alert, create_strategy, make_test_app, post_alert, send};
+
+const ENDPOINT: &str = "/webhook/time-limited?token=endpoint-token";
+
+async fn create_time_limited_strategy(app: &Router) {
+    let fields = json!({
+        "name": "Time limited",
+        "max_signal_age": 60.0,
+        "max_future_skew": 5.0,
+        "endpoint": { "slug": "time-limited", "token": "endpoint-token" }
+    });
+    create_strategy(app, fields).await;
+}
+
+async fn get_metrics(app: &Router) -> String {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("http://localhost:8000/metrics")
+        .header(
+            "Authorization",
+            AppConfig::build_for_test().unwrap().api_key,
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[sqlx::test]
+async fn stale_alert_is_rejected(pool: PgPool) {
+    let app = make_test_app(pool.clone()).await;
+    create_time_limited_strategy(&app).await;
+
+    let status = post_alert(
+        &app,
+        ENDPOINT,
+        alert("AAPL", "1", Utc::now() - Duration::minutes(10)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, reason): (String, Option<String>) =
+        sqlx::query_as("SELECT status, rejection_reason FROM alerts WHERE client_alert_id = '1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(status, "rejected");
+    assert!(reason.unwrap().contains("old"));
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 0);
+}
+
+#[sqlx::test]
+async fn future_dated_alert_is_rejected_beyond_skew(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_time_limited_strategy(&app).await;
+
+    let status = post_alert(
+        &app,
+        ENDPOINT,
+        alert("AAPL", "1", Utc::now() + Duration::minutes(1)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Clocks a little apart are tolerated
+    let status = post_alert(
+        &app,
+        ENDPOINT,
+        alert("AAPL", "2", Utc::now() + Duration::seconds(2)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 1);
+}
+
+#[sqlx::test]
+async fn alert_latency_is_tracked(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_time_limited_strategy(&app).await;
+
+    let status = post_alert(
+        &app,
+        ENDPOINT,
+        alert("AAPL", "1", Utc::now() - Duration::seconds(3)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let metrics = get_metrics(&app).await;
+    assert!(metrics.contains("market_alert_latency_seconds_bucket{le=\"2\"} 0"));
+    assert!(metrics.contains("market_alert_latency_seconds_bucket{le=\"5\"} 1"));
+    assert!(metrics.contains("market_alert_latency_seconds_count 1"));
+}
+
+#[sqlx::test]
+async fn rejected_and_duplicate_alerts_are_not_timed(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_time_limited_strategy(&app).await;
+
+    let fired_at = Utc::now() - Duration::seconds(3);
+    let status = post_alert(&app, ENDPOINT, alert("AAPL", "1", fired_at)).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = post_alert(&app, ENDPOINT, alert("AAPL", "1", fired_at)).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = post_alert(
+        &app,
+        ENDPOINT,
+        alert("AAPL", "2", Utc::now() - Duration::minutes(10)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let metrics = get_metrics(&app).await;
+    assert!(metrics.contains("market_alert_latency_seconds_count 1"));
+}
@@ -0,0 +1,59 @@
+use market::{
+    api::{
+        alert::PositionSide,
+        objects::{OrderSide, OrderType},
+    },
+    app_config::Alpaca,
+    clients::{AlpacaClient, BrokerClient},
+    order::{ExitKind, ExitOrder},
+    strategy::CurrencyType,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+
+fn client() -> AlpacaClient {
+    AlpacaClient::new(&Alpaca {
+        apca_api_key_id: "key".to_string(),
+        apca_api_secret_key: "secret".to_string(),
+        apca_api_base_url: "https://paper-api.alpaca.markets".to_string(),
+        crypto_stop_slippage: Decimal::TWO,
+    })
+    .unwrap()
+}
+
+fn stop_loss(position_side: PositionSide, currency_type: CurrencyType) -> ExitOrder {
+    ExitOrder {
+        client_order_id: "c6f055d9-7f21-4079-913d-e6523a9cfffa".to_string(),
+        ticker: "BTC/USD".to_string(),
+        position_side,
+        quantity: Decimal::ONE,
+        currency_type,
+        kind: ExitKind::StopLoss(Decimal::from(100)),
+    }
+}
+
+#[test]
+fn crypto_stop_loss_limit_allows_slippage() {
+    let long = client()
+        .exit_order_request(&stop_loss(PositionSide::Long, CurrencyType::Crypto))
+        .unwrap();
+    let short = client()
+        .exit_order_request(&stop_loss(PositionSide::Short, CurrencyType::Crypto))
+        .unwrap();
+
+    assert_eq!(long.order_type, OrderType::StopLimit);
+    assert_eq!(long.side, OrderSide::Sell);
+    assert_eq!(long.stop_price, Some(Decimal::from(100)));
+    assert_eq!(long.limit_price, Some(Decimal::from(98)));
+    assert_eq!(short.limit_price, Some(Decimal::from(102)));
+}
+
+#[test]
+fn stock_stop_loss_is_a_stop_order() {
+    let exit = client()
+        .exit_order_request(&stop_loss(PositionSide::Long, CurrencyType::Stock))
+        .unwrap();
+
+    assert_eq!(exit.order_type, OrderType::Stop);
+    assert_eq!(exit.limit_price, None);
+}
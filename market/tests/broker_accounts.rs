@@ -0,0 +1,82 @@
+use market::app_config::Brokers;
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+
+#[test]
+fn single_account_is_default() {
+    let brokers: Brokers = serde_json::from_value(json!({
+        "alpaca": {
+            "apca_api_key_id": "key",
+            "apca_api_secret_key": "secret",
+            "apca_api_base_url": "https://paper-api.alpaca.markets"
+        },
+        "paper": { "starting_cash": 5000 }
+    }))
+    .unwrap();
+
+    let alpaca: Vec<_> = brokers
+        .alpaca
+        .iter()
+        .map(|(name, alpaca)| (name, alpaca.apca_api_key_id.as_str()))
+        .collect();
+    assert_eq!(alpaca, vec![("default", "key")]);
+
+    let paper: Vec<_> = brokers
+        .paper
+        .iter()
+        .map(|(name, paper)| (name, paper.starting_cash))
+        .collect();
+    assert_eq!(paper, vec![("default", Decimal::from(5000))]);
+}
+
+#[test]
+fn named_accounts() {
+    let brokers: Brokers = serde_json::from_value(json!({
+        "alpaca": {
+            "live": {
+                "apca_api_key_id": "live-key",
+                "apca_api_secret_key": "secret",
+                "apca_api_base_url": "https://api.alpaca.markets"
+            },
+            "research": {
+                "apca_api_key_id": "research-key",
+                "apca_api_secret_key": "secret",
+                "apca_api_base_url": "https://paper-api.alpaca.markets"
+            }
+        },
+        "paper": { "small": { "starting_cash": 1000 }, "large": {} }
+    }))
+    .unwrap();
+
+    let alpaca: Vec<_> = brokers
+        .alpaca
+        .iter()
+        .map(|(name, alpaca)| (name, alpaca.apca_api_key_id.as_str()))
+        .collect();
+    assert_eq!(
+        alpaca,
+        vec![("live", "live-key"), ("research", "research-key")]
+    );
+
+    let paper: Vec<_> = brokers
+        .paper
+        .iter()
+        .map(|(name, paper)| (name, paper.starting_cash))
+        .collect();
+    assert_eq!(
+        paper,
+        vec![
+            ("large", Decimal::from(100_000)),
+            ("small", Decimal::from(1000))
+        ]
+    );
+}
+
+#[test]
+fn no_accounts() {
+    let brokers: Brokers = serde_json::from_value(json!({})).unwrap();
+
+    assert!(brokers.alpaca.is_empty());
+    assert!(brokers.paper.is_empty());
+}
@@ -0,0 +1,202 @@
+#![cfg(feature = "bybit")]
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use market::{
+    api::{
+        alert::PositionSide,
+        objects::{OrderStatus, OrderType},
+    },
+    app_config::Bybit,
+    clients::{BrokerClient, BybitClient},
+    order::{ExitKind, ExitOrder},
+    strategy::CurrencyType,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+const ORDER_ID: &str = "c6f055d9-7f21-4079-913d-e6523a9cfffa";
+
+/// Orders received by the stand-in.
+type Received = Arc<Mutex<Vec<Value>>>;
+
+fn ok(result: Value) -> Json<Value> {
+    Json(json!({ "retCode": 0, "retMsg": "OK", "result": result, "time": 1690000000000u64 }))
+}
+
+async fn create_order(State(received): State<Received>, Json(body): Json<Value>) -> Json<Value> {
+    received.lock().unwrap().push(body);
+    ok(json!({ "orderId": ORDER_ID, "orderLinkId": "" }))
+}
+
+async fn get_orders(State(received): State<Received>) -> Json<Value> {
+    let list: Vec<Value> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| {
+            json!({
+                "orderId": ORDER_ID,
+                "orderLinkId": body["orderLinkId"],
+                "symbol": body["symbol"],
+                "side": body["side"],
+                "orderType": body["orderType"],
+                "price": body.get("price").cloned().unwrap_or(json!("0")),
+                "qty": body["qty"],
+                "triggerPrice": body.get("triggerPrice").cloned().unwrap_or(json!("")),
+                "orderStatus": "Untriggered",
+                "cumExecQty": "0",
+                "avgPrice": "",
+                "reduceOnly": body["reduceOnly"],
+                "updatedTime": "1690000000000",
+            })
+        })
+        .collect();
+    ok(json!({ "list": list }))
+}
+
+fn position(side: &str, size: &str, position_idx: u8) -> Value {
+    json!({
+        "symbol": "BTCUSDT",
+        "side": side,
+        "size": size,
+        "avgPrice": "29500",
+        "markPrice": "30000",
+        "unrealisedPnl": "5",
+        "leverage": "3",
+        "positionIdx": position_idx,
+        "createdTime": "1690000000000",
+    })
+}
+
+/// Local stand-in for the Bybit REST API answering with canned responses.
+async fn stand_in() -> (String, Received) {
+    stand_in_with_positions(vec![position("Buy", "0.01", 0)]).await
+}
+
+async fn stand_in_with_positions(positions: Vec<Value>) -> (String, Received) {
+    let received = Received::default();
+    let router = Router::new()
+        .route(
+            "/v5/account/wallet-balance",
+            get(|| async {
+                ok(json!({ "list": [{ "totalEquity": "1000", "totalAvailableBalance": "800" }] }))
+            }),
+        )
+        .route(
+            "/v5/position/closed-pnl",
+            get(|| async { ok(json!({ "list": [{ "closedPnl": "-15" }] })) }),
+        )
+        .route(
+            "/v5/position/list",
+            get(move || async move { ok(json!({ "list": positions })) }),
+        )
+        .route("/v5/order/create", post(create_order))
+        .route("/v5/order/realtime", get(get_orders))
+        .with_state(received.clone());
+
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    (url, received)
+}
+
+fn client(base_url: String) -> BybitClient {
+    client_in_mode(base_url, false)
+}
+
+fn client_in_mode(base_url: String, hedge_mode: bool) -> BybitClient {
+    BybitClient::new(&Bybit {
+        api_key: "key".to_string(),
+        api_secret: "secret".to_string(),
+        base_url,
+        settle_coin: "USDT".to_string(),
+        leverage: None,
+        hedge_mode,
+    })
+}
+
+#[tokio::test]
+async fn bybit_account_and_positions() {
+    let (url, _) = stand_in().await;
+    let client = client(url);
+
+    let account = client.get_account().await.unwrap();
+    let position = client.get_position("BTCUSDT".to_string()).await.unwrap();
+
+    assert_eq!(account.equity, Decimal::from(1000));
+    assert_eq!(account.buying_power, Decimal::from(800));
+    // Equity less today's realized loss and open profit
+    assert_eq!(account.last_equity, Decimal::from(1010));
+    assert_eq!(position.side, PositionSide::Long);
+    assert_eq!(position.market_value, Decimal::from(300));
+}
+
+#[tokio::test]
+async fn bybit_stop_loss_is_reduce_only_conditional_order() {
+    let (url, received) = stand_in().await;
+    let client = client(url);
+    let exit = ExitOrder {
+        client_order_id: "stop-loss".to_string(),
+        ticker: "BTCUSDT".to_string(),
+        position_side: PositionSide::Long,
+        quantity: Decimal::new(1, 2),
+        currency_type: CurrencyType::Crypto,
+        kind: ExitKind::StopLoss(Decimal::from(29000)),
+    };
+
+    let order = client
+        .create_order(client.exit_order_request(&exit).unwrap())
+        .await
+        .unwrap();
+    let body = received.lock().unwrap()[0].clone();
+
+    assert_eq!(body["category"], "linear");
+    assert_eq!(body["side"], "Sell");
+    assert_eq!(body["reduceOnly"], true);
+    assert_eq!(body["triggerDirection"], 2);
+    assert_eq!(body["positionIdx"], 0);
+    assert_eq!(order.order_type, OrderType::Stop);
+    assert_eq!(order.stop_price, Some(Decimal::from(29000)));
+    assert_eq!(order.status, OrderStatus::New);
+}
+
+#[tokio::test]
+async fn bybit_hedge_mode_position_is_selected_by_side() {
+    let positions = vec![position("Buy", "0.01", 1), position("Sell", "0.02", 2)];
+    let (url, received) = stand_in_with_positions(positions).await;
+    let client = client_in_mode(url, true);
+
+    let short = client
+        .get_side_position("BTCUSDT".to_string(), PositionSide::Short)
+        .await
+        .unwrap();
+    assert_eq!(short.quantity, Decimal::new(2, 2));
+    assert!(client.get_position("BTCUSDT".to_string()).await.is_err());
+
+    for _ in 0..2 {
+        client
+            .delete_side_position("BTCUSDT".to_string(), PositionSide::Short)
+            .await
+            .unwrap();
+    }
+    let received = received.lock().unwrap().clone();
+
+    assert_eq!(received[0]["side"], "Buy");
+    assert_eq!(received[0]["qty"], "0.02");
+    assert_eq!(received[0]["positionIdx"], 2);
+    assert_eq!(received[0]["reduceOnly"], true);
+    // Closing the same position again reuses the order id
+    assert_eq!(received[1]["orderLinkId"], received[0]["orderLinkId"]);
+}
@@ -0,0 +1,129 @@
+use market::{
+    app_config::{AppConfig, ConfigDiff},
+    strategy::Strategy,
+};
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const KEPT: &str = "559a0466-9301-4198-ab4d-0302beac3cc2";
+const CHANGED: &str = "0d1e2f0a-6b5c-4d3e-8f7a-1b2c3d4e5f60";
+const REMOVED: &str = "7a8b9c0d-1e2f-4a3b-8c5d-6e7f8091a2b3";
+const ADDED: &str = "c6f055d9-7f21-4079-913d-e6523a9cfffa";
+
+fn strategy(id: &str, enabled: bool) -> Value {
+    json!({
+        "id": id,
+        "name": format!("Strategy {}", id),
+        "enabled": enabled,
+        "broker": "paper",
+        "currency_type": "stock",
+        "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+        "max_order_retries": 3,
+        "order_retry_delay": 1.0,
+        "webhook_passphrase": "secret"
+    })
+}
+
+fn config(strategies: Vec<Value>, risk: Value) -> AppConfig {
+    serde_json::from_value(json!({
+        "api_key": "key",
+        "database": { "url": "postgres://localhost/market" },
+        "brokers": {},
+        "strategies": strategies,
+        "risk": risk
+    }))
+    .unwrap()
+}
+
+#[test]
+fn diff_of_reloaded_config() {
+    let current = config(
+        vec![
+            strategy(KEPT, true),
+            strategy(CHANGED, true),
+            strategy(REMOVED, true),
+        ],
+        json!({}),
+    );
+    let reloaded = config(
+        vec![
+            strategy(KEPT, true),
+            strategy(CHANGED, false),
+            strategy(ADDED, true),
+        ],
+        json!({ "max_open_positions": 5 }),
+    );
+
+    let diff = ConfigDiff::new(&current, &current.strategies, &reloaded);
+
+    assert_eq!(diff.added_strategies, vec![Uuid::parse_str(ADDED).unwrap()]);
+    assert_eq!(
+        diff.changed_strategies,
+        vec![Uuid::parse_str(CHANGED).unwrap()]
+    );
+    assert_eq!(
+        diff.removed_strategies,
+        vec![Uuid::parse_str(REMOVED).unwrap()]
+    );
+    assert!(diff.risk_changed);
+    assert!(ConfigDiff::new(&current, &current.strategies, &current).is_empty());
+}
+
+#[test]
+fn diff_is_against_stored_strategies() {
+    let current = config(
+        vec![strategy(KEPT, true), strategy(REMOVED, true)],
+        json!({}),
+    );
+    // Disabled through the API, removed one deleted through it
+    let stored = config(vec![strategy(KEPT, false)], json!({}));
+
+    let diff = ConfigDiff::new(
+        &current,
+        &stored.strategies,
+        &config(vec![strategy(KEPT, true)], json!({})),
+    );
+
+    assert_eq!(
+        diff.changed_strategies,
+        vec![Uuid::parse_str(KEPT).unwrap()]
+    );
+    assert!(diff.removed_strategies.is_empty());
+}
+
+#[test]
+fn duplicate_strategy_ids_are_invalid() {
+    let config = config(vec![strategy(KEPT, true), strategy(KEPT, false)], json!({}));
+
+    assert!(config.validate().is_err());
+}
+
+#[sqlx::test]
+async fn seeding_keeps_stored_strategies(pool: PgPool) {
+    let stored = config(vec![strategy(KEPT, true)], json!({}));
+    let edited = config(
+        vec![strategy(KEPT, false), strategy(ADDED, true)],
+        json!({}),
+    );
+
+    Strategy::seed(&pool, &stored.strategies).await.unwrap();
+    Strategy::seed(&pool, &edited.strategies).await.unwrap();
+
+    let kept = Strategy::find(&pool, Uuid::parse_str(KEPT).unwrap()).await;
+    let added = Strategy::find(&pool, Uuid::parse_str(ADDED).unwrap()).await;
+    assert!(kept.unwrap().unwrap().enabled);
+    assert!(added.unwrap().is_some());
+}
+
+#[test]
+fn workers_must_leave_database_connections() {
+    let mut config = config(vec![strategy(KEPT, true)], json!({}));
+    assert!(config.validate().is_ok());
+
+    config.queue.workers = 5;
+    assert!(config.validate().is_err());
+    config.database.max_connections = 20;
+    assert!(config.validate().is_ok());
+}
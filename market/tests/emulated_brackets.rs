@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use market::{
+    api::objects::{OrdersRequest, Position},
+    clients::{BrokerClient, PaperBroker},
+    core::Core,
+    risk::RiskLimits,
+    strategy::TakeProfit,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal, BrokerDouble};
+
+/// Opens a long position at 100 with the stop loss at 95 and the take profit at 110.
+async fn open_long(pool: &PgPool, core: &Core, client: &Arc<dyn BrokerClient>) {
+    let mut strategy = paper_strategy();
+    strategy.take_profit = Some(TakeProfit::RiskReward {
+        ratio: Decimal::TWO,
+    });
+    client.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(pool, &strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+    core.process_trade_signal(Arc::clone(client), entry)
+        .await
+        .unwrap();
+    core.sync_orders(&strategy.broker_account(), client.as_ref())
+        .await
+        .unwrap();
+}
+
+async fn open_orders(client: &Arc<dyn BrokerClient>) -> usize {
+    let orders_req = OrdersRequest {
+        symbol: Some("AAPL".to_owned()),
+        open_only: true,
+    };
+    client.get_orders(orders_req).await.unwrap().len()
+}
+
+async fn bracket_statuses(pool: &PgPool) -> Vec<String> {
+    sqlx::query_scalar("SELECT status FROM emulated_brackets")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn reduce_only_exits_close_the_position_once(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(PaperBroker::new(Decimal::from(10_000)));
+    let account = paper_strategy().broker_account();
+    open_long(&pool, &core, &client).await;
+    assert_eq!(open_orders(&client).await, 2);
+
+    // Both exits are crossed by the same bar
+    client.on_bar("AAPL", &bar(100, 111, 94, 100));
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    let positions: Vec<Position> = client.get_positions().await.unwrap();
+    assert!(positions.is_empty());
+    assert_eq!(open_orders(&client).await, 0);
+    assert_eq!(bracket_statuses(&pool).await, vec!["closed"]);
+}
+
+#[sqlx::test]
+async fn take_profit_is_watched_without_reduce_only_exits(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(BrokerDouble {
+        reduce_only: false,
+        ..BrokerDouble::new(10_000)
+    });
+    let account = paper_strategy().broker_account();
+    open_long(&pool, &core, &client).await;
+
+    // Only the stop loss rests at the broker
+    assert_eq!(open_orders(&client).await, 1);
+    assert_eq!(bracket_statuses(&pool).await, vec!["active"]);
+
+    // Take profit reached: the stop loss is canceled first, then the position is closed
+    client.on_bar("AAPL", &bar(100, 111, 99, 111));
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+    assert_eq!(open_orders(&client).await, 0);
+    assert_eq!(bracket_statuses(&pool).await, vec!["taking_profit"]);
+
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    let positions: Vec<Position> = client.get_positions().await.unwrap();
+    assert!(positions.is_empty());
+    assert_eq!(bracket_statuses(&pool).await, vec!["closed"]);
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use axum::http::{method::Method, StatusCode};
+use market::{
+    api::objects::{Broker, OrdersRequest},
+    clients::{BrokerClient, BrokerRegistry, PaperBroker},
+    core::Core,
+    halt::HaltRequest,
+    risk::RiskLimits,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, make_test_app, paper_strategy, send, trade_signal};
+
+#[sqlx::test]
+async fn global_halt_survives_until_resumed(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let (status, report) = send(
+        &app,
+        Method::POST,
+        "/admin/halt",
+        Some(json!({ "reason": "broker outage" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["halt"]["reason"], "broker outage");
+
+    // Halting again keeps the active halt
+    let (_, repeated) = send(&app, Method::POST, "/admin/halt", Some(json!({}))).await;
+    assert_eq!(repeated["halt"]["halt_id"], report["halt"]["halt_id"]);
+
+    let (_, halts) = send(&app, Method::GET, "/admin/halts", None).await;
+    assert_eq!(halts.as_array().unwrap().len(), 1);
+
+    let (status, _) = send(&app, Method::POST, "/admin/resume", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, Method::POST, "/admin/resume", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn halt_of_unknown_strategy(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/admin/halt",
+        Some(json!({ "strategy_id": "7a8b9c0d-1e2f-4a3b-8c5d-6e7f8091a2b3" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn strategy_flatten_leaves_other_strategies_positions(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(PaperBroker::new(Decimal::from(10_000)));
+    let (halted, other) = (paper_strategy(), paper_strategy());
+    let account = halted.broker_account();
+    let mut brokers = BrokerRegistry::new();
+    brokers.register(Broker::Paper, &account.account, Arc::clone(&client));
+    client.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    for strategy in [&halted, &other] {
+        let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+        let entry = trade_signal(&pool, strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+        core.process_trade_signal(Arc::clone(&client), entry)
+            .await
+            .unwrap();
+    }
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    let request = HaltRequest {
+        flatten: true,
+        ..Default::default()
+    };
+    let report = core.halt(&brokers, Some(&halted), &request).await.unwrap();
+    assert_eq!(report.closed_positions, vec!["AAPL"]);
+    assert_eq!(report.canceled_orders.len(), 1);
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    // Only the halted strategy's shares are sold and only the other strategy's stop is left
+    let position = client.get_position("AAPL".to_owned()).await.unwrap();
+    let orders_req = OrdersRequest {
+        symbol: Some("AAPL".to_owned()),
+        open_only: true,
+    };
+    let open_orders = client.get_orders(orders_req).await.unwrap();
+    let brackets: Vec<String> =
+        sqlx::query_scalar("SELECT status FROM emulated_brackets ORDER BY status")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(position.quantity, Decimal::TEN);
+    assert_eq!(open_orders.len(), 1);
+    assert_eq!(brackets, vec!["active", "closed"]);
+
+    // Repeated halt has nothing left to close
+    let report = core.halt(&brokers, Some(&halted), &request).await.unwrap();
+    assert!(report.closed_positions.is_empty());
+    assert!(report.errors.is_empty());
+}
@@ -0,0 +1,86 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use market::{
+    clients::{BrokerClient, BrokerClientError},
+    core::{Core, TradeError},
+    risk::RiskLimits,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal, BrokerDouble};
+
+/// Opens a long position for a strategy retrying orders twice, with the broker failing the
+/// given number of order requests first.
+async fn open_long(
+    pool: &PgPool,
+    failing_orders: u32,
+) -> (Arc<BrokerDouble>, Result<(), TradeError>) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let broker = Arc::new(BrokerDouble::new(10_000));
+    broker.on_bar("AAPL", &bar(100, 100, 100, 100));
+    broker.fail_next_orders(failing_orders);
+    let mut strategy = paper_strategy();
+    strategy.max_order_retries = 2;
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(pool, &strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+    let result = core.process_trade_signal(broker.clone(), entry).await;
+    (broker, result.map(|_| ()))
+}
+
+#[sqlx::test]
+async fn order_is_retried_until_the_broker_recovers(pool: PgPool) {
+    let (broker, result) = open_long(&pool, 2).await;
+
+    let position = broker.get_position("AAPL".to_owned()).await.unwrap();
+    assert!(result.is_ok());
+    assert_eq!(position.quantity, Decimal::TEN);
+}
+
+#[sqlx::test]
+async fn order_retries_are_exhausted(pool: PgPool) {
+    let (broker, result) = open_long(&pool, 4).await;
+
+    // First attempt and two retries, the signal itself may be retried later
+    let err = result.unwrap_err();
+    assert!(matches!(err, TradeError::MaxRetriesReached(3, _)));
+    assert!(err.is_retryable());
+    assert_eq!(broker.failing_orders.load(Ordering::SeqCst), 1);
+    assert!(broker.get_positions().await.unwrap().is_empty());
+}
+
+#[sqlx::test]
+async fn position_close_is_retried_until_the_broker_recovers(pool: PgPool) {
+    let (broker, result) = open_long(&pool, 0).await;
+    assert!(result.is_ok());
+    broker.fail_next_closes(2);
+
+    let mut strategy = paper_strategy();
+    strategy.max_order_retries = 2;
+    let close = json!({ "signal_type": "close_long" });
+    let close = trade_signal(&pool, &strategy, "close", close, bar(100, 100, 100, 100)).await;
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    core.process_trade_signal(broker.clone(), close)
+        .await
+        .unwrap();
+
+    assert_eq!(broker.failing_closes.load(Ordering::SeqCst), 0);
+    assert!(broker.get_positions().await.unwrap().is_empty());
+}
+
+#[test]
+fn only_transient_errors_are_retryable() {
+    let unavailable = || BrokerClientError::Unavailable("Timeout".to_owned());
+    let rejected = || BrokerClientError::OrderRejected("Insufficient buying power".to_owned());
+
+    assert!(unavailable().is_retryable());
+    assert!(!rejected().is_retryable());
+    assert!(TradeError::BrokerClientError(unavailable()).is_retryable());
+    assert!(!TradeError::BrokerClientError(rejected()).is_retryable());
+    assert!(!TradeError::InvalidSignal("No stop".to_owned()).is_retryable());
+    assert!(!TradeError::Halted("Kill switch".to_owned()).is_retryable());
+}
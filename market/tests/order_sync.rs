@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use market::{
+    clients::{BrokerClient, PaperBroker},
+    core::Core,
+    risk::RiskLimits,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal};
+
+#[sqlx::test]
+async fn orders_unknown_to_the_broker_are_given_up(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let strategy = paper_strategy();
+    let account = strategy.broker_account();
+    let client: Arc<dyn BrokerClient> = Arc::new(PaperBroker::new(Decimal::from(10_000)));
+    client.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(&pool, &strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+    core.process_trade_signal(Arc::clone(&client), entry)
+        .await
+        .unwrap();
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    // Paper broker keeps its orders in memory only
+    let restarted = PaperBroker::new(Decimal::from(10_000));
+    core.sync_orders(&account, &restarted).await.unwrap();
+
+    let stops: Vec<String> =
+        sqlx::query_scalar("SELECT status FROM orders WHERE order_type = 'stop'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let brackets: Vec<String> = sqlx::query_scalar("SELECT status FROM emulated_brackets")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stops, vec!["unknown"]);
+    assert_eq!(brackets, vec!["closed"]);
+}
@@ -0,0 +1,147 @@
+use chrono::{TimeZone, Utc};
+use market::{
+    api::{
+        alert::BarData,
+        objects::{OrderRequest, OrderSide, OrderStatus, OrderType, Position, TimeInForce},
+        price::Price,
+    },
+    clients::{BrokerClient, PaperBroker},
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+
+fn bar(hour: u32, open: i64, high: i64, low: i64, close: i64) -> BarData {
+    BarData {
+        time: Utc.with_ymd_and_hms(2023, 8, 1, hour, 0, 0).unwrap(),
+        open: Price::new(Decimal::from(open)),
+        high: Price::new(Decimal::from(high)),
+        low: Price::new(Decimal::from(low)),
+        close: Price::new(Decimal::from(close)),
+        volume: Decimal::ONE_HUNDRED,
+    }
+}
+
+fn order(
+    client_order_id: &str,
+    side: OrderSide,
+    order_type: OrderType,
+    price: Option<i64>,
+) -> OrderRequest {
+    OrderRequest {
+        client_order_id: client_order_id.to_string(),
+        symbol: "AAPL".to_string(),
+        side,
+        order_type,
+        quantity: Decimal::TEN,
+        limit_price: price
+            .filter(|_| order_type == OrderType::Limit)
+            .map(Decimal::from),
+        stop_price: price
+            .filter(|_| order_type == OrderType::Stop)
+            .map(Decimal::from),
+        time_in_force: TimeInForce::Gtc,
+        stop_loss: None,
+        take_profit: None,
+        reduce_only: false,
+    }
+}
+
+#[tokio::test]
+async fn market_order_fills_at_last_close() {
+    let broker = PaperBroker::new(Decimal::from(10_000));
+    broker.on_bar("AAPL", &bar(10, 98, 101, 97, 100));
+
+    let entry = broker
+        .create_order(order("entry", OrderSide::Buy, OrderType::Market, None))
+        .await
+        .unwrap();
+    let account = broker.get_account().await.unwrap();
+
+    assert_eq!(entry.status, OrderStatus::Filled);
+    assert_eq!(entry.average_fill_price, Some(Decimal::from(100)));
+    assert_eq!(account.equity, Decimal::from(10_000));
+    assert_eq!(account.buying_power, Decimal::from(9_000));
+}
+
+#[tokio::test]
+async fn stop_order_fills_on_later_bar() {
+    let broker = PaperBroker::new(Decimal::from(10_000));
+    broker.on_bar("AAPL", &bar(10, 98, 101, 97, 100));
+    broker
+        .create_order(order("entry", OrderSide::Buy, OrderType::Market, None))
+        .await
+        .unwrap();
+    broker
+        .create_order(order("stop", OrderSide::Sell, OrderType::Stop, Some(95)))
+        .await
+        .unwrap();
+
+    broker.on_bar("AAPL", &bar(11, 99, 102, 96, 97));
+    let stop = broker
+        .get_order_by_client_id("stop".to_string())
+        .await
+        .unwrap();
+    assert_eq!(stop.status, OrderStatus::New);
+
+    // Gap below the stop fills at the open
+    broker.on_bar("AAPL", &bar(12, 93, 94, 90, 92));
+    let stop = broker
+        .get_order_by_client_id("stop".to_string())
+        .await
+        .unwrap();
+    let account = broker.get_account().await.unwrap();
+    let positions: Vec<Position> = broker.get_positions().await.unwrap();
+
+    assert_eq!(stop.average_fill_price, Some(Decimal::from(93)));
+    assert_eq!(account.equity, Decimal::from(9_930));
+    assert!(positions.is_empty());
+}
+
+#[tokio::test]
+async fn market_order_without_buying_power_is_rejected() {
+    let broker = PaperBroker::new(Decimal::from(500));
+    broker.on_bar("AAPL", &bar(10, 98, 101, 97, 100));
+
+    let result = broker
+        .create_order(order("entry", OrderSide::Buy, OrderType::Market, None))
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn reduce_only_exits_do_not_reverse_the_position() {
+    let broker = PaperBroker::new(Decimal::from(10_000));
+    broker.on_bar("AAPL", &bar(10, 98, 101, 97, 100));
+    broker
+        .create_order(order("entry", OrderSide::Buy, OrderType::Market, None))
+        .await
+        .unwrap();
+    for (client_order_id, order_type, price) in [
+        ("stop_loss", OrderType::Stop, 95),
+        ("take_profit", OrderType::Limit, 105),
+    ] {
+        let exit = OrderRequest {
+            reduce_only: true,
+            ..order(client_order_id, OrderSide::Sell, order_type, Some(price))
+        };
+        broker.create_order(exit).await.unwrap();
+    }
+
+    // A wide bar crosses both exits, only the first one fills
+    broker.on_bar("AAPL", &bar(11, 100, 106, 94, 100));
+    let stop_loss = broker
+        .get_order_by_client_id("stop_loss".to_string())
+        .await
+        .unwrap();
+    let take_profit = broker
+        .get_order_by_client_id("take_profit".to_string())
+        .await
+        .unwrap();
+    let positions: Vec<Position> = broker.get_positions().await.unwrap();
+
+    assert_eq!(stop_loss.status, OrderStatus::Filled);
+    assert_eq!(stop_loss.filled_quantity, Decimal::TEN);
+    assert_eq!(take_profit.status, OrderStatus::Canceled);
+    assert!(positions.is_empty());
+}
@@ -0,0 +1,113 @@
+use chrono::{TimeZone, Utc};
+use market::api::{alert::SignalType, payload::PayloadMapping};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+
+fn mapping() -> PayloadMapping {
+    serde_json::from_value(json!({
+        "fields": {
+            "strategy_id": "/strategy",
+            "passphrase": "/token",
+            "ticker": "/symbol",
+            "timeframe": { "default": "1h" },
+            "exchange": { "default": "NASDAQ" },
+            "signal_type": {
+                "pointer": "/action",
+                "values": { "buy": "open_long", "sell": "open_short", "exit": "close_all" }
+            },
+            "trail_stop_price": "/stop",
+            "bar_data.open": "/price",
+            "bar_data.high": "/price",
+            "bar_data.low": "/price",
+            "bar_data.close": "/price",
+            "bar_data.volume": { "default": 0 },
+            "bar_data.time": "/bar_time"
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn payload_is_mapped_onto_alert() {
+    let bar_time = Utc.with_ymd_and_hms(2023, 10, 2, 14, 0, 0).unwrap();
+    let received_at = Utc.with_ymd_and_hms(2023, 10, 2, 14, 30, 0).unwrap();
+    let payload = json!({
+        "strategy": "559a0466-9301-4198-ab4d-0302beac3cc2",
+        "token": "secret",
+        "symbol": "AAPL",
+        "action": "buy",
+        "stop": 171.5,
+        "price": 175.25,
+        "bar_time": "2023-10-02T14:00:00Z"
+    });
+
+    let alert = mapping().apply(&payload, received_at).unwrap();
+
+    assert_eq!(alert.ticker, "AAPL");
+    assert_eq!(alert.timeframe, "1h");
+    assert_eq!(alert.exchange, "NASDAQ");
+    assert_eq!(alert.passphrase.as_deref(), Some("secret"));
+    assert!(matches!(alert.signal_type, SignalType::OpenLong(_)));
+    assert_eq!(
+        alert.signal_type.trail_stop_price(),
+        Some(Decimal::new(1715, 1))
+    );
+    assert_eq!(*alert.bar_data.close.as_ref(), Decimal::new(17525, 2));
+    assert_eq!(alert.bar_data.time, bar_time);
+    assert_eq!(alert.time, bar_time);
+}
+
+#[test]
+fn unmapped_value_is_rejected() {
+    let payload = json!({
+        "strategy": "559a0466-9301-4198-ab4d-0302beac3cc2",
+        "symbol": "AAPL",
+        "action": "hold",
+        "price": 175.25
+    });
+
+    assert!(mapping().apply(&payload, Utc::now()).is_err());
+}
+
+#[test]
+fn exit_signal_needs_no_stop() {
+    let payload = json!({
+        "strategy": "559a0466-9301-4198-ab4d-0302beac3cc2",
+        "symbol": "AAPL",
+        "action": "exit",
+        "price": 175.25,
+        "bar_time": "2023-10-02T14:00:00Z"
+    });
+
+    let alert = mapping().apply(&payload, Utc::now()).unwrap();
+
+    assert!(matches!(alert.signal_type, SignalType::CloseAll));
+}
+
+#[test]
+fn mapping_needs_an_alert_id_or_bar_time() {
+    let mut mapping = mapping();
+    assert!(mapping.validate().is_ok());
+
+    mapping.fields.remove("bar_data.time");
+    assert!(mapping.validate().is_err());
+
+    let client_alert_id = serde_json::from_value(json!("/id")).unwrap();
+    mapping
+        .fields
+        .insert("client_alert_id".to_owned(), client_alert_id);
+    assert!(mapping.validate().is_ok());
+}
+
+#[test]
+fn payload_without_alert_id_or_bar_time_is_rejected() {
+    let payload = json!({
+        "strategy": "559a0466-9301-4198-ab4d-0302beac3cc2",
+        "symbol": "AAPL",
+        "action": "exit",
+        "price": 175.25
+    });
+
+    assert!(mapping().apply(&payload, Utc::now()).is_err());
+}
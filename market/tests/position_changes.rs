@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use market::{
+    api::{alert::PositionSide, objects::OrdersRequest},
+    clients::{BrokerClient, PaperBroker},
+    core::{Core, TradeError},
+    risk::RiskLimits,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal, BrokerDouble};
+
+/// Opens a long position of 10 shares at 100 with its stop loss at 95.
+async fn open_long(pool: &PgPool, core: &Core, client: &Arc<dyn BrokerClient>) {
+    let strategy = paper_strategy();
+    client.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(pool, &strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+    core.process_trade_signal(Arc::clone(&client), entry)
+        .await
+        .unwrap();
+    core.sync_orders(&strategy.broker_account(), client.as_ref())
+        .await
+        .unwrap();
+}
+
+/// Opens a long position per `open_long` and processes `signal`.
+async fn change_long(pool: &PgPool, signal: Value) -> Arc<dyn BrokerClient> {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(PaperBroker::new(Decimal::from(10_000)));
+    let strategy = paper_strategy();
+    open_long(pool, &core, &client).await;
+
+    let change = trade_signal(pool, &strategy, "change", signal, bar(100, 100, 100, 100)).await;
+    core.process_trade_signal(Arc::clone(&client), change)
+        .await
+        .unwrap();
+    client
+}
+
+#[sqlx::test]
+async fn partial_close_moves_exits_to_the_remaining_quantity(pool: PgPool) {
+    let signal = json!({ "signal_type": "partial_close", "close_percent": "50" });
+    let client = change_long(&pool, signal).await;
+
+    let position = client.get_position("AAPL".to_owned()).await.unwrap();
+    let orders_req = OrdersRequest {
+        symbol: Some("AAPL".to_owned()),
+        open_only: true,
+    };
+    let stops = client.get_orders(orders_req).await.unwrap();
+    assert_eq!(position.quantity, Decimal::from(5));
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0].quantity, Some(Decimal::from(5)));
+    assert_eq!(stops[0].stop_price, Some(Decimal::from(95)));
+}
+
+#[sqlx::test]
+async fn reverse_opens_the_entry_after_the_close_filled(pool: PgPool) {
+    let signal = json!({ "signal_type": "reverse", "trail_stop_price": "105" });
+    let client = change_long(&pool, signal).await;
+
+    let position = client.get_position("AAPL".to_owned()).await.unwrap();
+    assert_eq!(position.side, PositionSide::Short);
+    assert_eq!(position.quantity, Decimal::TEN);
+}
+
+#[sqlx::test]
+async fn close_fails_retryably_while_the_broker_is_down(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let broker = Arc::new(BrokerDouble::new(10_000));
+    let client: Arc<dyn BrokerClient> = broker.clone();
+    let strategy = paper_strategy();
+    open_long(&pool, &core, &client).await;
+
+    broker.fail_next_position_requests(1);
+    let close = json!({ "signal_type": "close_long" });
+    let close = trade_signal(&pool, &strategy, "close", close, bar(100, 100, 100, 100)).await;
+    let err = core
+        .process_trade_signal(Arc::clone(&client), close)
+        .await
+        .unwrap_err();
+
+    assert!(err.is_retryable());
+    assert!(!matches!(err, TradeError::NoOpenPosition(_)));
+    let position = client.get_position("AAPL".to_owned()).await.unwrap();
+    assert_eq!(position.quantity, Decimal::TEN);
+}
+
+#[sqlx::test]
+async fn close_of_the_other_side_finds_no_position(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(PaperBroker::new(Decimal::from(10_000)));
+    let strategy = paper_strategy();
+    open_long(&pool, &core, &client).await;
+
+    let close = json!({ "signal_type": "close_short" });
+    let close = trade_signal(&pool, &strategy, "close", close, bar(100, 100, 100, 100)).await;
+    let err = core
+        .process_trade_signal(Arc::clone(&client), close)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, TradeError::NoOpenPosition(_)));
+    assert!(!err.is_retryable());
+}
+
+#[sqlx::test]
+async fn retried_partial_close_restores_the_stop_again(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let broker = Arc::new(BrokerDouble::new(10_000));
+    let client: Arc<dyn BrokerClient> = broker.clone();
+    open_long(&pool, &core, &client).await;
+
+    let mut strategy = paper_strategy();
+    strategy.max_order_retries = 0;
+    let signal = json!({ "signal_type": "partial_close", "close_percent": "50" });
+    let signal = trade_signal(&pool, &strategy, "change", signal, bar(100, 100, 100, 100)).await;
+    // Processed twice as the failing close is retried
+    for _ in 0..2 {
+        broker.fail_next_orders(1);
+        let result = core
+            .process_trade_signal(Arc::clone(&client), signal.clone())
+            .await;
+        assert!(result.unwrap_err().is_retryable());
+    }
+
+    let orders_req = OrdersRequest {
+        symbol: Some("AAPL".to_owned()),
+        open_only: true,
+    };
+    let stops = client.get_orders(orders_req).await.unwrap();
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0].quantity, Some(Decimal::TEN));
+    assert_eq!(stops[0].stop_price, Some(Decimal::from(95)));
+}
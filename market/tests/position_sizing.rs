@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use market::{
+    api::{
+        alert::PositionSide,
+        objects::{Asset, AssetClass, Broker},
+    },
+    clients::BrokerClient,
+    core::{Core, TradeError},
+    risk::RiskLimits,
+    strategy::{round_quantity, PositionSizing},
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal, BrokerDouble};
+
+#[test]
+fn fixed_risk_position_size() {
+    let sizing = PositionSizing::FixedRisk {
+        percent: Decimal::ONE,
+    };
+
+    // 1% of 10000 equity risked over a stop 5 below the entry
+    let long = sizing.quantity(
+        PositionSide::Long,
+        Decimal::from(100),
+        Decimal::from(95),
+        Decimal::from(10000),
+    );
+    let short = sizing.quantity(
+        PositionSide::Short,
+        Decimal::from(100),
+        Decimal::from(104),
+        Decimal::from(10000),
+    );
+
+    assert_eq!(long, Some(Decimal::from(20)));
+    assert_eq!(short, Some(Decimal::from(25)));
+    assert_eq!(
+        sizing.quantity(
+            PositionSide::Long,
+            Decimal::from(100),
+            Decimal::from(100),
+            Decimal::from(10000)
+        ),
+        None
+    );
+    // Stop on the wrong side of the entry
+    assert_eq!(
+        sizing.quantity(
+            PositionSide::Long,
+            Decimal::from(100),
+            Decimal::from(105),
+            Decimal::from(10000)
+        ),
+        None
+    );
+    assert_eq!(
+        sizing.quantity(
+            PositionSide::Short,
+            Decimal::from(100),
+            Decimal::from(95),
+            Decimal::from(10000)
+        ),
+        None
+    );
+}
+
+#[test]
+fn notional_and_equity_position_size() {
+    let notional = PositionSizing::FixedNotional {
+        notional: Decimal::from(1000),
+    };
+    let equity = PositionSizing::PercentOfEquity {
+        percent: Decimal::from(10),
+    };
+
+    assert_eq!(
+        notional.quantity(
+            PositionSide::Long,
+            Decimal::from(400),
+            Decimal::from(390),
+            Decimal::ZERO
+        ),
+        Some(Decimal::new(25, 1))
+    );
+    assert_eq!(
+        equity.quantity(
+            PositionSide::Long,
+            Decimal::from(50),
+            Decimal::from(45),
+            Decimal::from(5000)
+        ),
+        Some(Decimal::from(10))
+    );
+}
+
+#[test]
+fn position_size_rounding() {
+    let quantity = Decimal::new(123456, 4);
+
+    assert_eq!(
+        round_quantity(quantity, Decimal::from(10), Decimal::from(1000), 0),
+        Decimal::from(12)
+    );
+    assert_eq!(
+        round_quantity(quantity, Decimal::from(10), Decimal::from(1000), 2),
+        Decimal::new(1234, 2)
+    );
+    // Capped by buying power
+    assert_eq!(
+        round_quantity(quantity, Decimal::from(10), Decimal::from(55), 0),
+        Decimal::from(5)
+    );
+}
+
+#[test]
+fn quantity_is_rounded_to_the_asset_increment() {
+    let asset = Asset {
+        broker: Broker::Paper,
+        symbol: "BTCUSD".to_owned(),
+        class: AssetClass::Crypto,
+        tradable: true,
+        fractionable: false,
+        quantity_increment: Some(Decimal::from(10)),
+        min_order_quantity: Some(Decimal::from(20)),
+        raw: None,
+    };
+
+    assert_eq!(
+        asset.round_to_increment(Decimal::from(37)),
+        Decimal::from(30)
+    );
+    assert!(!asset.below_min_order(Decimal::from(30)));
+    assert!(asset.below_min_order(Decimal::from(10)));
+}
+
+/// Opens a long position of 10 shares at 100 at a broker with the given lot size filter.
+async fn open_long(
+    pool: &PgPool,
+    step: i64,
+    min: i64,
+) -> (Arc<BrokerDouble>, Result<(), TradeError>) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let broker = Arc::new(BrokerDouble {
+        lot_size: Some((Decimal::from(step), Decimal::from(min))),
+        ..BrokerDouble::new(10_000)
+    });
+    broker.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(
+        pool,
+        &paper_strategy(),
+        "entry",
+        entry,
+        bar(100, 100, 100, 100),
+    )
+    .await;
+    let result = core.process_trade_signal(broker.clone(), entry).await;
+    (broker, result.map(|_| ()))
+}
+
+#[sqlx::test]
+async fn entry_is_rounded_down_to_the_quantity_step(pool: PgPool) {
+    let (broker, result) = open_long(&pool, 4, 4).await;
+
+    assert!(result.is_ok());
+    let position = broker.get_position("AAPL".to_owned()).await.unwrap();
+    assert_eq!(position.quantity, Decimal::from(8));
+}
+
+#[sqlx::test]
+async fn entry_below_the_minimum_order_is_rejected(pool: PgPool) {
+    let (broker, result) = open_long(&pool, 4, 12).await;
+
+    assert!(matches!(result, Err(TradeError::InsufficientFunds(_))));
+    assert!(broker.get_positions().await.unwrap().is_empty());
+}
@@ -0,0 +1,76 @@
+use market::{
+    api::alert::PositionSide,
+    risk::{Exposure, ProposedEntry, RiskBreach, RiskLimits},
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+
+fn exposure() -> Exposure {
+    Exposure {
+        equity: Decimal::from(9700),
+        start_of_day_equity: Decimal::from(10000),
+        positions: vec![
+            ("AAPL".to_string(), Decimal::from(3000)),
+            ("TSLA".to_string(), Decimal::from(-1000)),
+        ],
+        unrealized_intraday_pl: Decimal::from(-100),
+    }
+}
+
+fn entry(ticker: &str, side: PositionSide, notional: i64) -> ProposedEntry<'_> {
+    ProposedEntry {
+        ticker,
+        side,
+        notional: Decimal::from(notional),
+    }
+}
+
+#[test]
+fn exposure_limits() {
+    let limits = RiskLimits {
+        max_open_positions: Some(2),
+        max_gross_exposure: Some(Decimal::from(5000)),
+        max_net_exposure: Some(Decimal::from(2500)),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        limits.check_entry(&exposure(), &entry("MSFT", PositionSide::Long, 100)),
+        Err(RiskBreach::MaxOpenPositions(2))
+    );
+    assert_eq!(
+        limits.check_entry(&exposure(), &entry("AAPL", PositionSide::Long, 1500)),
+        Err(RiskBreach::GrossExposure(Decimal::from(5500)))
+    );
+    assert_eq!(
+        limits.check_entry(&exposure(), &entry("AAPL", PositionSide::Long, 1000)),
+        Err(RiskBreach::NetExposure(Decimal::from(3000)))
+    );
+    assert_eq!(
+        limits.check_entry(&exposure(), &entry("TSLA", PositionSide::Short, 1000)),
+        Ok(())
+    );
+}
+
+#[test]
+fn circuit_breaker_limits() {
+    // Equity is down 300 of which 100 is unrealized
+    let loss = RiskLimits {
+        max_daily_loss: Some(Decimal::from(150)),
+        ..Default::default()
+    };
+    let drawdown = RiskLimits {
+        max_drawdown_percent: Some(Decimal::TWO),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        loss.check_breaker(&exposure()),
+        Err(RiskBreach::DailyLoss(Decimal::from(200)))
+    );
+    assert_eq!(
+        drawdown.check_entry(&exposure(), &entry("AAPL", PositionSide::Long, 100)),
+        Err(RiskBreach::Drawdown(Decimal::from(3)))
+    );
+    assert!(RiskBreach::Drawdown(Decimal::from(3)).trips_breaker());
+}
@@ -1,15 +1,433 @@
-use axum::Router;
-use market::{app_config::AppConfig, build_clients, build_routes, App};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use market::{
+    api::{
+        alert::{AlertStatus, BarData, WebhookAlertData},
+        objects::{
+            Account, ActivitiesRequest, Activity, Asset, AssetClass, Order, OrderRequest,
+            OrderUpdate, OrdersRequest, Position, TimeInForce,
+        },
+        price::Price,
+    },
+    app_config::AppConfig,
+    build_brokers, build_routes,
+    clients::{BrokerClient, BrokerClientError, PaperBroker},
+    core::Core,
+    metrics::Metrics,
+    order::{EntryOrder, ExitOrder},
+    rate_limit::RateLimiter,
+    strategy::{CurrencyType, Strategy},
+    trade_signal::TradeSignal,
+    App,
+};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
 use sqlx::PgPool;
+use tower::ServiceExt;
+use uuid::Uuid;
 
 pub async fn make_test_app(pool: PgPool) -> Router {
     let config = AppConfig::build_for_test().unwrap();
 
-    let clients = build_clients(&config).unwrap();
+    let brokers = build_brokers(&config).unwrap();
+    Strategy::seed(&pool, &config.strategies).await.unwrap();
 
     build_routes(std::sync::Arc::new(App {
+        core: std::sync::Arc::new(Core::new(pool.clone(), config.risk.clone())),
         db: pool,
-        clients,
-        config,
+        brokers,
+        config: std::sync::RwLock::new(config),
+        reload_lock: Default::default(),
+        webhook_limiter: RateLimiter::default(),
+        metrics: Metrics::default(),
+    }))
+}
+
+/// Sends API request authenticated with the configured API key and returns the response status
+/// and JSON body.
+#[allow(dead_code)]
+pub async fn send(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let api_key = AppConfig::build_for_test().unwrap().api_key;
+    let request = Request::builder()
+        .method(method)
+        .uri(format!("http://localhost:8000{}", uri))
+        .header("Authorization", api_key)
+        .header("Content-Type", "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+
+    let resp = app.clone().oneshot(request).await.unwrap();
+    let status = resp.status();
+    let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// Creates strategy trading on the paper broker through the API and returns its id. Fields of
+/// `fields` replace the defaults, e.g. `{ "endpoint": { "slug": "breakout", "token": "t" } }`.
+#[allow(dead_code)]
+pub async fn create_strategy(app: &Router, fields: Value) -> Value {
+    let mut strategy = json!({
+        "name": "Test strategy",
+        "enabled": true,
+        "broker": "paper",
+        "currency_type": "stock",
+        "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+        "max_order_retries": 3,
+        "order_retry_delay": 1.0,
+        "webhook_passphrase": "secret"
+    });
+    for (field, value) in fields.as_object().unwrap() {
+        strategy[field] = value.clone();
+    }
+
+    let (status, strategy) = send(app, Method::POST, "/strategies", Some(strategy)).await;
+    assert_eq!(status, StatusCode::OK);
+    strategy["id"].clone()
+}
+
+/// Webhook alert opening a long position in the ticker with the bar at `time`, without strategy
+/// and passphrase.
+#[allow(dead_code)]
+pub fn alert(ticker: &str, client_alert_id: &str, time: DateTime<Utc>) -> Value {
+    json!({
+        "client_alert_id": client_alert_id,
+        "ticker": ticker,
+        "timeframe": "5m",
+        "exchange": "NASDAQ",
+        "signal_type": "open_long",
+        "trail_stop_price": "175.00",
+        "bar_data": {
+            "time": time,
+            "open": "176.55",
+            "high": "176.58",
+            "low": "176.20",
+            "close": "176.40",
+            "volume": "113.629"
+        },
+        "time": time
+    })
+}
+
+/// Posts alert to the webhook at `uri` and returns the response status.
+#[allow(dead_code)]
+pub async fn post_alert(app: &Router, uri: &str, alert: Value) -> StatusCode {
+    post_alert_with_headers(app, uri, alert, &[]).await
+}
+
+/// Posts alert like `post_alert` with additional request headers.
+#[allow(dead_code)]
+pub async fn post_alert_with_headers(
+    app: &Router,
+    uri: &str,
+    alert: Value,
+    headers: &[(&str, &str)],
+) -> StatusCode {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://localhost:8000{}", uri))
+        .header("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = request.body(Body::from(alert.to_string())).unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// Strategy trading 10 shares of a ticker on the paper broker, for tests driving `Core` directly.
+#[allow(dead_code)]
+pub fn paper_strategy() -> Strategy {
+    serde_json::from_value(json!({
+        "id": Uuid::new_v4(),
+        "name": "Paper test",
+        "enabled": true,
+        "broker": "paper",
+        "currency_type": "stock",
+        "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+        "take_profit": null,
+        "max_order_retries": 0,
+        "order_retry_delay": 0.0,
+        "webhook_passphrase": "secret",
+        "endpoint": null
     }))
+    .unwrap()
+}
+
+/// Bar of the current time.
+#[allow(dead_code)]
+pub fn bar(open: i64, high: i64, low: i64, close: i64) -> BarData {
+    BarData {
+        time: Utc::now(),
+        open: Price::new(Decimal::from(open)),
+        high: Price::new(Decimal::from(high)),
+        low: Price::new(Decimal::from(low)),
+        close: Price::new(Decimal::from(close)),
+        volume: Decimal::ONE_HUNDRED,
+    }
+}
+
+/// Stores accepted AAPL alert of the strategy and returns its id and data. `signal` holds the
+/// signal fields of the alert, e.g. `{ "signal_type": "open_long", "trail_stop_price": "95" }`.
+#[allow(dead_code)]
+pub async fn save_alert(
+    pool: &PgPool,
+    strategy: &Strategy,
+    client_alert_id: &str,
+    signal: Value,
+    bar: BarData,
+) -> (Uuid, WebhookAlertData) {
+    let mut alert = json!({
+        "strategy_id": strategy.id,
+        "client_alert_id": client_alert_id,
+        "ticker": "AAPL",
+        "timeframe": "5m",
+        "exchange": "NASDAQ",
+        "time": bar.time,
+        "bar_data": bar
+    });
+    for (field, value) in signal.as_object().unwrap() {
+        alert[field] = value.clone();
+    }
+    let alert: WebhookAlertData = serde_json::from_value(alert).unwrap();
+    let alert_id = Uuid::new_v4();
+    alert
+        .save(pool, alert_id, AlertStatus::Accepted, None)
+        .await
+        .unwrap();
+
+    (alert_id, alert)
+}
+
+/// Stores accepted alert like `save_alert` and returns its trade signal.
+#[allow(dead_code)]
+pub async fn trade_signal(
+    pool: &PgPool,
+    strategy: &Strategy,
+    client_alert_id: &str,
+    signal: Value,
+    bar: BarData,
+) -> TradeSignal {
+    let (alert_id, alert) = save_alert(pool, strategy, client_alert_id, signal, bar).await;
+
+    TradeSignal {
+        alert_id,
+        dedup_key: alert.dedup_key(),
+        strategy: strategy.clone(),
+        ticker: alert.ticker,
+        timeframe: alert.timeframe,
+        exchange: alert.exchange,
+        signal_type: alert.signal_type,
+        bar_data: alert.bar_data,
+        time: alert.time,
+    }
+}
+
+/// Paper broker behaving like a real broker where the paper broker differs.
+#[allow(dead_code)]
+pub struct BrokerDouble {
+    pub paper: PaperBroker,
+    /// Replace orders on change like Alpaca does: the changed order is closed and a new one with
+    /// a new id takes its place.
+    pub replaces_orders: bool,
+    /// Whether exits can be reduce only, Alpaca's can't.
+    pub reduce_only: bool,
+    /// Number of the next orders failing to be placed as if the broker was down.
+    pub failing_orders: AtomicU32,
+    /// Number of the next position requests failing as if the broker was down.
+    pub failing_position_requests: AtomicU32,
+    /// Number of the next position closes failing as if the broker was down.
+    pub failing_closes: AtomicU32,
+    /// Lot size filter of the assets like Bybit's: quantity step and minimum order quantity.
+    pub lot_size: Option<(Decimal, Decimal)>,
+}
+
+#[allow(dead_code)]
+impl BrokerDouble {
+    /// Behaves like the paper broker until configured otherwise.
+    pub fn new(cash: i64) -> Self {
+        Self {
+            paper: PaperBroker::new(Decimal::from(cash)),
+            replaces_orders: false,
+            reduce_only: true,
+            failing_orders: AtomicU32::new(0),
+            failing_position_requests: AtomicU32::new(0),
+            failing_closes: AtomicU32::new(0),
+            lot_size: None,
+        }
+    }
+
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.fail_next_orders(if unavailable { u32::MAX } else { 0 });
+    }
+
+    pub fn fail_next_orders(&self, count: u32) {
+        self.failing_orders.store(count, Ordering::SeqCst);
+    }
+
+    pub fn fail_next_position_requests(&self, count: u32) {
+        self.failing_position_requests
+            .store(count, Ordering::SeqCst);
+    }
+
+    pub fn fail_next_closes(&self, count: u32) {
+        self.failing_closes.store(count, Ordering::SeqCst);
+    }
+
+    /// Fails the request if the counter of failing requests isn't down to zero yet.
+    fn fail_if_pending(failing: &AtomicU32) -> Result<(), BrokerClientError> {
+        match failing.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+            count.checked_sub(1)
+        }) {
+            Ok(_) => Err(BrokerClientError::Unavailable("Broker is down".to_owned())),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl BrokerClient for BrokerDouble {
+    async fn get_account(&self) -> Result<Account, BrokerClientError> {
+        self.paper.get_account().await
+    }
+
+    async fn get_activities(
+        &self,
+        activities_req: ActivitiesRequest,
+    ) -> Result<Vec<Activity>, BrokerClientError> {
+        self.paper.get_activities(activities_req).await
+    }
+
+    async fn get_asset(&self, symbol: String) -> Result<Asset, BrokerClientError> {
+        let mut asset = self.paper.get_asset(symbol).await?;
+        if let Some((step, min)) = self.lot_size {
+            asset.quantity_increment = Some(step);
+            asset.min_order_quantity = Some(min);
+        }
+        Ok(asset)
+    }
+
+    async fn get_assets(&self, class: AssetClass) -> Result<Vec<Asset>, BrokerClientError> {
+        self.paper.get_assets(class).await
+    }
+
+    async fn get_position(&self, symbol: String) -> Result<Position, BrokerClientError> {
+        Self::fail_if_pending(&self.failing_position_requests)?;
+        self.paper.get_position(symbol).await
+    }
+
+    async fn get_positions(&self) -> Result<Vec<Position>, BrokerClientError> {
+        self.paper.get_positions().await
+    }
+
+    async fn delete_position(&self, symbol: String) -> Result<Order, BrokerClientError> {
+        Self::fail_if_pending(&self.failing_position_requests)?;
+        Self::fail_if_pending(&self.failing_closes)?;
+        self.paper.delete_position(symbol).await
+    }
+
+    async fn get_order_by_client_id(&self, client_id: String) -> Result<Order, BrokerClientError> {
+        self.paper.get_order_by_client_id(client_id).await
+    }
+
+    async fn get_orders(&self, orders_req: OrdersRequest) -> Result<Vec<Order>, BrokerClientError> {
+        self.paper.get_orders(orders_req).await
+    }
+
+    async fn create_order(
+        &self,
+        mut new_order_req: OrderRequest,
+    ) -> Result<Order, BrokerClientError> {
+        Self::fail_if_pending(&self.failing_orders)?;
+        new_order_req.reduce_only &= self.reduce_only;
+        self.paper.create_order(new_order_req).await
+    }
+
+    async fn update_order(
+        &self,
+        order_id: &str,
+        update_req: OrderUpdate,
+    ) -> Result<Order, BrokerClientError> {
+        if !self.replaces_orders {
+            return self.paper.update_order(order_id, update_req).await;
+        }
+
+        let orders_req = OrdersRequest {
+            symbol: None,
+            open_only: true,
+        };
+        let order = self
+            .paper
+            .get_orders(orders_req)
+            .await?
+            .into_iter()
+            .find(|order| order.broker_order_id == order_id)
+            .ok_or_else(|| {
+                BrokerClientError::OrderRejected(format!("Order {order_id} not open"))
+            })?;
+        self.paper.delete_order(order_id).await?;
+
+        self.paper
+            .create_order(OrderRequest {
+                client_order_id: update_req
+                    .client_order_id
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                symbol: order.symbol,
+                side: order.side,
+                order_type: order.order_type,
+                quantity: order.quantity.unwrap_or_default(),
+                limit_price: update_req.limit_price.or(order.limit_price),
+                stop_price: update_req.stop_price.or(order.stop_price),
+                time_in_force: TimeInForce::Gtc,
+                stop_loss: None,
+                take_profit: None,
+                reduce_only: self.reduce_only,
+            })
+            .await
+    }
+
+    async fn delete_order(&self, order_id: &str) -> Result<(), BrokerClientError> {
+        self.paper.delete_order(order_id).await
+    }
+
+    fn new_order_request(
+        &self,
+        trade_signal: &TradeSignal,
+        entry: &EntryOrder,
+    ) -> Result<OrderRequest, BrokerClientError> {
+        self.paper.new_order_request(trade_signal, entry)
+    }
+
+    fn exit_order_request(&self, exit: &ExitOrder) -> Result<OrderRequest, BrokerClientError> {
+        self.paper.exit_order_request(exit)
+    }
+
+    fn quantity_scale(&self, asset: &Asset, bracket: bool) -> u32 {
+        self.paper.quantity_scale(asset, bracket)
+    }
+
+    fn supports_bracket_orders(&self, currency_type: &CurrencyType) -> bool {
+        self.paper.supports_bracket_orders(currency_type)
+    }
+
+    fn supports_reduce_only(&self, _currency_type: &CurrencyType) -> bool {
+        self.reduce_only
+    }
+
+    fn on_bar(&self, symbol: &str, bar: &BarData) {
+        self.paper.on_bar(symbol, bar);
+    }
 }
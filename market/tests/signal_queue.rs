@@ -0,0 +1,236 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    body::Body,
+    http::{method::Method, Request, StatusCode},
+};
+use market::{
+    api::{
+        alert::{BarData, SignalType, TrailStopPrice, WebhookAlertData},
+        objects::Broker,
+        price::Price,
+    },
+    clients::{BrokerClient, BrokerRegistry},
+    core::Core,
+    queue::{process_next, QueueSettings, SignalJob},
+    risk::RiskLimits,
+    strategy::Strategy,
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+mod setup;
+use setup::{bar, make_test_app, paper_strategy, save_alert, send, BrokerDouble};
+
+/// Signal queue of a strategy trading on a paper broker account, driven one job at a time.
+struct TestQueue {
+    core: Core,
+    brokers: BrokerRegistry,
+    broker: Arc<BrokerDouble>,
+    strategy: Strategy,
+    settings: QueueSettings,
+}
+
+impl TestQueue {
+    async fn new(pool: &PgPool) -> Self {
+        let strategy = paper_strategy();
+        strategy.insert(pool).await.unwrap();
+        let broker = Arc::new(BrokerDouble::new(10_000));
+        broker.on_bar("AAPL", &bar(100, 100, 100, 100));
+        let mut brokers = BrokerRegistry::new();
+        brokers.register(Broker::Paper, &strategy.account, broker.clone());
+
+        Self {
+            core: Core::new(pool.clone(), RiskLimits::default()),
+            brokers,
+            broker,
+            strategy,
+            settings: QueueSettings {
+                max_attempts: 3,
+                retry_delay: 60.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn enqueue(&self, pool: &PgPool, client_alert_id: &str, signal: Value) -> Uuid {
+        let bar = bar(100, 100, 100, 100);
+        let (alert_id, alert) =
+            save_alert(pool, &self.strategy, client_alert_id, signal, bar).await;
+        let lane = format!("{}/{}", self.strategy.broker_account(), alert.ticker);
+        SignalJob::enqueue(pool, alert_id, &alert, &lane, self.settings.max_attempts)
+            .await
+            .unwrap()
+    }
+
+    async fn process_next(&self, pool: &PgPool) -> bool {
+        process_next(pool, &self.core, &self.brokers, &self.settings)
+            .await
+            .unwrap()
+    }
+}
+
+async fn job(pool: &PgPool, job_id: Uuid) -> SignalJob {
+    SignalJob::find(pool, job_id).await.unwrap().unwrap()
+}
+
+#[sqlx::test]
+async fn accepted_alert_is_queued(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let alert = WebhookAlertData {
+        strategy_id: Uuid::from_str("559A0466-9301-4198-AB4D-0302BEAC3CC2").unwrap(),
+        passphrase: Some("test-passphrase".to_string()),
+        client_alert_id: Some("queued-1".to_string()),
+        ticker: "AAPL".to_string(),
+        timeframe: "5m".to_string(),
+        exchange: "NASDAQ".to_string(),
+        signal_type: SignalType::OpenLong(TrailStopPrice(Decimal::new(17500, 2))),
+        bar_data: BarData {
+            time: chrono::Utc::now(),
+            open: Price::new(Decimal::new(17655, 2)),
+            high: Price::new(Decimal::new(17658, 2)),
+            low: Price::new(Decimal::new(17620, 2)),
+            close: Price::new(Decimal::new(17640, 2)),
+            volume: Decimal::new(113629, 3),
+        },
+        time: chrono::Utc::now(),
+    };
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("http://localhost:8000/webhook")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&alert).unwrap()))
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap();
+
+    let (status, jobs) = send(&app, Method::GET, "/jobs?status=pending", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jobs["total"], 1);
+    let job = &jobs["results"][0];
+    assert_eq!(job["payload"]["ticker"], "AAPL");
+    assert!(job["payload"].get("passphrase").is_none());
+
+    // Only dead jobs can be replayed
+    let uri = format!("/job/{}/replay", job["job_id"].as_str().unwrap());
+    let (status, _) = send(&app, Method::POST, &uri, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn lane_runs_jobs_in_order_behind_a_retried_job(pool: PgPool) {
+    let queue = TestQueue::new(&pool).await;
+    let open = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let open = queue.enqueue(&pool, "open", open).await;
+    let close = json!({ "signal_type": "partial_close", "close_percent": "50" });
+    let close = queue.enqueue(&pool, "close", close).await;
+
+    // The first job fails and waits for its retry, concurrent workers don't get past it
+    queue.broker.set_unavailable(true);
+    let (first, second) = tokio::join!(queue.process_next(&pool), queue.process_next(&pool));
+    assert_eq!(u8::from(first) + u8::from(second), 1);
+    assert_eq!(job(&pool, open).await.attempts, 1);
+    assert_eq!(job(&pool, close).await.attempts, 0);
+    assert!(!queue.process_next(&pool).await);
+
+    // Once due again both jobs run, the close only after the position is open
+    queue.broker.set_unavailable(false);
+    sqlx::query("UPDATE signal_jobs SET run_at = NOW()")
+        .execute(&pool)
+        .await
+        .unwrap();
+    loop {
+        let (first, second) = tokio::join!(queue.process_next(&pool), queue.process_next(&pool));
+        if !first && !second {
+            break;
+        }
+    }
+
+    let position = queue.broker.get_position("AAPL".to_owned()).await.unwrap();
+    assert_eq!(job(&pool, open).await.status, "done");
+    assert_eq!(job(&pool, close).await.status, "done");
+    assert_eq!(position.quantity, Decimal::from(5));
+}
+
+#[sqlx::test]
+async fn failing_job_is_retried_with_backoff(pool: PgPool) {
+    let queue = TestQueue::new(&pool).await;
+    let open = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let open = queue.enqueue(&pool, "open", open).await;
+    queue.broker.set_unavailable(true);
+
+    // Delay of the retry doubles with every failed attempt
+    for (attempts, delay) in [(1, 60), (2, 120)] {
+        sqlx::query("UPDATE signal_jobs SET run_at = NOW()")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let started = chrono::Utc::now();
+        assert!(queue.process_next(&pool).await);
+
+        let job = job(&pool, open).await;
+        let delay = chrono::Duration::seconds(delay);
+        assert_eq!(job.status, "pending");
+        assert_eq!(job.attempts, attempts);
+        assert!(job.last_error.is_some());
+        assert!(job.run_at >= started + delay);
+        assert!(job.run_at <= chrono::Utc::now() + delay);
+
+        // Not due until then
+        assert!(!queue.process_next(&pool).await);
+    }
+}
+
+#[sqlx::test]
+async fn dead_job_is_replayed(pool: PgPool) {
+    let queue = TestQueue::new(&pool).await;
+    let open = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let open = queue.enqueue(&pool, "open", open).await;
+    queue.broker.set_unavailable(true);
+
+    for _ in 0..queue.settings.max_attempts {
+        sqlx::query("UPDATE signal_jobs SET run_at = NOW()")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(queue.process_next(&pool).await);
+    }
+    let dead = job(&pool, open).await;
+    assert_eq!(dead.status, "dead");
+    assert_eq!(dead.attempts, queue.settings.max_attempts);
+    assert!(!queue.process_next(&pool).await);
+
+    queue.broker.set_unavailable(false);
+    assert!(SignalJob::replay(&pool, open).await.unwrap());
+    assert!(queue.process_next(&pool).await);
+
+    // Entry recorded as failed by the dead attempts is replaced by the placed one
+    let entries: Vec<String> =
+        sqlx::query_scalar("SELECT status FROM orders WHERE order_type = 'market'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    let position = queue.broker.get_position("AAPL".to_owned()).await.unwrap();
+    assert_eq!(job(&pool, open).await.status, "done");
+    assert_eq!(entries, vec!["filled"]);
+    assert_eq!(position.quantity, Decimal::TEN);
+}
+
+#[sqlx::test]
+async fn job_failing_for_good_is_dead_at_once(pool: PgPool) {
+    let queue = TestQueue::new(&pool).await;
+    let close = json!({ "signal_type": "close_long" });
+    let close = queue.enqueue(&pool, "close", close).await;
+
+    // No position to close, retrying won't help
+    assert!(queue.process_next(&pool).await);
+
+    let job = job(&pool, close).await;
+    assert_eq!(job.status, "dead");
+    assert_eq!(job.attempts, 1);
+    assert!(job.last_error.is_some());
+}
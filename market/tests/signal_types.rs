@@ -0,0 +1,59 @@
+use market::api::alert::SignalType;
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+
+#[test]
+fn exit_signals_need_no_stop_price() {
+    for name in ["close_long", "close_short", "close_all"] {
+        let signal: SignalType = serde_json::from_value(json!({ "signal_type": name })).unwrap();
+        assert_eq!(signal.as_ref(), name);
+        assert_eq!(signal.trail_stop_price(), None);
+        assert_eq!(signal.entry_side(), None);
+    }
+}
+
+#[test]
+fn reverse_needs_stop_price() {
+    let signal: SignalType = serde_json::from_value(json!({
+        "signal_type": "reverse",
+        "trail_stop_price": "101.5"
+    }))
+    .unwrap();
+    assert_eq!(signal.trail_stop_price(), Some(Decimal::new(1015, 1)));
+
+    let result = serde_json::from_value::<SignalType>(json!({ "signal_type": "reverse" }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn partial_close_percent_is_validated() {
+    let signal: SignalType = serde_json::from_value(json!({
+        "signal_type": "partial_close",
+        "close_percent": "25"
+    }))
+    .unwrap();
+    assert_eq!(signal.close_percent(), Some(Decimal::from(25)));
+
+    for percent in ["0", "120"] {
+        let result = serde_json::from_value::<SignalType>(json!({
+            "signal_type": "partial_close",
+            "close_percent": percent
+        }));
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn signal_round_trips_through_json() {
+    let signal: SignalType = serde_json::from_value(json!({
+        "signal_type": "partial_close",
+        "close_percent": "50"
+    }))
+    .unwrap();
+
+    let value = serde_json::to_value(&signal).unwrap();
+    assert_eq!(value["trail_stop_price"], serde_json::Value::Null);
+    let signal: SignalType = serde_json::from_value(value).unwrap();
+    assert_eq!(signal.close_percent(), Some(Decimal::from(50)));
+}
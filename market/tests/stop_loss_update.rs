@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use market::{api::objects::OrdersRequest, clients::BrokerClient, core::Core, risk::RiskLimits};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{bar, paper_strategy, trade_signal, BrokerDouble};
+
+#[sqlx::test]
+async fn replaced_stop_is_tracked_across_syncs(pool: PgPool) {
+    let core = Core::new(pool.clone(), RiskLimits::default());
+    let client: Arc<dyn BrokerClient> = Arc::new(BrokerDouble {
+        replaces_orders: true,
+        ..BrokerDouble::new(10_000)
+    });
+    let strategy = paper_strategy();
+    let account = strategy.broker_account();
+    client.on_bar("AAPL", &bar(100, 100, 100, 100));
+
+    let entry = json!({ "signal_type": "open_long", "trail_stop_price": "95" });
+    let entry = trade_signal(&pool, &strategy, "entry", entry, bar(100, 100, 100, 100)).await;
+    core.process_trade_signal(Arc::clone(&client), entry)
+        .await
+        .unwrap();
+    core.sync_orders(&account, client.as_ref()).await.unwrap();
+
+    for (client_alert_id, stop) in [("update-1", "96"), ("update-2", "97")] {
+        let update = json!({ "signal_type": "stop_loss_update", "trail_stop_price": stop });
+        let update_bar = bar(100, 100, 100, 100);
+        let update = trade_signal(&pool, &strategy, client_alert_id, update, update_bar).await;
+        core.process_trade_signal(Arc::clone(&client), update)
+            .await
+            .unwrap();
+        core.sync_orders(&account, client.as_ref()).await.unwrap();
+    }
+
+    let orders_req = OrdersRequest {
+        symbol: Some("AAPL".to_owned()),
+        open_only: true,
+    };
+    let stops = client.get_orders(orders_req).await.unwrap();
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0].stop_price, Some(Decimal::from(97)));
+}
@@ -0,0 +1,92 @@
+use axum::http::{method::Method, StatusCode};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+use sqlx::PgPool;
+
+mod setup;
+use setup::{make_test_app, send};
+
+#[sqlx::test]
+async fn strategy_lifecycle(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let (status, created) = send(
+        &app,
+        Method::POST,
+        "/strategies",
+        Some(json!({
+            "name": "Paper breakout",
+            "enabled": true,
+            "broker": "paper",
+            "currency_type": "stock",
+            "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+            "max_order_retries": 3,
+            "order_retry_delay": 1.0,
+            "webhook_passphrase": "secret"
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["account"], "default");
+    assert!(created.get("webhook_passphrase").is_none());
+    let uri = format!("/strategy/{}", created["id"].as_str().unwrap());
+
+    let (status, updated) = send(
+        &app,
+        Method::PATCH,
+        &uri,
+        Some(json!({ "name": "Paper breakout 2", "take_profit": { "type": "percent", "percent": "2" } })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["name"], "Paper breakout 2");
+    assert_eq!(updated["take_profit"]["type"], "percent");
+
+    let (status, disabled) = send(&app, Method::POST, &format!("{}/disable", uri), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(disabled["enabled"], false);
+
+    let (status, fetched) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched["name"], "Paper breakout 2");
+    assert_eq!(fetched["enabled"], false);
+
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, listed) = send(&app, Method::GET, "/strategies", None).await;
+    let ids: Vec<_> = listed["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|strategy| strategy["id"].clone())
+        .collect();
+    assert!(!ids.contains(&created["id"]));
+}
+
+#[sqlx::test]
+async fn enabled_strategy_needs_configured_account(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/strategies",
+        Some(json!({
+            "name": "Unknown account",
+            "enabled": true,
+            "broker": "paper",
+            "account": "missing",
+            "currency_type": "stock",
+            "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+            "max_order_retries": 3,
+            "order_retry_delay": 1.0,
+            "webhook_passphrase": "secret"
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
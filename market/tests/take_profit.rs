@@ -0,0 +1,196 @@
+use chrono::{DateTime, Duration, Utc};
+use market::{
+    api::{
+        alert::{AlertStatus, BarData, PositionSide, WebhookAlertData},
+        price::Price,
+    },
+    strategy::{average_true_range, TakeProfit},
+};
+use pretty_assertions::assert_eq;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+fn bar(high: i64, low: i64, close: i64) -> BarData {
+    BarData {
+        time: Utc::now(),
+        open: Price::new(Decimal::from(close)),
+        high: Price::new(Decimal::from(high)),
+        low: Price::new(Decimal::from(low)),
+        close: Price::new(Decimal::from(close)),
+        volume: Decimal::ONE_HUNDRED,
+    }
+}
+
+#[test]
+fn risk_reward_take_profit() {
+    let rule = TakeProfit::RiskReward {
+        ratio: Decimal::TWO,
+    };
+
+    let long = rule.price(
+        PositionSide::Long,
+        Decimal::from(100),
+        Decimal::from(95),
+        &[],
+    );
+    let short = rule.price(
+        PositionSide::Short,
+        Decimal::from(100),
+        Decimal::from(105),
+        &[],
+    );
+
+    assert_eq!(long, Some(Decimal::from(110)));
+    assert_eq!(short, Some(Decimal::from(90)));
+}
+
+#[test]
+fn percent_take_profit() {
+    let rule = TakeProfit::Percent {
+        percent: Decimal::from(5),
+    };
+
+    let long = rule.price(
+        PositionSide::Long,
+        Decimal::from(200),
+        Decimal::from(190),
+        &[],
+    );
+
+    assert_eq!(long, Some(Decimal::from(210)));
+}
+
+#[test]
+fn atr_take_profit() {
+    let rule = TakeProfit::Atr {
+        multiplier: Decimal::TWO,
+        period: 3,
+    };
+    // True ranges: 10, max(8, 12, 4) = 12, 8
+    let bars = [bar(110, 100, 105), bar(117, 109, 112), bar(115, 107, 110)];
+
+    assert_eq!(average_true_range(&bars), Some(Decimal::from(10)));
+    assert_eq!(
+        rule.price(
+            PositionSide::Long,
+            Decimal::from(110),
+            Decimal::from(100),
+            &bars
+        ),
+        Some(Decimal::from(130))
+    );
+}
+
+#[test]
+fn take_profit_rejects_stop_on_wrong_side() {
+    let rule = TakeProfit::RiskReward {
+        ratio: Decimal::TWO,
+    };
+
+    let long = rule.price(
+        PositionSide::Long,
+        Decimal::from(100),
+        Decimal::from(101),
+        &[],
+    );
+    let atr = TakeProfit::Atr {
+        multiplier: Decimal::ONE,
+        period: 14,
+    }
+    .price(
+        PositionSide::Long,
+        Decimal::from(100),
+        Decimal::from(95),
+        &[],
+    );
+
+    assert_eq!(long, None);
+    assert_eq!(atr, None);
+}
+
+#[test]
+fn degenerate_take_profit_rules_are_invalid() {
+    let atr = |multiplier: i64, period: usize| TakeProfit::Atr {
+        multiplier: Decimal::from(multiplier),
+        period,
+    };
+
+    assert!(atr(2, 14).validate().is_ok());
+    assert!(atr(2, 0).validate().is_err());
+    assert!(atr(0, 14).validate().is_err());
+    assert!(TakeProfit::Percent {
+        percent: Decimal::NEGATIVE_ONE
+    }
+    .validate()
+    .is_err());
+}
+
+async fn save_alert(
+    pool: &PgPool,
+    strategy_id: Uuid,
+    client_alert_id: &str,
+    bar_time: DateTime<Utc>,
+    high: &str,
+    status: AlertStatus,
+) {
+    let alert: WebhookAlertData = serde_json::from_value(json!({
+        "strategy_id": strategy_id,
+        "client_alert_id": client_alert_id,
+        "ticker": "AAPL",
+        "timeframe": "5m",
+        "exchange": "NASDAQ",
+        "signal_type": "open_long",
+        "trail_stop_price": "95",
+        "bar_data": {
+            "time": bar_time,
+            "open": "100",
+            "high": high,
+            "low": "99",
+            "close": "100",
+            "volume": "100"
+        },
+        "time": bar_time
+    }))
+    .unwrap();
+
+    alert
+        .save(pool, Uuid::new_v4(), status, None)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn atr_bars_come_from_accepted_alerts_of_the_strategy(pool: PgPool) {
+    let strategy_id = Uuid::new_v4();
+    let bar_time = Utc::now() - Duration::minutes(5);
+    save_alert(
+        &pool,
+        strategy_id,
+        "1",
+        bar_time,
+        "101",
+        AlertStatus::Accepted,
+    )
+    .await;
+    // Anyone can get an alert stored as rejected, or as another strategy's
+    save_alert(
+        &pool,
+        strategy_id,
+        "2",
+        bar_time,
+        "500",
+        AlertStatus::Rejected,
+    )
+    .await;
+    let other = Uuid::new_v4();
+    save_alert(&pool, other, "3", bar_time, "500", AlertStatus::Accepted).await;
+
+    let bars = BarData::recent(&pool, strategy_id, "AAPL", "5m", Utc::now(), 14)
+        .await
+        .unwrap();
+
+    assert_eq!(bars.len(), 1);
+    assert_eq!(*bars[0].high.as_ref(), Decimal::from(101));
+}
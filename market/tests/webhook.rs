@@ -2,31 +2,46 @@ use std::str::FromStr;
 
 use axum::{
     body::Body,
-    http::{method::Method, Request},
+    http::{method::Method, Request, StatusCode},
 };
+use chrono::Utc;
+use hmac::{Hmac, Mac};
 use market::api::{
-    alert::{SignalType, BarData, WebhookAlertData},
+    alert::{BarData, SignalType, TrailStopPrice, WebhookAlertData},
     price::Price,
 };
 use pretty_assertions::assert_eq;
 use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use sha2::Sha256;
 use sqlx::PgPool;
 use tower::ServiceExt;
 
 mod setup;
-use setup::make_test_app;
+use setup::{alert, create_strategy, make_test_app, post_alert, post_alert_with_headers, send};
 use uuid::Uuid;
 
+const HMAC_SECRET: &str = "hmac-secret";
+
+fn signature(secret: &str, alert: &Value) -> String {
+    let mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .unwrap()
+        .chain_update(alert.to_string());
+    hex::encode(mac.finalize().into_bytes())
+}
+
 #[sqlx::test]
 pub(crate) async fn webhook(pool: PgPool) {
     let app = make_test_app(pool).await;
 
     let new_alert = WebhookAlertData {
         strategy_id: Uuid::from_str("559A0466-9301-4198-AB4D-0302BEAC3CC2").unwrap(),
+        passphrase: Some("test-passphrase".to_string()),
+        client_alert_id: None,
         ticker: "AAPL".to_string(),
         timeframe: "5m".to_string(),
         exchange: "NASDAQ".to_string(),
-        signal_type: SignalType::OpenLong,
+        signal_type: SignalType::OpenLong(TrailStopPrice(Decimal::new(17500, 2))),
         bar_data: BarData {
             time: chrono::Utc::now(),
             open: Price::new(Decimal::new(17655, 2)),
@@ -49,3 +64,73 @@ pub(crate) async fn webhook(pool: PgPool) {
 
     assert_eq!(resp.status(), 201);
 }
+
+#[sqlx::test]
+async fn webhook_needs_the_strategy_passphrase(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    let strategy_id = create_strategy(&app, json!({})).await;
+
+    for (client_alert_id, passphrase) in [("1", None), ("2", Some("wrong"))] {
+        let mut alert = alert("AAPL", client_alert_id, Utc::now());
+        alert["strategy_id"] = strategy_id.clone();
+        alert["passphrase"] = json!(passphrase);
+        let status = post_alert(&app, "/webhook", alert).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    let mut alert = alert("AAPL", "3", Utc::now());
+    alert["strategy_id"] = strategy_id;
+    alert["passphrase"] = json!("secret");
+    let status = post_alert(&app, "/webhook", alert).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 1);
+}
+
+#[sqlx::test]
+async fn webhook_accepts_signed_alert_without_passphrase(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    let strategy_id = create_strategy(&app, json!({ "webhook_hmac_secret": HMAC_SECRET })).await;
+
+    let mut forged = alert("AAPL", "1", Utc::now());
+    forged["strategy_id"] = strategy_id.clone();
+    let forged_signature = signature("other-secret", &forged);
+    let headers = [("x-signature", forged_signature.as_str())];
+    let status = post_alert_with_headers(&app, "/webhook", forged, &headers).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let mut signed = alert("AAPL", "2", Utc::now());
+    signed["strategy_id"] = strategy_id;
+    let signed_signature = signature(HMAC_SECRET, &signed);
+    let headers = [("x-signature", signed_signature.as_str())];
+    let status = post_alert_with_headers(&app, "/webhook", signed, &headers).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 1);
+}
+
+#[sqlx::test]
+async fn duplicate_alert_is_acknowledged_once_queued(pool: PgPool) {
+    let app = make_test_app(pool.clone()).await;
+    let strategy_id = create_strategy(&app, json!({})).await;
+
+    let mut alert = alert("AAPL", "1", Utc::now());
+    alert["strategy_id"] = strategy_id;
+    alert["passphrase"] = json!("secret");
+    for _ in 0..2 {
+        let status = post_alert(&app, "/webhook", alert.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let statuses: Vec<String> = sqlx::query_scalar("SELECT status FROM alerts ORDER BY created_at")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["accepted", "duplicate"]);
+
+    // Only the first alert is processed
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 1);
+}
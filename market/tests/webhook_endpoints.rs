@@ -0,0 +1,127 @@
+use axum::{
+    http::{method::Method, StatusCode},
+    Router,
+};
+use chrono::Utc;
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+mod setup;
+use setup::{alert, create_strategy, make_test_app, post_alert, send};
+
+async fn create_endpoint_strategy(app: &Router) -> Value {
+    let endpoint = json!({
+        "name": "Endpoint breakout",
+        "endpoint": {
+            "slug": "endpoint-breakout",
+            "token": "endpoint-token",
+            "rate_limit": 2,
+            "allowed_tickers": ["AAPL"]
+        }
+    });
+    create_strategy(app, endpoint).await
+}
+
+#[sqlx::test]
+async fn endpoint_needs_its_token(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_endpoint_strategy(&app).await;
+
+    let status = post_alert(
+        &app,
+        "/webhook/endpoint-breakout",
+        alert("AAPL", "1", Utc::now()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let uri = "/webhook/endpoint-breakout?token=wrong";
+    let status = post_alert(&app, uri, alert("AAPL", "2", Utc::now())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let uri = "/webhook/endpoint-breakout?token=endpoint-token";
+    let status = post_alert(&app, uri, alert("AAPL", "3", Utc::now())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs?status=pending", None).await;
+    assert_eq!(jobs["total"], 1);
+}
+
+#[sqlx::test]
+async fn endpoint_rejects_disallowed_ticker(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_endpoint_strategy(&app).await;
+
+    let uri = "/webhook/endpoint-breakout?token=endpoint-token";
+    let status = post_alert(&app, uri, alert("MSFT", "1", Utc::now())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 0);
+}
+
+#[sqlx::test]
+async fn endpoint_is_rate_limited(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_endpoint_strategy(&app).await;
+
+    let uri = "/webhook/endpoint-breakout?token=endpoint-token";
+    for id in ["1", "2"] {
+        assert_eq!(
+            post_alert(&app, uri, alert("AAPL", id, Utc::now())).await,
+            StatusCode::OK
+        );
+    }
+    let status = post_alert(&app, uri, alert("AAPL", "3", Utc::now())).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[sqlx::test]
+async fn unknown_endpoint_is_not_found(pool: PgPool) {
+    let app = make_test_app(pool).await;
+
+    let status = post_alert(
+        &app,
+        "/webhook/missing?token=token",
+        alert("AAPL", "1", Utc::now()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn shared_webhook_rejects_alerts_of_endpoint_strategy(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    let strategy_id = create_endpoint_strategy(&app).await;
+
+    let mut shared_alert = alert("AAPL", "1", Utc::now());
+    shared_alert["strategy_id"] = strategy_id;
+    shared_alert["passphrase"] = json!("secret");
+    let status = post_alert(&app, "/webhook", shared_alert).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, jobs) = send(&app, Method::GET, "/jobs", None).await;
+    assert_eq!(jobs["total"], 0);
+}
+
+#[sqlx::test]
+async fn endpoint_slug_of_another_strategy_is_rejected(pool: PgPool) {
+    let app = make_test_app(pool).await;
+    create_endpoint_strategy(&app).await;
+
+    let strategy = json!({
+        "name": "Second breakout",
+        "enabled": true,
+        "broker": "paper",
+        "currency_type": "stock",
+        "position_sizing": { "type": "fixed_quantity", "quantity": "10" },
+        "max_order_retries": 3,
+        "order_retry_delay": 1.0,
+        "webhook_passphrase": "secret",
+        "endpoint": { "slug": "endpoint-breakout", "token": "other-token" }
+    });
+    let (status, _) = send(&app, Method::POST, "/strategies", Some(strategy)).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}